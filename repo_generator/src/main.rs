@@ -1,6 +1,6 @@
 use std::path::PathBuf;
 
-use clap::{command, Parser, Subcommand};
+use clap::{Parser, Subcommand};
 use repo_generator::{
     local_commit_changed, rebase_with_conflict, rebased_local_commit_changed,
     rebased_local_commit_unchanged, remote_branch_changed_local_unchanged,
@@ -11,6 +11,8 @@ use git2::{Commit, Oid};
 use pretty_assertions::assert_eq;
 use tempfile::tempdir;
 
+pub mod mock_server;
+
 pub struct RemoteRepo {
     dir: Box<dyn AsRef<Path>>,
 }
@@ -27,12 +29,16 @@ impl RemoteRepo {
         RemoteRepo { dir: Box::new(dir) }
     }
 
-    pub fn clone_repo(&self) -> TestRepoWithRemote {
+    pub fn path(&self) -> &Path {
+        (*self.dir).as_ref()
+    }
+
+    pub fn clone_repo(&self) -> TestRepoWithRemote<'_> {
         let local_repo_dir = tempdir().unwrap();
         self.clone_repo_into(local_repo_dir)
     }
 
-    pub fn clone_repo_into<P>(&self, dir: P) -> TestRepoWithRemote
+    pub fn clone_repo_into<P>(&self, dir: P) -> TestRepoWithRemote<'_>
     where
         P: AsRef<Path> + 'static,
     {
@@ -335,14 +341,52 @@ impl TestRepoWithRemote<'_> {
         self
     }
 
+    #[allow(dead_code)]
+    pub fn add_remote(self, name: &str, remote: &RemoteRepo) -> Self {
+        let current_dir = (*self.local_repo_dir).as_ref();
+        assert!(Command::new("git")
+            .current_dir(current_dir)
+            .arg("remote")
+            .arg("add")
+            .arg(name)
+            .arg(remote.path())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .unwrap()
+            .success());
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn set_config(self, key: &str, value: &str) -> Self {
+        let current_dir = (*self.local_repo_dir).as_ref();
+        assert!(Command::new("git")
+            .current_dir(current_dir)
+            .arg("config")
+            .arg(key)
+            .arg(value)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .unwrap()
+            .success());
+        self
+    }
+
     #[allow(dead_code)]
     pub fn ls_remote_heads(&self, name: &str) -> Output {
+        self.ls_remote_heads_in("origin", name)
+    }
+
+    #[allow(dead_code)]
+    pub fn ls_remote_heads_in(&self, remote: &str, name: &str) -> Output {
         let current_dir = (*self.local_repo_dir).as_ref();
         Command::new("git")
             .current_dir(current_dir)
             .arg("ls-remote")
             .arg("--heads")
-            .arg("origin")
+            .arg(remote)
             .arg(name)
             .output()
             .unwrap()
@@ -391,6 +435,9 @@ impl TestRepoWithRemote<'_> {
         println!("{}", out);
     }
 
+    ///
+    /// The ubr metadata note of `rev`, stored in `refs/notes/ubr`.
+    ///
     #[allow(dead_code)]
     pub fn find_note(&self, rev: &str) -> String {
         let current_dir = (*self.local_repo_dir).as_ref();
@@ -398,6 +445,7 @@ impl TestRepoWithRemote<'_> {
         let out = Command::new("git")
             .current_dir(current_dir)
             .arg("notes")
+            .arg("--ref=ubr")
             .arg("show")
             .arg(rev)
             .output()
@@ -405,7 +453,7 @@ impl TestRepoWithRemote<'_> {
         String::from_utf8(out.stdout).expect("Output is not valid UTF-8")
     }
 
-    pub fn find_commit(&self, ancestors: u32) -> Commit {
+    pub fn find_commit(&self, ancestors: u32) -> Commit<'_> {
         let head = self.local_repo.head().unwrap();
 
         let mut commit = head.peel_to_commit().unwrap();
@@ -418,7 +466,7 @@ impl TestRepoWithRemote<'_> {
     }
 
     #[allow(dead_code)]
-    pub fn find_commit_by_reference(&self, reference: &str) -> Commit {
+    pub fn find_commit_by_reference(&self, reference: &str) -> Commit<'_> {
         self.local_repo
             .find_reference(reference)
             .unwrap()
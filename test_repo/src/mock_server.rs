@@ -0,0 +1,106 @@
+use std::{
+    sync::{Arc, Mutex},
+    thread::JoinHandle,
+};
+
+use tiny_http::{Header, Response, Server};
+
+///
+/// A request received by a [`MockServer`].
+///
+#[derive(Debug, Clone)]
+pub struct Request {
+    pub method: String,
+    /// The path and query, e.g. `/repos/owner/name/pulls?state=open`
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl Request {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn path(&self) -> &str {
+        self.url
+            .split_once('?')
+            .map(|(path, _)| path)
+            .unwrap_or(&self.url)
+    }
+
+    pub fn json(&self) -> serde_json::Value {
+        serde_json::from_str(&self.body).unwrap()
+    }
+}
+
+///
+/// An HTTP server on localhost that answers every request with the status and JSON body
+/// returned by a handler, and records the requests it received.
+///
+pub struct MockServer {
+    server: Arc<Server>,
+    requests: Arc<Mutex<Vec<Request>>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl MockServer {
+    pub fn start<F>(handler: F) -> Self
+    where
+        F: Fn(&Request) -> (u16, serde_json::Value) + Send + 'static,
+    {
+        let server = Arc::new(Server::http("127.0.0.1:0").unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let thread = {
+            let server = Arc::clone(&server);
+            let requests = Arc::clone(&requests);
+            std::thread::spawn(move || {
+                for mut incoming in server.incoming_requests() {
+                    let mut body = String::new();
+                    incoming.as_reader().read_to_string(&mut body).unwrap();
+                    let request = Request {
+                        method: incoming.method().to_string(),
+                        url: incoming.url().to_string(),
+                        headers: incoming
+                            .headers()
+                            .iter()
+                            .map(|header| (header.field.to_string(), header.value.to_string()))
+                            .collect(),
+                        body,
+                    };
+                    let (status, body) = handler(&request);
+                    requests.lock().unwrap().push(request);
+                    let response = Response::from_string(body.to_string())
+                        .with_status_code(status)
+                        .with_header("Content-Type: application/json".parse::<Header>().unwrap());
+                    let _ = incoming.respond(response);
+                }
+            })
+        };
+        MockServer {
+            server,
+            requests,
+            thread: Some(thread),
+        }
+    }
+
+    pub fn url(&self) -> String {
+        format!("http://{}", self.server.server_addr())
+    }
+
+    pub fn requests(&self) -> Vec<Request> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.server.unblock();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
@@ -0,0 +1,136 @@
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, BTreeSet},
+};
+
+use anyhow::Context;
+use git2::{Diff, DiffFormat};
+use tracing::warn;
+
+use crate::{
+    git::{
+        local_commit::{CommitMetadata, MainCommit, META_DATA_VERSION},
+        GitRepo,
+    },
+    Error,
+};
+
+#[derive(clap::Parser)]
+pub struct Options {
+    /// The commit to track
+    pub commit_ref: String,
+    /// The existing remote branch to track it with
+    pub branch: String,
+}
+
+impl Options {
+    pub fn new<C: Into<String>, B: Into<String>>(commit_ref: C, branch: B) -> Self {
+        Options {
+            commit_ref: commit_ref.into(),
+            branch: branch.into(),
+        }
+    }
+}
+
+///
+/// Track a commit with a remote branch that already exists, e.g. one created by hand before
+/// using ubr, without pushing anything.
+///
+pub fn execute(options: Options, git_repo: GitRepo) -> crate::Result<()> {
+    git_repo.begin_operation("adopt")?;
+    git_repo
+        .remote()
+        .fetch(&[&options.branch])
+        .context("Fetching the remote branch")?;
+    let commit = match git_repo.find_unpushed_commit(&options.commit_ref)? {
+        MainCommit::Tracked(tracked) => {
+            return Err(Error::CommitAlreadyTracked {
+                commit: tracked.as_commit().id(),
+                branch: tracked.meta_data().remote_branch_name.to_string(),
+            })
+        }
+        MainCommit::UnTracked(commit) => commit.commit(),
+    };
+    if let Some(branch) = git_repo.group_branch(commit.id()) {
+        return Err(Error::CommitAlreadyTracked {
+            commit: commit.id(),
+            branch,
+        });
+    }
+    if let Some(tracked_commit) = git_repo.virtual_branches()?.get(&options.branch) {
+        return Err(Error::BranchAlreadyTracked {
+            branch: options.branch,
+            commit: *tracked_commit,
+        });
+    }
+    let remote_head = git_repo
+        .find_head_of_remote_branch(&options.branch)
+        .with_context(|| {
+            format!(
+                "Remote branch {}/{} not found",
+                git_repo.remote_name(),
+                options.branch
+            )
+        })?;
+
+    let local_changes = changed_lines(&git_repo.commit_diff(&commit)?)?;
+    let remote_changes = changed_lines(&git_repo.remote_branch_diff(&options.branch, None)?)?;
+    if local_changes != remote_changes {
+        let files = local_changes
+            .keys()
+            .chain(remote_changes.keys())
+            .filter(|file| local_changes.get(*file) != remote_changes.get(*file))
+            .collect::<BTreeSet<_>>();
+        warn!(
+            "{}/{} doesn't have the same changes as {}, they differ in:\n{}",
+            git_repo.remote_name(),
+            options.branch,
+            commit.id(),
+            files
+                .iter()
+                .map(|file| format!("  {}", file))
+                .collect::<Vec<_>>()
+                .join("\n")
+        );
+    }
+
+    git_repo.save_meta_data(
+        &commit,
+        &CommitMetadata {
+            version: META_DATA_VERSION,
+            remote_branch_name: Cow::Borrowed(&options.branch),
+            remote_commit: remote_head.id(),
+            parent_branch: None,
+            pull_request: None,
+            extra: Vec::new(),
+        },
+    )?;
+    git_repo.finish_operation()?;
+    println!("Tracking {} as {}", commit.id(), options.branch);
+    Ok(())
+}
+
+/// The added (`+`) and removed (`-`) lines of a file
+type ChangedLines = Vec<(char, Vec<u8>)>;
+
+///
+/// The added and removed lines of every file changed by `diff`, leaving out the line numbers
+/// so that the same changes on different bases compare equal.
+///
+fn changed_lines(diff: &Diff) -> anyhow::Result<BTreeMap<String, ChangedLines>> {
+    let mut files: BTreeMap<String, ChangedLines> = BTreeMap::new();
+    diff.print(DiffFormat::Patch, |delta, _, line| {
+        let path = delta
+            .new_file()
+            .path()
+            .or(delta.old_file().path())
+            .map(|path| path.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let lines = files.entry(path).or_default();
+        if matches!(line.origin(), '+' | '-') {
+            lines.push((line.origin(), line.content().to_vec()));
+        }
+        true
+    })?;
+    Ok(files)
+}
@@ -0,0 +1,148 @@
+use std::collections::HashSet;
+
+use git2::Oid;
+
+use crate::{
+    commands::fetch,
+    git::{
+        local_commit::{MainCommit, RemoteState},
+        GitRepo,
+    },
+};
+
+#[derive(clap::Parser, Default)]
+pub struct Options {
+    /// Also delete the remote branches of the virtual branches that were cleaned up
+    #[arg(long)]
+    pub delete_remote: bool,
+}
+
+impl Options {
+    pub fn with_delete_remote(mut self) -> Self {
+        self.delete_remote = true;
+        self
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum LandedState {
+    /// All changes of the virtual branch are already on the base branch.
+    Merged,
+    /// The remote branch no longer exists.
+    RemoteBranchDeleted,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct LandedBranch {
+    pub remote_branch_name: String,
+    pub state: LandedState,
+    /// All local commits of the virtual branch, oldest first.
+    pub commits: Vec<Oid>,
+}
+
+///
+/// Find the virtual branches among `commits` that have been merged into the base branch or
+/// whose remote branch has been deleted.
+///
+/// A missing remote-tracking branch only means that the remote branch was deleted if the
+/// remote branches were just `fetched`, as it may otherwise simply never have been fetched.
+///
+pub fn find_landed(commits: &[MainCommit], fetched: bool) -> anyhow::Result<Vec<LandedBranch>> {
+    let mut landed = Vec::new();
+    for commit in commits {
+        let MainCommit::Tracked(tracked) = commit else {
+            continue;
+        };
+        let state = if tracked.is_merged()? {
+            LandedState::Merged
+        } else if fetched && tracked.remote_state()? == RemoteState::Missing {
+            LandedState::RemoteBranchDeleted
+        } else {
+            continue;
+        };
+        landed.push(LandedBranch {
+            remote_branch_name: tracked.meta_data().remote_branch_name.to_string(),
+            state,
+            commits: tracked.commits()?,
+        });
+    }
+    Ok(landed)
+}
+
+///
+/// Remove the notes of all landed virtual branches and return the commits that should be
+/// dropped from the main branch, i.e. those whose changes are already on the base branch.
+///
+/// The commits of a virtual branch whose remote branch was deleted are kept, as their changes
+/// would otherwise be lost.
+///
+pub fn untrack_landed(repo: &GitRepo, landed: &[LandedBranch]) -> anyhow::Result<HashSet<Oid>> {
+    let mut dropped = HashSet::new();
+    for branch in landed {
+        match branch.state {
+            LandedState::Merged => println!(
+                "{} has been merged, dropping {} commit(s)",
+                branch.remote_branch_name,
+                branch.commits.len()
+            ),
+            LandedState::RemoteBranchDeleted => println!(
+                "{}/{} has been deleted, untracking {} commit(s)",
+                repo.remote_name(),
+                branch.remote_branch_name,
+                branch.commits.len()
+            ),
+        }
+        for commit_id in &branch.commits {
+            repo.remove_meta_data(&repo.find_commit(&format!("{}", commit_id))?)?;
+            if branch.state == LandedState::Merged {
+                dropped.insert(*commit_id);
+            }
+        }
+    }
+    Ok(dropped)
+}
+
+pub fn execute(options: Options, repo: GitRepo) -> crate::Result<()> {
+    repo.begin_operation("cleanup")?;
+    fetch::fetch_branches(&repo)?;
+    let commits = repo.unpushed_commits()?;
+    let landed = find_landed(&commits, true)?;
+    if landed.is_empty() {
+        println!("Nothing to clean up");
+        return Ok(());
+    }
+    let dropped = untrack_landed(&repo, &landed)?;
+
+    if !dropped.is_empty() {
+        let mut parent_commit = match commits.first() {
+            Some(commit) => repo.find_commit(&format!("{}^", commit.id()))?,
+            None => repo.head()?,
+        };
+        for commit in commits {
+            if dropped.contains(&commit.id()) {
+                continue;
+            }
+            let commit_id = commit.id();
+            parent_commit =
+                if repo.find_commit(&format!("{}^", commit_id))?.id() == parent_commit.id() {
+                    repo.find_commit(&format!("{}", commit_id))?
+                } else {
+                    commit.rebase(&parent_commit)?
+                };
+        }
+        repo.update_current_branch(&parent_commit)?;
+    }
+
+    if options.delete_remote {
+        for branch in &landed {
+            if repo
+                .find_head_of_remote_branch(&branch.remote_branch_name)
+                .is_some()
+            {
+                repo.record_push(&branch.remote_branch_name, None)?;
+                repo.remote().force_push(&branch.remote_branch_name, None)?;
+            }
+        }
+    }
+    repo.finish_operation()
+}
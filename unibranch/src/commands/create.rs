@@ -1,4 +1,13 @@
-use crate::git::{local_commit::MainCommit, GitRepo};
+use anyhow::Context;
+
+use crate::{
+    forge,
+    git::{
+        local_commit::{MainCommit, UnTrackedCommit},
+        GitRepo,
+    },
+    Error,
+};
 
 #[derive(clap::Parser, Default)]
 pub struct Options {
@@ -7,6 +16,15 @@ pub struct Options {
     pub commit_ref: Option<String>,
     #[arg(short, long)]
     pub name: Option<String>,
+    /// Base the branch on the branch of the closest tracked ancestor instead of the base branch
+    #[arg(short, long)]
+    pub stack: bool,
+    /// Open a pull request for the branch, or update the one it already has
+    #[arg(long)]
+    pub pr: bool,
+    /// Open the pull request as a draft
+    #[arg(long, requires = "pr")]
+    pub draft: bool,
 }
 
 impl Options {
@@ -14,6 +32,18 @@ impl Options {
         self.force = true;
         self
     }
+    pub fn with_stack(mut self) -> Self {
+        self.stack = true;
+        self
+    }
+    pub fn with_pr(mut self) -> Self {
+        self.pr = true;
+        self
+    }
+    pub fn with_draft(mut self) -> Self {
+        self.draft = true;
+        self
+    }
     pub fn with_name<T: Into<String>>(mut self, name: T) -> Self {
         self.name.replace(name.into());
         self
@@ -25,23 +55,114 @@ impl Options {
     }
 }
 
-pub fn execute(config: Options, git_repo: GitRepo) -> anyhow::Result<()> {
+pub fn execute(config: Options, git_repo: GitRepo) -> crate::Result<()> {
+    git_repo.begin_operation("create")?;
     let rev = config.commit_ref.unwrap_or_else(|| "HEAD".to_string());
-    let commit = git_repo.find_unpushed_commit(&rev)?;
+    let (group_base_ref, rev) = match rev.split_once("..") {
+        Some((from, "")) => (Some(from), "HEAD"),
+        Some((from, to)) => (Some(from), to),
+        None => (None, rev.as_str()),
+    };
 
-    let untracked_commit = match commit {
-        MainCommit::UnTracked(commit) => commit,
-        MainCommit::Tracked(tracked) => {
-            if !config.force {
-                anyhow::bail!("Commit is already tracked");
+    let group_base = match group_base_ref {
+        Some(group_base_ref) => {
+            let group_base = git_repo.find_commit(group_base_ref)?;
+            let mut member = git_repo.find_commit(rev)?.parent(0)?;
+            while member.id() != group_base.id() {
+                let commit = git_repo
+                    .find_unpushed_commit(&format!("{}", member.id()))
+                    .with_context(|| {
+                        format!(
+                            "{}..{} is not a range of unpushed commits",
+                            group_base_ref, rev
+                        )
+                    })?;
+                untrack(&git_repo, commit, config.force)?;
+                member = member.parent(0)?;
             }
-
-            tracked.untrack()?
+            Some(group_base)
         }
+        None => None,
     };
 
-    let tracked_commit = untracked_commit.track(config.name)?;
-    git_repo.remote().push(tracked_commit.meta_data())?;
+    let commit = git_repo.find_unpushed_commit(rev)?;
+    let previous_push = match &commit {
+        MainCommit::Tracked(tracked) => Some((
+            tracked.meta_data().remote_branch_name.to_string(),
+            tracked.meta_data().remote_commit,
+        )),
+        MainCommit::UnTracked(_) => None,
+    };
+    let untracked_commit = untrack(&git_repo, commit, config.force)?;
+
+    // A commit that is tracked again keeps its remote branch, unless it is named explicitly
+    let name = config
+        .name
+        .or_else(|| previous_push.as_ref().map(|(branch, _)| branch.clone()));
+    let tracked_commit = match group_base {
+        Some(group_base) => untracked_commit.track_range(group_base, name, config.stack)?,
+        None => untracked_commit.track(name, config.stack)?,
+    };
+    git_repo.record_push(
+        &tracked_commit.meta_data().remote_branch_name,
+        Some(tracked_commit.meta_data().remote_commit),
+    )?;
+    let meta_data = tracked_commit.meta_data();
+    let result = match previous_push {
+        // Replace what we pushed when the commit was tracked before
+        Some((branch, previous)) if branch == meta_data.remote_branch_name => {
+            git_repo.remote().push_replacing(meta_data, previous)
+        }
+        _ => git_repo.remote().push(meta_data),
+    };
+    if let Err(e) = result {
+        tracked_commit.untrack()?;
+        return Err(e.into());
+    }
+    git_repo.finish_operation()?;
 
+    if config.pr && !git_repo.is_dry_run() {
+        // Open the pull request and add it to the navigation of the others
+        let tracked_commits: Vec<_> = git_repo
+            .tracked_commits(&git_repo.head()?)?
+            .into_iter()
+            .filter(|tracked| {
+                tracked.meta_data().pull_request.is_some()
+                    || tracked.as_commit().id() == tracked_commit.as_commit().id()
+            })
+            .collect();
+        let forge = forge::open(&git_repo)?;
+        forge::publish_stack(forge.as_ref(), &git_repo, &tracked_commits, config.draft)?;
+    }
     Ok(())
 }
+
+fn untrack<'repo>(
+    git_repo: &GitRepo,
+    commit: MainCommit<'repo>,
+    force: bool,
+) -> anyhow::Result<UnTrackedCommit<'repo>> {
+    if let Some(branch) = git_repo.group_branch(commit.id()) {
+        if !force {
+            return Err(Error::CommitAlreadyTracked {
+                commit: commit.id(),
+                branch,
+            }
+            .into());
+        }
+    }
+    match commit {
+        MainCommit::UnTracked(commit) => Ok(commit),
+        MainCommit::Tracked(tracked) => {
+            if !force {
+                return Err(Error::CommitAlreadyTracked {
+                    commit: tracked.as_commit().id(),
+                    branch: tracked.meta_data().remote_branch_name.to_string(),
+                }
+                .into());
+            }
+
+            tracked.untrack()
+        }
+    }
+}
@@ -0,0 +1,93 @@
+use std::collections::HashMap;
+
+use anyhow::Context;
+
+use crate::git::{local_commit::MainCommit, GitRepo};
+
+#[derive(clap::Parser, Default)]
+pub struct Options {
+    /// Also fetch the metadata of the virtual branches and merge it with the local metadata
+    #[arg(long)]
+    pub metadata: bool,
+}
+
+impl Options {
+    pub fn with_metadata(mut self) -> Self {
+        self.metadata = true;
+        self
+    }
+}
+
+///
+/// Fetch the base branch and the remote branches of all virtual branches. With `--metadata`
+/// the metadata pushed from other clones is merged in first, and untracked commits with the
+/// same changes as one of its virtual branches start tracking it.
+///
+pub fn execute(options: Options, repo: GitRepo) -> crate::Result<()> {
+    if options.metadata {
+        repo.begin_operation("fetch")?;
+        repo.fetch_meta_data()?;
+        for tracked_commit in repo.rebuild_tracking()? {
+            println!(
+                "Tracking {} as {}",
+                tracked_commit.as_commit().id(),
+                tracked_commit.meta_data().remote_branch_name
+            );
+        }
+        repo.finish_operation()?;
+    }
+    fetch_branches(&repo)?;
+    Ok(())
+}
+
+///
+/// Fetch the base branch and the remote branches of all virtual branches, and report the
+/// branches that received new remote commits with this fetch. Returns those branches together
+/// with the number of new commits.
+///
+pub fn fetch_branches(repo: &GitRepo) -> anyhow::Result<Vec<(String, usize)>> {
+    let unpushed_commits = repo.unpushed_commits()?;
+    let mut branches: Vec<&str> = unpushed_commits
+        .iter()
+        .filter_map(|commit| match commit {
+            MainCommit::Tracked(tracked) => Some(&*tracked.meta_data().remote_branch_name),
+            MainCommit::UnTracked(_) => None,
+        })
+        .collect();
+    let old_heads: HashMap<&str, git2::Oid> = branches
+        .iter()
+        .filter_map(|branch| Some((*branch, repo.find_head_of_remote_branch(branch)?.id())))
+        .collect();
+    if repo.base_remote_name() == repo.remote_name() {
+        branches.push(repo.base_branch_name());
+    } else if repo.base_remote_name() != "." {
+        repo.base_remote()
+            .fetch(&[repo.base_branch_name()])
+            .context("Fetching the base branch")?;
+    }
+    repo.remote()
+        .fetch(&branches)
+        .context("Fetching the remote branches")?;
+
+    let mut updated = Vec::new();
+    for commit in &unpushed_commits {
+        let MainCommit::Tracked(tracked) = commit else {
+            continue;
+        };
+        let branch = &*tracked.meta_data().remote_branch_name;
+        let Some(new_head) = repo.find_head_of_remote_branch(branch) else {
+            continue;
+        };
+        // A branch that wasn't fetched before is compared with what was last pushed to it
+        let old_head = old_heads
+            .get(branch)
+            .copied()
+            .unwrap_or(tracked.meta_data().remote_commit);
+        let new_commits = repo.count_new_commits(old_head, new_head.id())?;
+        if new_commits > 0 {
+            println!("{} has {} new remote commit(s)", branch, new_commits);
+            updated.push((branch.to_string(), new_commits));
+        }
+    }
+    Ok(updated)
+}
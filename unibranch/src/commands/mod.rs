@@ -1,3 +1,9 @@
+pub mod adopt;
+pub mod cleanup;
 pub mod create;
+pub mod fetch;
+pub mod op;
 pub mod push;
+pub mod status;
 pub mod sync;
+pub mod untrack;
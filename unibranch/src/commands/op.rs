@@ -0,0 +1,116 @@
+use anyhow::Context;
+use tracing::info;
+
+use crate::git::{GitRepo, Operation};
+
+#[derive(clap::Subcommand)]
+pub enum Command {
+    /// List the recorded operations
+    Log,
+    /// Restore the state from just before the given operation
+    Restore(RestoreOptions),
+}
+
+#[derive(clap::Parser, Default)]
+pub struct RestoreOptions {
+    pub id: u64,
+
+    /// Also force push the previous heads of the remote branches
+    #[arg(short, long)]
+    pub restore_remote: bool,
+}
+
+#[derive(clap::Parser, Default)]
+pub struct UndoOptions {
+    /// Also force push the previous heads of the remote branches
+    #[arg(short, long)]
+    pub restore_remote: bool,
+}
+
+pub fn execute(command: Command, repo: GitRepo) -> crate::Result<()> {
+    match command {
+        Command::Log => log(repo),
+        Command::Restore(options) => restore(options, repo),
+    }
+}
+
+pub fn log(repo: GitRepo) -> crate::Result<()> {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0);
+    for op in repo.op_log()?.operations.iter().rev() {
+        let main_branch_head = format!("{}", op.snapshot.main_branch_head);
+        println!(
+            "{:>4} {:>10} {:<16} {} {}",
+            op.id,
+            time_ago(now - op.time),
+            op.command,
+            op.snapshot.main_branch_name,
+            &main_branch_head[..7],
+        );
+    }
+    Ok(())
+}
+
+pub fn restore(options: RestoreOptions, repo: GitRepo) -> crate::Result<()> {
+    let op_log = repo.op_log()?;
+    let op = op_log
+        .find(options.id)
+        .with_context(|| format!("No operation with id {}", options.id))?;
+    Ok(restore_operation(
+        op,
+        options.restore_remote,
+        &repo,
+        "op restore",
+    )?)
+}
+
+///
+/// Undo the last operation. Since the undo is recorded as an operation itself, undoing
+/// twice brings back the original state.
+///
+pub fn undo(options: UndoOptions, repo: GitRepo) -> crate::Result<()> {
+    let op_log = repo.op_log()?;
+    let op = op_log.last().context("Nothing to undo")?;
+    Ok(restore_operation(
+        op,
+        options.restore_remote,
+        &repo,
+        "undo",
+    )?)
+}
+
+fn restore_operation(
+    op: &Operation,
+    restore_remote: bool,
+    repo: &GitRepo,
+    command: &str,
+) -> anyhow::Result<()> {
+    info!(
+        "Restoring state from before operation {} ({})",
+        op.id, op.command
+    );
+    repo.begin_operation(&format!("{} {}", command, op.id))?;
+    repo.restore_snapshot(&op.snapshot)?;
+
+    if restore_remote {
+        for remote_head in &op.snapshot.remote_heads {
+            let head = remote_head.head.map(|h| h.into());
+            repo.record_push(&remote_head.branch, head)?;
+            repo.remote()
+                .force_push(&remote_head.branch, head)
+                .with_context(|| format!("Restoring remote branch {}", remote_head.branch))?;
+        }
+    }
+    Ok(repo.finish_operation()?)
+}
+
+fn time_ago(seconds: i64) -> String {
+    match seconds {
+        s if s < 60 => format!("{}s ago", s),
+        s if s < 60 * 60 => format!("{}m ago", s / 60),
+        s if s < 60 * 60 * 24 => format!("{}h ago", s / (60 * 60)),
+        s => format!("{}d ago", s / (60 * 60 * 24)),
+    }
+}
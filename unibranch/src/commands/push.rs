@@ -1,29 +1,32 @@
 use crate::git::GitRepo;
 use anyhow::Context;
-use std::path::Path;
-use std::process::Command;
 
-pub fn execute<P>(repo_dir: P) -> anyhow::Result<()>
-where
-    P: AsRef<Path>,
-{
-    let git_repo = GitRepo::open(repo_dir.as_ref()).context("Opening git repository")?;
+#[derive(clap::Parser, Default)]
+pub struct Options {
+    /// Push the metadata of the virtual branches instead, merged with the remote metadata
+    #[arg(long)]
+    pub metadata: bool,
+}
+
+impl Options {
+    pub fn with_metadata(mut self) -> Self {
+        self.metadata = true;
+        self
+    }
+}
 
+pub fn execute(options: Options, git_repo: GitRepo) -> crate::Result<()> {
+    if options.metadata {
+        git_repo.begin_operation("push --metadata")?;
+        git_repo.push_meta_data()?;
+        return git_repo.finish_operation();
+    }
     let user = std::env::var("USER").context("No $USER env variable")?;
     let branch_name = format!("{}/{}", user, git_repo.current_branch_name);
+    let head = git_repo.head()?.id();
+    git_repo.begin_operation("push")?;
+    git_repo.record_push(&branch_name, Some(head))?;
 
-    Command::new("git")
-        .current_dir(repo_dir.as_ref())
-        .arg("push")
-        .arg("--no-verify")
-        .arg("--force-with-lease")
-        .arg("--")
-        .arg("origin")
-        .arg(format!(
-            "{}:refs/heads/{}",
-            git_repo.head()?.id(),
-            &branch_name
-        ))
-        .status()?;
-    Ok(())
+    git_repo.remote().push_branch(&branch_name, head)?;
+    git_repo.finish_operation()
 }
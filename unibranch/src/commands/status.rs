@@ -0,0 +1,136 @@
+use std::collections::HashMap;
+
+use git2::Oid;
+use tracing::warn;
+
+use crate::{
+    forge::{self, PullRequestStatus},
+    git::{
+        local_commit::{MainCommit, RemoteState},
+        GitRepo,
+    },
+};
+
+#[derive(clap::Parser, Default)]
+pub struct Options {
+    /// Read the status of the pull requests from the forge instead of the cache
+    #[arg(long)]
+    pub refresh: bool,
+}
+
+impl Options {
+    pub fn with_refresh(mut self) -> Self {
+        self.refresh = true;
+        self
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct CommitStatus {
+    pub commit_id: Oid,
+    pub summary: String,
+    pub branch: Option<BranchStatus>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct BranchStatus {
+    pub remote_branch_name: String,
+    pub remote_state: RemoteState,
+    pub local_changes: bool,
+    pub pull_request: Option<u64>,
+    /// The commit the remote branch was last synced with.
+    pub remote_commit: Oid,
+}
+
+///
+/// Collect the status of every unpushed commit, oldest commit first.
+///
+pub fn collect(repo: &GitRepo) -> crate::Result<Vec<CommitStatus>> {
+    repo.unpushed_commits()?
+        .into_iter()
+        .map(|commit| {
+            let summary = match &commit {
+                MainCommit::UnTracked(c) => c.as_commit().summary(),
+                MainCommit::Tracked(c) => c.as_commit().summary(),
+            }
+            .unwrap_or("")
+            .to_string();
+            let branch = match &commit {
+                MainCommit::UnTracked(_) => None,
+                MainCommit::Tracked(tracked) => Some(BranchStatus {
+                    remote_branch_name: tracked.meta_data().remote_branch_name.to_string(),
+                    remote_state: tracked.remote_state()?,
+                    local_changes: tracked.has_local_changes()?,
+                    pull_request: tracked.meta_data().pull_request,
+                    remote_commit: tracked.meta_data().remote_commit,
+                }),
+            };
+            Ok(CommitStatus {
+                commit_id: commit.id(),
+                summary,
+                branch,
+            })
+        })
+        .collect()
+}
+
+///
+/// The review, check and merge status of the pull requests of `statuses`, by number.
+///
+pub fn collect_pull_requests(
+    repo: &GitRepo,
+    statuses: &[CommitStatus],
+    refresh: bool,
+) -> crate::Result<HashMap<u64, PullRequestStatus>> {
+    let pull_requests: Vec<_> = statuses
+        .iter()
+        .filter_map(|status| status.branch.as_ref())
+        .filter_map(|branch| Some((branch.pull_request?, branch.remote_commit)))
+        .collect();
+    if pull_requests.is_empty() {
+        return Ok(HashMap::new());
+    }
+    Ok(forge::pull_request_statuses(repo, &pull_requests, refresh)?)
+}
+
+pub fn execute(options: Options, repo: GitRepo) -> crate::Result<()> {
+    let statuses = collect(&repo)?;
+    // The status of the branches is worth showing even when the forge can't be reached
+    let pull_requests =
+        collect_pull_requests(&repo, &statuses, options.refresh).unwrap_or_else(|e| {
+            warn!("Unable to read the status of the pull requests: {:#}", e);
+            HashMap::new()
+        });
+    for status in statuses.into_iter().rev() {
+        let short_id = format!("{}", status.commit_id);
+        println!("{} {}", &short_id[..7], status.summary);
+        match &status.branch {
+            None => match repo.group_branch(status.commit_id) {
+                Some(branch) => {
+                    println!("        tracked: part of {}/{}", repo.remote_name(), branch)
+                }
+                None => println!("        untracked"),
+            },
+            Some(branch) => println!(
+                "        tracked: {}/{} ({}{})",
+                repo.remote_name(),
+                branch.remote_branch_name,
+                branch.remote_state,
+                if branch.local_changes {
+                    ", local changes not synced"
+                } else {
+                    ""
+                }
+            ),
+        }
+        if let Some(number) = status.branch.and_then(|branch| branch.pull_request) {
+            match pull_requests.get(&number) {
+                Some(pull_request_status) => {
+                    println!("        pull request #{}: {}", number, pull_request_status)
+                }
+                None => println!("        pull request #{}", number),
+            }
+        }
+    }
+    Ok(())
+}
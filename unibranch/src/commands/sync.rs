@@ -1,16 +1,54 @@
 use anyhow::Context;
-use tracing::{debug, info, span, Level};
+use git2::Commit;
+use tracing::{debug, error, info, span, Level};
 
-use crate::git::{local_commit::MainCommit, GitRepo};
+use crate::{
+    commands::{cleanup, fetch},
+    forge,
+    git::{
+        local_commit::{MainCommit, RemoteState},
+        GitRepo,
+    },
+};
 
 #[derive(clap::Parser, Default)]
 pub struct Options {
     #[arg(short, long)]
     pub cont: bool,
 
+    #[arg(long, conflicts_with = "cont")]
+    pub abort: bool,
+
+    /// Don't fetch the base branch and the remote branches before syncing
+    #[arg(long)]
+    pub no_fetch: bool,
+
+    /// Also open pull requests for the branches that don't have one yet
+    #[arg(long)]
+    pub pr: bool,
+
+    /// Open the new pull requests as drafts
+    #[arg(long, requires = "pr")]
+    pub draft: bool,
+
     pub commit_ref: Option<String>,
 }
 
+impl Options {
+    pub fn with_no_fetch(mut self) -> Self {
+        self.no_fetch = true;
+        self
+    }
+    pub fn with_pr(mut self) -> Self {
+        self.pr = true;
+        self
+    }
+    pub fn with_draft(mut self) -> Self {
+        self.draft = true;
+        self
+    }
+}
+
 ///```text
 ///
 ///              *
@@ -24,9 +62,50 @@ pub struct Options {
 ///              | /
 /// (old_origin) *
 ///```
-pub fn execute(options: Options, repo: GitRepo) -> anyhow::Result<()> {
+pub fn execute(options: Options, repo: GitRepo) -> crate::Result<()> {
     debug!("Syncing local changes with remote");
 
+    if options.abort {
+        if options.commit_ref.is_some() {
+            return Err(anyhow::anyhow!("Can not call --abort with a reference").into());
+        }
+        repo.resume_operation()?;
+        repo.abort_sync()?;
+        return repo.discard_operation();
+    }
+
+    if options.cont {
+        repo.resume_sync()?;
+        repo.resume_operation()?;
+    } else {
+        repo.begin_sync()?;
+        repo.begin_operation("sync")?;
+    }
+    let (open_pull_requests, draft) = (options.pr, options.draft);
+    match sync(options, &repo) {
+        Ok(()) => {
+            repo.finish_sync()?;
+            repo.finish_operation()?;
+            Ok(update_pull_requests(&repo, open_pull_requests, draft)?)
+        }
+        Err(e) if repo.sync_paused() => {
+            repo.finish_operation()?;
+            Err(e.into())
+        }
+        Err(e) => {
+            if let Err(rollback_error) = repo.rollback_sync() {
+                error!("Unable to roll back sync: {:?}", rollback_error);
+            }
+            repo.discard_operation()?;
+            Err(e.into())
+        }
+    }
+}
+
+fn sync(options: Options, repo: &GitRepo) -> anyhow::Result<()> {
+    if !options.cont && !options.no_fetch {
+        fetch::fetch_branches(repo)?;
+    }
     let mut unpushed_commits = repo.unpushed_commits()?;
     let mut parent_commit = if options.cont {
         //Read the current state
@@ -34,7 +113,7 @@ pub fn execute(options: Options, repo: GitRepo) -> anyhow::Result<()> {
         if options.commit_ref.is_some() {
             anyhow::bail!("Can not call --continue with a reference");
         }
-        let tracked_commit = repo.finish_merge()?;
+        let tracked_commit = repo.continue_sync()?;
 
         //
         tracked_commit.commit()
@@ -48,6 +127,15 @@ pub fn execute(options: Options, repo: GitRepo) -> anyhow::Result<()> {
             MainCommit::Tracked(c) => c.commit().parent(0)?,
         }
     } else {
+        let landed = cleanup::find_landed(&unpushed_commits, !options.no_fetch)?;
+        if !landed.is_empty() {
+            let dropped = cleanup::untrack_landed(repo, &landed)?;
+            unpushed_commits = repo
+                .unpushed_commits()?
+                .into_iter()
+                .filter(|commit| !dropped.contains(&commit.id()))
+                .collect();
+        }
         repo.base_commit()?
     };
 
@@ -66,19 +154,23 @@ pub fn execute(options: Options, repo: GitRepo) -> anyhow::Result<()> {
                     summary = tracked_commit.as_commit().summary()
                 )
                 .entered();
+                let tracked_commit = match &tracked_commit.meta_data().parent_branch {
+                    Some(parent_branch) => {
+                        match repo.find_tracked_branch(&parent_commit, parent_branch)? {
+                            Some(parent) => {
+                                let parent_head = parent.meta_data().remote_commit;
+                                tracked_commit.with_base(parent_head)
+                            }
+                            None => tracked_commit,
+                        }
+                    }
+                    None => tracked_commit,
+                };
                 let new_parent_1 = tracked_commit
                     .update_local_branch_head()?
                     .merge_remote_head(Some(&parent_commit))?;
                 //.sync_with_main()?;
 
-                info!(
-                    "Pushing {} to branch {}",
-                    new_parent_1.meta_data().remote_commit,
-                    new_parent_1.meta_data().remote_branch_name
-                );
-                repo.remote()
-                    .push(new_parent_1.meta_data())
-                    .with_context(|| format!("Pushing {}", new_parent_1.meta_data()))?;
                 parent_commit = new_parent_1.commit();
             }
             MainCommit::UnTracked(local_commit) => {
@@ -93,7 +185,55 @@ pub fn execute(options: Options, repo: GitRepo) -> anyhow::Result<()> {
         }
     }
 
+    push_updated_branches(repo, &parent_commit)?;
     repo.update_current_branch(&parent_commit)?;
 
     Ok(())
 }
+
+///
+/// Push every virtual branch up to `head` that has commits the remote branch doesn't have,
+/// all in one push. This also picks up branches rewritten before a `--continue`.
+///
+fn push_updated_branches(repo: &GitRepo, head: &Commit) -> anyhow::Result<()> {
+    let mut meta_data = Vec::new();
+    let tracked_commits = repo.tracked_commits(head)?;
+    for tracked_commit in &tracked_commits {
+        if matches!(
+            tracked_commit.remote_state()?,
+            RemoteState::Ahead(_) | RemoteState::Missing
+        ) {
+            info!(
+                "Pushing {} to branch {}",
+                tracked_commit.meta_data().remote_commit,
+                tracked_commit.meta_data().remote_branch_name
+            );
+            repo.record_push(
+                &tracked_commit.meta_data().remote_branch_name,
+                Some(tracked_commit.meta_data().remote_commit),
+            )?;
+            meta_data.push(tracked_commit.meta_data().clone());
+        }
+    }
+    repo.remote()
+        .push_all(&meta_data)
+        .context("Pushing the synced branches")
+}
+
+///
+/// Update the pull requests of all virtual branches that have one, and open one for every other
+/// branch if `open` is set, as a draft if `draft` is set. The forge is only contacted when there
+/// is something to update.
+///
+fn update_pull_requests(repo: &GitRepo, open: bool, draft: bool) -> anyhow::Result<()> {
+    let tracked_commits: Vec<_> = repo
+        .tracked_commits(&repo.head()?)?
+        .into_iter()
+        .filter(|tracked_commit| open || tracked_commit.meta_data().pull_request.is_some())
+        .collect();
+    if tracked_commits.is_empty() || repo.is_dry_run() {
+        return Ok(());
+    }
+    let forge = forge::open(repo)?;
+    forge::publish_stack(forge.as_ref(), repo, &tracked_commits, draft)
+}
@@ -0,0 +1,73 @@
+use anyhow::Context;
+
+use crate::git::{local_commit::MainCommit, GitRepo};
+
+#[derive(clap::Parser, Default)]
+pub struct Options {
+    /// The commit, or range of commits (`<from>..<to>`), to untrack [default: HEAD]
+    pub commit_ref: Option<String>,
+
+    /// Also delete the remote branches
+    #[arg(long)]
+    pub delete_remote: bool,
+}
+
+impl Options {
+    pub fn with_commit_ref<T: Into<String>>(mut self, name: T) -> Self {
+        self.commit_ref.replace(name.into());
+        self
+    }
+
+    pub fn with_delete_remote(mut self) -> Self {
+        self.delete_remote = true;
+        self
+    }
+}
+
+pub fn execute(options: Options, git_repo: GitRepo) -> crate::Result<()> {
+    git_repo.begin_operation("untrack")?;
+    let rev = options.commit_ref.unwrap_or_else(|| "HEAD".to_string());
+    let commits = match rev.split_once("..") {
+        Some((from, to)) => {
+            let to = if to.is_empty() { "HEAD" } else { to };
+            let from = git_repo.find_commit(from)?.id();
+            let mut commits = Vec::new();
+            let mut commit = git_repo.find_commit(to)?;
+            while commit.id() != from {
+                commits.push(commit.id());
+                commit = commit
+                    .parent(0)
+                    .with_context(|| format!("{} is not an ancestor of {}", from, to))?;
+            }
+            commits
+        }
+        None => vec![git_repo.find_commit(&rev)?.id()],
+    };
+
+    let mut untracked_branches = Vec::new();
+    for commit in git_repo.unpushed_commits()? {
+        let MainCommit::Tracked(tracked) = commit else {
+            continue;
+        };
+        if !tracked.commits()?.iter().any(|c| commits.contains(c)) {
+            continue;
+        }
+        let remote_branch_name = tracked.meta_data().remote_branch_name.to_string();
+        tracked.untrack()?;
+        println!("Untracked {}", remote_branch_name);
+        untracked_branches.push(remote_branch_name);
+    }
+    if untracked_branches.is_empty() {
+        return Err(anyhow::anyhow!("No tracked commits in {}", rev).into());
+    }
+
+    if options.delete_remote {
+        for branch in &untracked_branches {
+            if git_repo.find_head_of_remote_branch(branch).is_some() {
+                git_repo.record_push(branch, None)?;
+                git_repo.remote().delete_branch(branch)?;
+            }
+        }
+    }
+    git_repo.finish_operation()
+}
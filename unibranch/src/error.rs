@@ -0,0 +1,141 @@
+use std::fmt::Display;
+
+use git2::Oid;
+
+use crate::git::remote_command::PushError;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+///
+/// The failures that callers may want to handle. The commands and the public methods of
+/// [`GitRepo`](crate::git::GitRepo), [`TrackedCommit`](crate::git::local_commit::TrackedCommit)
+/// and [`UnTrackedCommit`](crate::git::local_commit::UnTrackedCommit) return them.
+///
+#[derive(Debug)]
+pub enum Error {
+    /// The commit is already tracked by a virtual branch.
+    CommitAlreadyTracked {
+        commit: Oid,
+        branch: String,
+    },
+    /// The remote branch is already used by another virtual branch, that tracks `commit`.
+    BranchAlreadyTracked {
+        branch: String,
+        commit: Oid,
+    },
+    /// The commit is already on the base branch.
+    AlreadyPushed {
+        commit: Oid,
+    },
+    /// Applying the changes of a commit resulted in conflicts. If the sync was paused to let
+    /// the user resolve them, `paused` tells where.
+    MergeConflict {
+        files: Vec<String>,
+        paused: Option<PausedSync>,
+    },
+    /// The remote did not accept all branches, or they have changed since the last sync.
+    PushRejected(PushError),
+    /// HEAD is not a branch.
+    DetachedHead,
+    /// A sync has been paused by conflicts and must be continued or aborted first.
+    SyncInProgress,
+    Other(anyhow::Error),
+}
+
+///
+/// The step a sync was paused at because of conflicts.
+///
+#[derive(Debug)]
+pub enum PausedSync {
+    /// Merging the head of the remote branch `remote` with the local commit `local`.
+    Merge { local: Oid, remote: Oid },
+    /// Updating the remote branch of the local commit `commit`.
+    Update { commit: Oid },
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::CommitAlreadyTracked { commit, branch } => {
+                write!(f, "Commit {} is already tracked by {}", commit, branch)
+            }
+            Error::BranchAlreadyTracked { branch, commit } => {
+                write!(
+                    f,
+                    "Branch {} is already tracked by commit {}",
+                    branch, commit
+                )
+            }
+            Error::AlreadyPushed { commit } => {
+                write!(f, "Commit {} is already pushed to the remote", commit)
+            }
+            Error::MergeConflict {
+                files,
+                paused: None,
+            } => write!(f, "Conflicts in {}", files.join(", ")),
+            Error::MergeConflict {
+                files,
+                paused: Some(paused),
+            } => {
+                match paused {
+                    PausedSync::Merge { local, remote } => writeln!(
+                        f,
+                        "Unable to merge local commit ({}) with commit from remote ({})",
+                        local, remote
+                    )?,
+                    PausedSync::Update { commit } => writeln!(
+                        f,
+                        "Unable to update the remote branch of {} because of conflicts in {}",
+                        commit,
+                        files.join(", ")
+                    )?,
+                }
+                writeln!(
+                    f,
+                    "Once all the conflicts has been resolved, run 'ubr sync --continue'"
+                )
+            }
+            Error::PushRejected(error) => error.fmt(f),
+            Error::DetachedHead => write!(f, "Detached HEAD"),
+            Error::SyncInProgress => write!(
+                f,
+                "A sync is already in progress, run 'ubr sync --continue' or 'ubr sync --abort'"
+            ),
+            Error::Other(error) => error.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Other(error) => Some(error.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<anyhow::Error> for Error {
+    fn from(error: anyhow::Error) -> Self {
+        let error = match error.downcast::<Error>() {
+            Ok(error) => return error,
+            Err(error) => error,
+        };
+        match error.downcast::<PushError>() {
+            Ok(error) => Error::PushRejected(error),
+            Err(error) => Error::Other(error),
+        }
+    }
+}
+
+impl From<git2::Error> for Error {
+    fn from(error: git2::Error) -> Self {
+        Error::Other(error.into())
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(error: std::io::Error) -> Self {
+        Error::Other(error.into())
+    }
+}
@@ -0,0 +1,67 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+const DESCRIPTIONS_FILE: &str = ".ubr/PULL_REQUEST_DESCRIPTIONS";
+
+///
+/// The descriptions last written by ubr, from the commit messages, by pull request number. The
+/// rest of the description of a pull request was written on the forge.
+///
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub(crate) struct Descriptions {
+    descriptions: HashMap<u64, String>,
+}
+
+impl Descriptions {
+    fn path<P>(repo_path: P) -> PathBuf
+    where
+        P: AsRef<Path>,
+    {
+        repo_path.as_ref().join(DESCRIPTIONS_FILE)
+    }
+
+    ///
+    /// The descriptions written in the repository at `repo_path`. Descriptions that can't be
+    /// read are only reported, the descriptions on the forge are then left as they are.
+    ///
+    pub(crate) fn load<P>(repo_path: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        match std::fs::File::open(Descriptions::path(repo_path)) {
+            Ok(file) => serde_json::from_reader(file).unwrap_or_else(|e| {
+                warn!("Ignoring the written pull request descriptions: {}", e);
+                Descriptions::default()
+            }),
+            Err(_) => Descriptions::default(),
+        }
+    }
+
+    pub(crate) fn save<P>(&self, repo_path: P) -> anyhow::Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        let path = Descriptions::path(repo_path);
+        let tmp_path = path.with_extension("tmp");
+        std::fs::create_dir_all(
+            path.parent()
+                .expect("Descriptions are always in a directory"),
+        )?;
+        serde_json::to_writer(std::fs::File::create(&tmp_path)?, self)?;
+        std::fs::rename(&tmp_path, &path).context("Writing pull request descriptions")
+    }
+
+    pub(crate) fn get(&self, number: u64) -> Option<&str> {
+        self.descriptions.get(&number).map(String::as_str)
+    }
+
+    pub(crate) fn insert(&mut self, number: u64, description: String) {
+        self.descriptions.insert(number, description);
+    }
+}
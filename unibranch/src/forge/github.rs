@@ -0,0 +1,288 @@
+use anyhow::Context;
+use serde::Deserialize;
+use serde_json::json;
+
+use super::{
+    api_error, CheckState, Forge, PullRequest, PullRequestContent, PullRequestStatus, ReviewState,
+};
+
+pub const DEFAULT_API_URL: &str = "https://api.github.com";
+
+///
+/// Pull requests through the GitHub REST API.
+///
+pub struct GitHub {
+    agent: ureq::Agent,
+    api_url: String,
+    repository: String,
+    token: String,
+}
+
+#[derive(Deserialize)]
+struct GitHubPullRequest {
+    number: u64,
+    html_url: String,
+    title: String,
+    body: Option<String>,
+    state: String,
+    #[serde(default)]
+    draft: bool,
+    #[serde(default)]
+    mergeable: Option<bool>,
+    head: GitHubBranch,
+    base: GitHubBranch,
+}
+
+#[derive(Deserialize)]
+struct GitHubBranch {
+    #[serde(rename = "ref")]
+    branch: String,
+    #[serde(default)]
+    sha: String,
+}
+
+#[derive(Deserialize)]
+struct GitHubReview {
+    user: Option<GitHubUser>,
+    state: String,
+}
+
+#[derive(Deserialize)]
+struct GitHubUser {
+    login: String,
+}
+
+#[derive(Deserialize)]
+struct GitHubCombinedStatus {
+    state: String,
+    total_count: u64,
+}
+
+#[derive(Deserialize)]
+struct GitHubCheckRuns {
+    total_count: usize,
+    check_runs: Vec<GitHubCheckRun>,
+}
+
+#[derive(Deserialize)]
+struct GitHubCheckRun {
+    status: String,
+    conclusion: Option<String>,
+}
+
+impl From<GitHubPullRequest> for PullRequest {
+    fn from(value: GitHubPullRequest) -> Self {
+        PullRequest {
+            number: value.number,
+            url: value.html_url,
+            title: value.title,
+            body: value.body.unwrap_or_default(),
+            head: value.head.branch,
+            base: value.base.branch,
+            open: value.state == "open",
+            draft: value.draft,
+        }
+    }
+}
+
+impl GitHub {
+    ///
+    /// The pull requests of `repository` (`owner/name`) through the API at `api_url`, e.g.
+    /// `https://api.github.com` or `https://github.example.com/api/v3`.
+    ///
+    pub fn new<U, R, T>(api_url: U, repository: R, token: T) -> Self
+    where
+        U: Into<String>,
+        R: Into<String>,
+        T: Into<String>,
+    {
+        GitHub {
+            agent: ureq::AgentBuilder::new().build(),
+            api_url: api_url.into().trim_end_matches('/').to_string(),
+            repository: repository.into(),
+            token: token.into(),
+        }
+    }
+
+    fn request(&self, method: &str, path: &str) -> ureq::Request {
+        self.agent
+            .request(
+                method,
+                &format!("{}/repos/{}{}", self.api_url, self.repository, path),
+            )
+            .set("Accept", "application/vnd.github+json")
+            .set("Authorization", &format!("Bearer {}", self.token))
+            .set("X-GitHub-Api-Version", "2022-11-28")
+    }
+
+    ///
+    /// All check runs of commit `sha`, read page by page until `total_count` of them are read.
+    ///
+    fn check_runs(&self, sha: &str) -> anyhow::Result<Vec<GitHubCheckRun>> {
+        let mut check_runs = Vec::new();
+        for page in 1.. {
+            let response: GitHubCheckRuns = self
+                .request("GET", &format!("/commits/{}/check-runs", sha))
+                .query("per_page", "100")
+                .query("page", &page.to_string())
+                .call()
+                .map_err(api_error)?
+                .into_json()?;
+            let last_page = response.check_runs.is_empty()
+                || check_runs.len() + response.check_runs.len() >= response.total_count;
+            check_runs.extend(response.check_runs);
+            if last_page {
+                break;
+            }
+        }
+        Ok(check_runs)
+    }
+
+    fn owner(&self) -> &str {
+        self.repository
+            .split_once('/')
+            .map(|(owner, _)| owner)
+            .unwrap_or(&self.repository)
+    }
+}
+
+impl Forge for GitHub {
+    fn find_pull_request(&self, branch: &str) -> anyhow::Result<Option<PullRequest>> {
+        let pull_requests: Vec<GitHubPullRequest> = self
+            .request("GET", "/pulls")
+            .query("head", &format!("{}:{}", self.owner(), branch))
+            .query("state", "open")
+            .call()
+            .map_err(api_error)?
+            .into_json()
+            .context("Reading the pull requests")?;
+        Ok(pull_requests.into_iter().next().map(PullRequest::from))
+    }
+
+    fn get_pull_request(&self, number: u64) -> anyhow::Result<PullRequest> {
+        let pull_request: GitHubPullRequest = self
+            .request("GET", &format!("/pulls/{}", number))
+            .call()
+            .map_err(api_error)?
+            .into_json()
+            .with_context(|| format!("Reading pull request #{}", number))?;
+        Ok(pull_request.into())
+    }
+
+    fn create_pull_request(&self, content: &PullRequestContent) -> anyhow::Result<PullRequest> {
+        let pull_request: GitHubPullRequest = self
+            .request("POST", "/pulls")
+            .send_json(json!({
+                "head": content.head,
+                "base": content.base,
+                "title": content.title,
+                "body": content.body,
+                "draft": content.draft,
+            }))
+            .map_err(api_error)?
+            .into_json()
+            .context("Reading the new pull request")?;
+        Ok(pull_request.into())
+    }
+
+    fn update_pull_request(
+        &self,
+        number: u64,
+        content: &PullRequestContent,
+    ) -> anyhow::Result<PullRequest> {
+        let pull_request: GitHubPullRequest = self
+            .request("PATCH", &format!("/pulls/{}", number))
+            .send_json(json!({
+                "base": content.base,
+                "title": content.title,
+                "body": content.body,
+            }))
+            .map_err(api_error)?
+            .into_json()
+            .with_context(|| format!("Reading pull request #{}", number))?;
+        Ok(pull_request.into())
+    }
+
+    fn pull_request_status(&self, number: u64) -> anyhow::Result<PullRequestStatus> {
+        let pull_request: GitHubPullRequest = self
+            .request("GET", &format!("/pulls/{}", number))
+            .call()
+            .map_err(api_error)?
+            .into_json()
+            .with_context(|| format!("Reading pull request #{}", number))?;
+        let reviews: Vec<GitHubReview> = self
+            .request("GET", &format!("/pulls/{}/reviews", number))
+            .call()
+            .map_err(api_error)?
+            .into_json()
+            .with_context(|| format!("Reading the reviews of pull request #{}", number))?;
+        // Checks are reported either as commit statuses or, e.g. by GitHub Actions, as check runs
+        let status: GitHubCombinedStatus = self
+            .request("GET", &format!("/commits/{}/status", pull_request.head.sha))
+            .call()
+            .map_err(api_error)?
+            .into_json()
+            .with_context(|| format!("Reading the checks of pull request #{}", number))?;
+        let check_runs = self
+            .check_runs(&pull_request.head.sha)
+            .with_context(|| format!("Reading the check runs of pull request #{}", number))?;
+
+        Ok(PullRequestStatus {
+            review: review_state(&reviews),
+            checks: combined_check_state(&status, &check_runs),
+            mergeable: pull_request.mergeable,
+        })
+    }
+}
+
+///
+/// The state of the commit statuses and the check runs of a commit together: failing if any
+/// of them fails, else pending if any of them is still running.
+///
+fn combined_check_state(
+    status: &GitHubCombinedStatus,
+    check_runs: &[GitHubCheckRun],
+) -> CheckState {
+    let status = match status.state.as_str() {
+        _ if status.total_count == 0 => None,
+        "success" => Some(CheckState::Passing),
+        "failure" | "error" => Some(CheckState::Failing),
+        _ => Some(CheckState::Pending),
+    };
+    let check_runs = check_runs.iter().map(|check_run| {
+        match (check_run.status.as_str(), check_run.conclusion.as_deref()) {
+            ("completed", Some("success" | "neutral" | "skipped")) => CheckState::Passing,
+            ("completed", _) => CheckState::Failing,
+            _ => CheckState::Pending,
+        }
+    });
+    let states: Vec<_> = status.into_iter().chain(check_runs).collect();
+    [
+        CheckState::Failing,
+        CheckState::Pending,
+        CheckState::Passing,
+    ]
+    .into_iter()
+    .find(|state| states.contains(state))
+    .unwrap_or(CheckState::NoChecks)
+}
+
+///
+/// The review state given by the latest review of each reviewer, comments aside.
+///
+fn review_state(reviews: &[GitHubReview]) -> ReviewState {
+    let mut latest = std::collections::HashMap::new();
+    for review in reviews {
+        if review.state != "COMMENTED" {
+            let reviewer = review.user.as_ref().map(|user| user.login.as_str());
+            latest.insert(reviewer, review.state.as_str());
+        }
+    }
+    if latest.values().any(|state| *state == "CHANGES_REQUESTED") {
+        ReviewState::ChangesRequested
+    } else if latest.values().any(|state| *state == "APPROVED") {
+        ReviewState::Approved
+    } else {
+        ReviewState::Pending
+    }
+}
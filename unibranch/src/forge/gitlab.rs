@@ -0,0 +1,207 @@
+use anyhow::Context;
+use serde::Deserialize;
+use serde_json::json;
+
+use super::{
+    api_error, CheckState, Forge, PullRequest, PullRequestContent, PullRequestStatus, ReviewState,
+};
+
+pub const DEFAULT_API_URL: &str = "https://gitlab.com/api/v4";
+
+/// The title prefix that marks a merge request as a draft
+const DRAFT_PREFIX: &str = "Draft: ";
+
+///
+/// Merge requests through the GitLab v4 API.
+///
+pub struct GitLab {
+    agent: ureq::Agent,
+    api_url: String,
+    project: String,
+    token: String,
+}
+
+#[derive(Deserialize)]
+struct GitLabMergeRequest {
+    iid: u64,
+    web_url: String,
+    title: String,
+    description: Option<String>,
+    state: String,
+    #[serde(default)]
+    draft: bool,
+    source_branch: String,
+    target_branch: String,
+    #[serde(default)]
+    merge_status: String,
+    #[serde(default)]
+    head_pipeline: Option<GitLabPipeline>,
+}
+
+#[derive(Deserialize)]
+struct GitLabPipeline {
+    status: String,
+}
+
+#[derive(Deserialize)]
+struct GitLabApprovals {
+    approved: bool,
+}
+
+impl From<GitLabMergeRequest> for PullRequest {
+    fn from(value: GitLabMergeRequest) -> Self {
+        PullRequest {
+            number: value.iid,
+            url: value.web_url,
+            title: value
+                .title
+                .strip_prefix(DRAFT_PREFIX)
+                .unwrap_or(&value.title)
+                .to_string(),
+            body: value.description.unwrap_or_default(),
+            head: value.source_branch,
+            base: value.target_branch,
+            open: value.state == "opened",
+            draft: value.draft || value.title.starts_with(DRAFT_PREFIX),
+        }
+    }
+}
+
+impl GitLab {
+    ///
+    /// The merge requests of `project` (`group/name`) through the API at `api_url`, e.g.
+    /// `https://gitlab.com/api/v4`.
+    ///
+    pub fn new<U, P, T>(api_url: U, project: P, token: T) -> Self
+    where
+        U: Into<String>,
+        P: Into<String>,
+        T: Into<String>,
+    {
+        GitLab {
+            agent: ureq::AgentBuilder::new().build(),
+            api_url: api_url.into().trim_end_matches('/').to_string(),
+            project: project.into(),
+            token: token.into(),
+        }
+    }
+
+    fn request(&self, method: &str, path: &str) -> ureq::Request {
+        // The project is identified by its path, with the `/` encoded
+        let project: String =
+            url::form_urlencoded::byte_serialize(self.project.as_bytes()).collect();
+        self.agent
+            .request(
+                method,
+                &format!("{}/projects/{}{}", self.api_url, project, path),
+            )
+            .set("PRIVATE-TOKEN", &self.token)
+    }
+}
+
+impl Forge for GitLab {
+    fn find_pull_request(&self, branch: &str) -> anyhow::Result<Option<PullRequest>> {
+        let merge_requests: Vec<GitLabMergeRequest> = self
+            .request("GET", "/merge_requests")
+            .query("source_branch", branch)
+            .query("state", "opened")
+            .call()
+            .map_err(api_error)?
+            .into_json()
+            .context("Reading the merge requests")?;
+        Ok(merge_requests.into_iter().next().map(PullRequest::from))
+    }
+
+    fn get_pull_request(&self, number: u64) -> anyhow::Result<PullRequest> {
+        let merge_request: GitLabMergeRequest = self
+            .request("GET", &format!("/merge_requests/{}", number))
+            .call()
+            .map_err(api_error)?
+            .into_json()
+            .with_context(|| format!("Reading merge request !{}", number))?;
+        Ok(merge_request.into())
+    }
+
+    fn create_pull_request(&self, content: &PullRequestContent) -> anyhow::Result<PullRequest> {
+        let merge_request: GitLabMergeRequest = self
+            .request("POST", "/merge_requests")
+            .send_json(json!({
+                "source_branch": content.head,
+                "target_branch": content.base,
+                "title": title(content),
+                "description": content.body,
+            }))
+            .map_err(api_error)?
+            .into_json()
+            .context("Reading the new merge request")?;
+        Ok(merge_request.into())
+    }
+
+    fn update_pull_request(
+        &self,
+        number: u64,
+        content: &PullRequestContent,
+    ) -> anyhow::Result<PullRequest> {
+        let merge_request: GitLabMergeRequest = self
+            .request("PUT", &format!("/merge_requests/{}", number))
+            .send_json(json!({
+                "target_branch": content.base,
+                "title": title(content),
+                "description": content.body,
+            }))
+            .map_err(api_error)?
+            .into_json()
+            .with_context(|| format!("Reading merge request !{}", number))?;
+        Ok(merge_request.into())
+    }
+
+    fn pull_request_status(&self, number: u64) -> anyhow::Result<PullRequestStatus> {
+        let merge_request: GitLabMergeRequest = self
+            .request("GET", &format!("/merge_requests/{}", number))
+            .call()
+            .map_err(api_error)?
+            .into_json()
+            .with_context(|| format!("Reading merge request !{}", number))?;
+        let approvals: GitLabApprovals = self
+            .request("GET", &format!("/merge_requests/{}/approvals", number))
+            .call()
+            .map_err(api_error)?
+            .into_json()
+            .with_context(|| format!("Reading the approvals of merge request !{}", number))?;
+
+        Ok(PullRequestStatus {
+            // Requesting changes isn't part of every edition of GitLab
+            review: if approvals.approved {
+                ReviewState::Approved
+            } else {
+                ReviewState::Pending
+            },
+            checks: match merge_request
+                .head_pipeline
+                .as_ref()
+                .map(|p| p.status.as_str())
+            {
+                None => CheckState::NoChecks,
+                Some("success") => CheckState::Passing,
+                Some("failed" | "canceled") => CheckState::Failing,
+                Some(_) => CheckState::Pending,
+            },
+            mergeable: match merge_request.merge_status.as_str() {
+                "can_be_merged" => Some(true),
+                "cannot_be_merged" => Some(false),
+                _ => None,
+            },
+        })
+    }
+}
+
+///
+/// The title of the merge request for `content`, which is how GitLab tells drafts apart.
+///
+fn title(content: &PullRequestContent) -> String {
+    if content.draft {
+        format!("{}{}", DRAFT_PREFIX, content.title)
+    } else {
+        content.title.clone()
+    }
+}
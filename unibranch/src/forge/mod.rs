@@ -0,0 +1,714 @@
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    io::Write,
+    process::{Command, Stdio},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::git::{local_commit::TrackedCommit, GitRepo};
+
+mod descriptions;
+mod github;
+mod gitlab;
+mod status_cache;
+use descriptions::Descriptions;
+pub use github::GitHub;
+pub use gitlab::GitLab;
+use status_cache::StatusCache;
+
+/// How long the status of a pull request is cached, in seconds, unless `ubr.forgeCacheTtl` is set
+pub const DEFAULT_CACHE_TTL: u64 = 300;
+
+///
+/// A pull request on a forge, e.g. GitHub, or a merge request on GitLab.
+///
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct PullRequest {
+    pub number: u64,
+    pub url: String,
+    pub title: String,
+    pub body: String,
+    /// The branch that is merged.
+    pub head: String,
+    /// The branch it is merged into.
+    pub base: String,
+    /// Whether it is still open, i.e. neither closed nor merged.
+    pub open: bool,
+    /// Whether it is a draft, not ready for review yet.
+    pub draft: bool,
+}
+
+///
+/// What a pull request should look like: the branches and the description.
+///
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct PullRequestContent {
+    pub head: String,
+    pub base: String,
+    pub title: String,
+    pub body: String,
+    /// Open the pull request as a draft. Whether an existing pull request is a draft is left
+    /// as it is.
+    pub draft: bool,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+pub enum ReviewState {
+    Approved,
+    ChangesRequested,
+    /// Not reviewed yet, or only commented on.
+    Pending,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+pub enum CheckState {
+    Passing,
+    Failing,
+    Pending,
+    /// No checks run on the pull request.
+    NoChecks,
+}
+
+///
+/// What is left to do before a pull request can be merged.
+///
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+pub struct PullRequestStatus {
+    pub review: ReviewState,
+    pub checks: CheckState,
+    /// Whether it can be merged without conflicts, `None` while the forge hasn't found out yet.
+    pub mergeable: Option<bool>,
+}
+
+impl Display for PullRequestStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let review = match self.review {
+            ReviewState::Approved => "approved",
+            ReviewState::ChangesRequested => "changes requested",
+            ReviewState::Pending => "review pending",
+        };
+        let checks = match self.checks {
+            CheckState::Passing => "checks passing",
+            CheckState::Failing => "checks failing",
+            CheckState::Pending => "checks pending",
+            CheckState::NoChecks => "no checks",
+        };
+        let mergeable = match self.mergeable {
+            Some(true) => "mergeable",
+            Some(false) => "conflicts",
+            None => "mergeability unknown",
+        };
+        write!(f, "{}, {}, {}", review, checks, mergeable)
+    }
+}
+
+///
+/// The hosting service that pull requests are opened on.
+///
+pub trait Forge {
+    ///
+    /// The open pull request that merges `branch`, if any.
+    ///
+    fn find_pull_request(&self, branch: &str) -> anyhow::Result<Option<PullRequest>>;
+
+    fn get_pull_request(&self, number: u64) -> anyhow::Result<PullRequest>;
+
+    fn create_pull_request(&self, content: &PullRequestContent) -> anyhow::Result<PullRequest>;
+
+    ///
+    /// Set the base branch, title and body of pull request `number` to those of `content`.
+    ///
+    fn update_pull_request(
+        &self,
+        number: u64,
+        content: &PullRequestContent,
+    ) -> anyhow::Result<PullRequest>;
+
+    ///
+    /// The review state, the state of the checks of the latest commit and the mergeability of
+    /// pull request `number`.
+    ///
+    fn pull_request_status(&self, number: u64) -> anyhow::Result<PullRequestStatus>;
+}
+
+///
+/// The forge configured for `git_repo`.
+///
+/// `ubr.forge` names the kind of forge, `github` or `gitlab`, which is told from the host of
+/// the push remote when not set. `ubr.forgeUrl` is the URL of its API and `ubr.forgeRepository`
+/// the repository (`owner/name`), which defaults to the path of the push remote URL.
+///
+pub fn open(git_repo: &GitRepo) -> anyhow::Result<Box<dyn Forge>> {
+    let config = git_repo.config()?;
+    let remote_url = git_repo.remote_url().ok();
+    let kind = match config.get_string("ubr.forge") {
+        Ok(kind) => kind,
+        Err(_) => match remote_url.as_deref().and_then(host_of).and_then(kind_of) {
+            Some(kind) => kind.to_string(),
+            None => anyhow::bail!(
+                "Unable to tell the forge of remote {}, set ubr.forge",
+                git_repo.remote_name()
+            ),
+        },
+    };
+    let repository = match config.get_string("ubr.forgeRepository") {
+        Ok(repository) => repository,
+        Err(_) => remote_url
+            .as_deref()
+            .and_then(repository_of)
+            .with_context(|| {
+                format!(
+                    "Unable to tell the repository of remote {}, set ubr.forgeRepository",
+                    git_repo.remote_name()
+                )
+            })?,
+    };
+    match kind.as_str() {
+        "github" => {
+            let api_url = config
+                .get_string("ubr.forgeUrl")
+                .unwrap_or_else(|_| github::DEFAULT_API_URL.to_string());
+            let token = token(git_repo, &api_url, &["GITHUB_TOKEN", "GH_TOKEN"])?;
+            Ok(Box::new(GitHub::new(api_url, repository, token)))
+        }
+        "gitlab" => {
+            // Self-hosted instances serve the API from the host of the remote
+            let api_url = config.get_string("ubr.forgeUrl").unwrap_or_else(|_| {
+                match remote_url.as_deref().and_then(host_of) {
+                    Some(host) => format!("https://{}/api/v4", host),
+                    None => gitlab::DEFAULT_API_URL.to_string(),
+                }
+            });
+            let token = token(git_repo, &api_url, &["GITLAB_TOKEN"])?;
+            Ok(Box::new(GitLab::new(api_url, repository, token)))
+        }
+        _ => anyhow::bail!("Unknown forge '{}' in ubr.forge", kind),
+    }
+}
+
+///
+/// The status of every pull request of `pull_requests`, given with the remote commit of its
+/// branch. Pull requests whose status can't be read are reported and left out.
+///
+/// Statuses are cached in `.ubr` for `ubr.forgeCacheTtl` seconds, or until the branch is
+/// pushed again, so the forge is only contacted for the ones that are outdated, or for all of
+/// them if `refresh` is set.
+///
+pub fn pull_request_statuses(
+    git_repo: &GitRepo,
+    pull_requests: &[(u64, git2::Oid)],
+    refresh: bool,
+) -> anyhow::Result<HashMap<u64, PullRequestStatus>> {
+    let ttl = git_repo
+        .config()?
+        .get_i64("ubr.forgeCacheTtl")
+        .ok()
+        .and_then(|ttl| u64::try_from(ttl).ok())
+        .unwrap_or(DEFAULT_CACHE_TTL);
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    let mut cache = StatusCache::load(git_repo.path());
+    let mut forge = None;
+    let mut statuses = HashMap::new();
+    for (number, remote_commit) in pull_requests {
+        let cached = cache
+            .get(*number, *remote_commit, now, ttl)
+            .filter(|_| !refresh);
+        let status = match cached {
+            Some(status) => status,
+            None => {
+                let forge = match &forge {
+                    Some(forge) => forge,
+                    None => forge.insert(open(git_repo)?),
+                };
+                // One pull request that can't be read shouldn't hide the status of the others
+                match forge.pull_request_status(*number) {
+                    Ok(status) => {
+                        cache.insert(*number, *remote_commit, now, status);
+                        status
+                    }
+                    Err(e) => {
+                        warn!(
+                            "Unable to read the status of pull request #{}: {:#}",
+                            number, e
+                        );
+                        continue;
+                    }
+                }
+            }
+        };
+        statuses.insert(*number, status);
+    }
+    cache.retain(|number| pull_requests.iter().any(|(n, _)| *n == number));
+    cache.save(git_repo.path())?;
+    Ok(statuses)
+}
+
+/// Marks the start of the navigation block in pull request descriptions
+const STACK_START: &str = "<!-- ubr-stack -->";
+/// Marks the end of the navigation block in pull request descriptions
+const STACK_END: &str = "<!-- /ubr-stack -->";
+
+///
+/// Open or update the pull requests of `tracked_commits`, see [`publish`], and keep a block
+/// that lists all of them, newest first, up to date in each of their descriptions.
+///
+pub fn publish_stack(
+    forge: &dyn Forge,
+    git_repo: &GitRepo,
+    tracked_commits: &[TrackedCommit],
+    draft: bool,
+) -> anyhow::Result<()> {
+    let mut stack = Vec::new();
+    for tracked_commit in tracked_commits {
+        if let Some(pull_request) = publish(forge, git_repo, tracked_commit, draft)? {
+            stack.push(pull_request);
+        }
+    }
+    stack.reverse();
+    for pull_request in &stack {
+        // A single pull request is no stack
+        let block = (stack.len() > 1).then(|| stack_block(&stack, pull_request.number));
+        let body = with_stack_block(&pull_request.body, block.as_deref());
+        if body != pull_request.body {
+            let content = PullRequestContent {
+                head: pull_request.head.clone(),
+                base: pull_request.base.clone(),
+                title: pull_request.title.clone(),
+                body,
+                draft: pull_request.draft,
+            };
+            forge
+                .update_pull_request(pull_request.number, &content)
+                .with_context(|| format!("Updating pull request #{}", pull_request.number))?;
+        }
+    }
+    Ok(())
+}
+
+///
+/// Open a pull request for the virtual branch of `tracked_commit`, or update the one it has,
+/// with the summary of the commit as title and the rest of its message as description. The
+/// number of the pull request is saved in the metadata.
+///
+/// Only the text that ubr wrote into the description is updated, so that changes made to it on
+/// the forge are kept. A new pull request is opened as a draft if `draft` is set. Pull requests
+/// that have been closed or merged are left alone.
+///
+pub fn publish(
+    forge: &dyn Forge,
+    git_repo: &GitRepo,
+    tracked_commit: &TrackedCommit,
+    draft: bool,
+) -> anyhow::Result<Option<PullRequest>> {
+    let meta_data = tracked_commit.meta_data();
+    let message = tracked_commit.as_commit().message().unwrap_or_default();
+    let (title, body) = message.split_once('\n').unwrap_or((message, ""));
+    let content = PullRequestContent {
+        head: meta_data.remote_branch_name.to_string(),
+        base: meta_data
+            .parent_branch
+            .as_deref()
+            .unwrap_or(git_repo.base_branch_name())
+            .to_string(),
+        title: title.trim().to_string(),
+        body: body.trim().to_string(),
+        draft,
+    };
+
+    let existing = match meta_data.pull_request {
+        Some(number) => Some(forge.get_pull_request(number)?),
+        None => forge.find_pull_request(&content.head)?,
+    };
+    let mut descriptions = Descriptions::load(git_repo.path());
+    let description = content.body.clone();
+    let pull_request = match existing {
+        Some(existing) if !existing.open => {
+            warn!(
+                "Pull request #{} of {} is closed, not updating it",
+                existing.number, content.head
+            );
+            return Ok(None);
+        }
+        Some(existing) => {
+            let body = updated_description(
+                &existing.body,
+                descriptions.get(existing.number),
+                &content.body,
+            );
+            if body.is_some() {
+                descriptions.insert(existing.number, description);
+            }
+            let body = body.unwrap_or_else(|| existing.body.clone());
+            if existing.base == content.base
+                && existing.title == content.title
+                && existing.body == body
+            {
+                existing
+            } else {
+                let content = PullRequestContent {
+                    body,
+                    draft: existing.draft,
+                    ..content
+                };
+                let pull_request = forge
+                    .update_pull_request(existing.number, &content)
+                    .with_context(|| format!("Updating pull request #{}", existing.number))?;
+                println!(
+                    "Updated pull request #{} {}",
+                    pull_request.number, pull_request.url
+                );
+                pull_request
+            }
+        }
+        None => {
+            let pull_request = forge
+                .create_pull_request(&content)
+                .with_context(|| format!("Opening a pull request for {}", content.head))?;
+            println!(
+                "Opened pull request #{} {}",
+                pull_request.number, pull_request.url
+            );
+            descriptions.insert(pull_request.number, description);
+            pull_request
+        }
+    };
+    descriptions.save(git_repo.path())?;
+
+    if meta_data.pull_request != Some(pull_request.number) {
+        let mut meta_data = meta_data.clone();
+        meta_data.pull_request = Some(pull_request.number);
+        git_repo.save_meta_data(tracked_commit.as_commit(), &meta_data)?;
+    }
+    Ok(Some(pull_request))
+}
+
+///
+/// `description` with `written`, the text ubr wrote at its start last time, replaced by `new`,
+/// or `None` if it can't tell where that text is: it wasn't recorded, or the start of the
+/// description has been edited on the forge.
+///
+fn updated_description(description: &str, written: Option<&str>, new: &str) -> Option<String> {
+    let written = written?;
+    if written == new {
+        return Some(description.to_string());
+    }
+    if written.is_empty() {
+        return Some(match description.trim_start() {
+            "" => new.to_string(),
+            rest => format!("{}\n\n{}", new, rest),
+        });
+    }
+    // Only a whole paragraph counts, not the start of a longer one
+    let rest = description.trim_start().strip_prefix(written)?;
+    if !(rest.is_empty() || rest.starts_with(['\r', '\n'])) {
+        return None;
+    }
+    Some(format!("{}{}", new, rest).trim_start().to_string())
+}
+
+///
+/// The navigation block for the pull request `current` of `stack`, in Markdown.
+///
+fn stack_block(stack: &[PullRequest], current: u64) -> String {
+    let mut block = format!("{}\n---\n**Stack**\n\n", STACK_START);
+    for pull_request in stack {
+        let link = format!("[{}]({})", pull_request.title, pull_request.url);
+        if pull_request.number == current {
+            block.push_str(&format!("- **{}** \u{2190} this one\n", link));
+        } else {
+            block.push_str(&format!("- {}\n", link));
+        }
+    }
+    block.push_str(STACK_END);
+    block
+}
+
+///
+/// `description` with its navigation block replaced by `block`, or added at the end when it
+/// has none. The block is removed if `block` is `None`. The rest of the description is kept as
+/// it is.
+///
+fn with_stack_block(description: &str, block: Option<&str>) -> String {
+    let existing = description.find(STACK_START).and_then(|start| {
+        description[start..]
+            .find(STACK_END)
+            .map(|end| (start, start + end + STACK_END.len()))
+    });
+    match (existing, block) {
+        (Some((start, end)), Some(block)) => {
+            format!("{}{}{}", &description[..start], block, &description[end..])
+        }
+        (Some((start, end)), None) => {
+            let before = description[..start].trim_end();
+            let after = &description[end..];
+            if before.is_empty() {
+                after.trim_start().to_string()
+            } else if after.trim().is_empty() {
+                before.to_string()
+            } else {
+                format!("{}\n\n{}", before, after.trim_start())
+            }
+        }
+        (None, Some(block)) if description.trim().is_empty() => block.to_string(),
+        (None, Some(block)) => format!("{}\n\n{}", description.trim_end(), block),
+        (None, None) => description.to_string(),
+    }
+}
+
+///
+/// The token to authenticate to the API at `api_url` with: the first of the environment
+/// variables `env_vars` that is set, or else the password git has stored for its host.
+///
+fn token(git_repo: &GitRepo, api_url: &str, env_vars: &[&str]) -> anyhow::Result<String> {
+    if let Some(token) = env_vars
+        .iter()
+        .find_map(|var| std::env::var(var).ok().filter(|token| !token.is_empty()))
+    {
+        return Ok(token);
+    }
+
+    let url = url::Url::parse(api_url).with_context(|| format!("Invalid URL '{}'", api_url))?;
+    let host = match (url.host_str(), url.port()) {
+        (Some(host), Some(port)) => format!("{}:{}", host, port),
+        (Some(host), None) => host.strip_prefix("api.").unwrap_or(host).to_string(),
+        (None, _) => anyhow::bail!("Invalid URL '{}'", api_url),
+    };
+    let mut credential = Command::new("git")
+        .current_dir(git_repo.path())
+        .args(["credential", "fill"])
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .context("Running git credential")?;
+    credential
+        .stdin
+        .take()
+        .context("Running git credential")?
+        .write_all(format!("protocol={}\nhost={}\n\n", url.scheme(), host).as_bytes())?;
+    let output = credential.wait_with_output()?;
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .find_map(|line| line.strip_prefix("password="))
+        .filter(|_| output.status.success())
+        .map(str::to_string)
+        .with_context(|| {
+            format!(
+                "No token for {}, set {} or store one with git credential",
+                host,
+                env_vars.join(" or ")
+            )
+        })
+}
+
+///
+/// The host of a remote URL such as `git@gitlab.example.com:group/name.git` or
+/// `https://gitlab.example.com/group/name`.
+///
+fn host_of(remote_url: &str) -> Option<&str> {
+    let authority = match remote_url.split_once("://") {
+        Some((_, rest)) => rest.split_once('/')?.0,
+        None => remote_url.split_once(':')?.0,
+    };
+    let host = authority
+        .rsplit_once('@')
+        .map_or(authority, |(_, host)| host);
+    let host = host.split_once(':').map_or(host, |(host, _)| host);
+    (!host.is_empty()).then_some(host)
+}
+
+///
+/// The kind of forge at `host`, if it is known from the host alone: `github.com`, or
+/// `gitlab.com` and self-hosted GitLab instances on a `gitlab.` host.
+///
+fn kind_of(host: &str) -> Option<&'static str> {
+    let host = host.to_ascii_lowercase();
+    if host == "github.com" {
+        Some("github")
+    } else if host == "gitlab.com" || host.starts_with("gitlab.") {
+        Some("gitlab")
+    } else {
+        None
+    }
+}
+
+///
+/// The repository (`owner/name`) a remote URL such as `git@github.com:owner/name.git` or
+/// `https://github.com/owner/name` points to.
+///
+fn repository_of(remote_url: &str) -> Option<String> {
+    let path = remote_url.trim_end_matches('/').trim_end_matches(".git");
+    let path = match path.split_once("://") {
+        Some((_, rest)) => rest.split_once('/')?.1,
+        None => path.split_once(':')?.1,
+    };
+    path.contains('/').then(|| path.to_string())
+}
+
+///
+/// An error that includes the message of the API, which tells why a request was refused.
+///
+fn api_error(error: ureq::Error) -> anyhow::Error {
+    match error {
+        ureq::Error::Status(status, response) => {
+            let url = response.get_url().to_string();
+            let message = response.into_string().unwrap_or_default();
+            anyhow::anyhow!("{} responded with {}: {}", url, status, message)
+        }
+        error => error.into(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use indoc::indoc;
+    use pretty_assertions::assert_eq;
+
+    use super::{
+        host_of, kind_of, repository_of, stack_block, updated_description, with_stack_block,
+        PullRequest,
+    };
+
+    fn pull_request(number: u64, title: &str) -> PullRequest {
+        PullRequest {
+            number,
+            url: format!("https://example.com/pull/{}", number),
+            title: title.to_string(),
+            body: String::new(),
+            head: format!("branch-{}", number),
+            base: "main".to_string(),
+            open: true,
+            draft: false,
+        }
+    }
+
+    #[test]
+    fn test_stack_block() {
+        let stack = vec![pull_request(2, "Feature 2"), pull_request(1, "Feature 1")];
+        assert_eq!(
+            stack_block(&stack, 1),
+            indoc! {"
+                <!-- ubr-stack -->
+                ---
+                **Stack**
+
+                - [Feature 2](https://example.com/pull/2)
+                - **[Feature 1](https://example.com/pull/1)** \u{2190} this one
+                <!-- /ubr-stack -->"}
+        );
+    }
+
+    #[test]
+    fn test_with_stack_block() {
+        let block = "<!-- ubr-stack -->\nnew\n<!-- /ubr-stack -->";
+        assert_eq!(with_stack_block("", Some(block)), block);
+        assert_eq!(
+            with_stack_block("Description\n", Some(block)),
+            format!("Description\n\n{}", block)
+        );
+        assert_eq!(
+            with_stack_block(
+                "Before\n<!-- ubr-stack -->\nold\n<!-- /ubr-stack -->\nAfter",
+                Some(block)
+            ),
+            format!("Before\n{}\nAfter", block)
+        );
+        assert_eq!(
+            with_stack_block(
+                "Before\n\n<!-- ubr-stack -->\nold\n<!-- /ubr-stack -->\n\nAfter",
+                None
+            ),
+            "Before\n\nAfter"
+        );
+        assert_eq!(
+            with_stack_block(
+                "Before\n\n<!-- ubr-stack -->\nold\n<!-- /ubr-stack -->",
+                None
+            ),
+            "Before"
+        );
+        assert_eq!(with_stack_block("Unchanged", None), "Unchanged");
+    }
+
+    #[test]
+    fn test_updated_description() {
+        let block = "<!-- ubr-stack -->\nblock\n<!-- /ubr-stack -->";
+        assert_eq!(
+            updated_description(&format!("Old\n\n{}", block), Some("Old"), "New"),
+            Some(format!("New\n\n{}", block))
+        );
+        assert_eq!(
+            updated_description(&format!("By hand\n\n{}", block), Some(""), "New"),
+            Some(format!("New\n\nBy hand\n\n{}", block))
+        );
+        assert_eq!(
+            updated_description("", Some(""), "New"),
+            Some("New".to_string())
+        );
+        assert_eq!(
+            updated_description(&format!("Old\n\n{}", block), Some("Old"), ""),
+            Some(block.to_string())
+        );
+        assert_eq!(updated_description("Edited", Some("Old"), "New"), None);
+        assert_eq!(
+            updated_description("By hand\n\nOld", Some("Old"), "New"),
+            None
+        );
+        assert_eq!(
+            updated_description("Old and more by hand", Some("Old"), "New"),
+            None
+        );
+        assert_eq!(updated_description("Old", None, "New"), None);
+    }
+
+    #[test]
+    fn test_repository_of() {
+        assert_eq!(
+            repository_of("git@github.com:owner/name.git"),
+            Some("owner/name".to_string())
+        );
+        assert_eq!(
+            repository_of("https://github.com/owner/name/"),
+            Some("owner/name".to_string())
+        );
+        assert_eq!(
+            repository_of("ssh://git@gitlab.example.com:2222/group/sub/name.git"),
+            Some("group/sub/name".to_string())
+        );
+        assert_eq!(repository_of("/tmp/remote"), None);
+    }
+
+    #[test]
+    fn test_host_of() {
+        assert_eq!(
+            host_of("git@gitlab.example.com:group/name.git"),
+            Some("gitlab.example.com")
+        );
+        assert_eq!(
+            host_of("https://gitlab.example.com/group/name"),
+            Some("gitlab.example.com")
+        );
+        assert_eq!(
+            host_of("ssh://git@gitlab.example.com:2222/group/name.git"),
+            Some("gitlab.example.com")
+        );
+        assert_eq!(host_of("/tmp/remote"), None);
+    }
+
+    #[test]
+    fn test_kind_of() {
+        assert_eq!(kind_of("github.com"), Some("github"));
+        assert_eq!(kind_of("GitHub.com"), Some("github"));
+        assert_eq!(kind_of("gitlab.com"), Some("gitlab"));
+        assert_eq!(kind_of("gitlab.example.com"), Some("gitlab"));
+        assert_eq!(kind_of("github.example.com"), None);
+        assert_eq!(kind_of("git.example.com"), None);
+        assert_eq!(kind_of("mygitlab.example.com"), None);
+    }
+}
@@ -0,0 +1,111 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use super::PullRequestStatus;
+use crate::git::Oid;
+
+const STATUS_CACHE_FILE: &str = ".ubr/FORGE_STATUS";
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct CachedStatus {
+    /// The remote commit of the branch when the status was read
+    remote_commit: Oid,
+    /// When the status was read, in seconds since the Unix epoch
+    read_at: u64,
+    status: PullRequestStatus,
+}
+
+///
+/// The statuses of pull requests last read from the forge, by pull request number.
+///
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub(crate) struct StatusCache {
+    statuses: HashMap<u64, CachedStatus>,
+}
+
+impl StatusCache {
+    fn path<P>(repo_path: P) -> PathBuf
+    where
+        P: AsRef<Path>,
+    {
+        repo_path.as_ref().join(STATUS_CACHE_FILE)
+    }
+
+    ///
+    /// The cache of the repository at `repo_path`. A cache that can't be read is only reported,
+    /// as the statuses can always be read again.
+    ///
+    pub(crate) fn load<P>(repo_path: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        match std::fs::File::open(StatusCache::path(repo_path)) {
+            Ok(file) => serde_json::from_reader(file).unwrap_or_else(|e| {
+                warn!("Ignoring the cached pull request statuses: {}", e);
+                StatusCache::default()
+            }),
+            Err(_) => StatusCache::default(),
+        }
+    }
+
+    pub(crate) fn save<P>(&self, repo_path: P) -> anyhow::Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        let path = StatusCache::path(repo_path);
+        let tmp_path = path.with_extension("tmp");
+        std::fs::create_dir_all(path.parent().expect("Cache is always in a directory"))?;
+        serde_json::to_writer(std::fs::File::create(&tmp_path)?, self)?;
+        std::fs::rename(&tmp_path, &path).context("Writing pull request status cache")
+    }
+
+    ///
+    /// The status of pull request `number` if it was read for `remote_commit` less than `ttl`
+    /// seconds before `now`.
+    ///
+    pub(crate) fn get(
+        &self,
+        number: u64,
+        remote_commit: git2::Oid,
+        now: u64,
+        ttl: u64,
+    ) -> Option<PullRequestStatus> {
+        self.statuses
+            .get(&number)
+            .filter(|cached| {
+                git2::Oid::from(cached.remote_commit) == remote_commit
+                    && now < cached.read_at.saturating_add(ttl)
+            })
+            .map(|cached| cached.status)
+    }
+
+    pub(crate) fn insert(
+        &mut self,
+        number: u64,
+        remote_commit: git2::Oid,
+        read_at: u64,
+        status: PullRequestStatus,
+    ) {
+        self.statuses.insert(
+            number,
+            CachedStatus {
+                remote_commit: remote_commit.into(),
+                read_at,
+                status,
+            },
+        );
+    }
+
+    pub(crate) fn retain<F>(&mut self, keep: F)
+    where
+        F: Fn(u64) -> bool,
+    {
+        self.statuses.retain(|number, _| keep(*number));
+    }
+}
@@ -0,0 +1,282 @@
+use anyhow::Context;
+use git2::Config;
+use regex::Regex;
+
+pub const DEFAULT_TEMPLATE: &str = "{slug}";
+pub const DEFAULT_TICKET_PATTERN: &str = "[A-Z][A-Z0-9]+-[0-9]+";
+pub const DEFAULT_MAX_LENGTH: usize = 64;
+
+///
+/// How the remote branches of new virtual branches are named.
+///
+/// The template may contain `{slug}`, the commit title in lower case with every run of other
+/// characters than ASCII letters, digits and `_` replaced by a single `-`, `{user}` and
+/// `{ticket}`, the first match of the ticket pattern in the title. The slug is shortened to keep
+/// the name within the maximum length. If even the rest of the template doesn't fit, the
+/// short commit id is used instead.
+///
+pub struct BranchNameTemplate {
+    template: String,
+    ticket_pattern: Regex,
+    max_length: usize,
+    user: String,
+}
+
+impl BranchNameTemplate {
+    pub fn new(
+        template: &str,
+        ticket_pattern: &str,
+        max_length: usize,
+        user: &str,
+    ) -> anyhow::Result<Self> {
+        Ok(BranchNameTemplate {
+            template: template.to_string(),
+            ticket_pattern: Regex::new(ticket_pattern)
+                .with_context(|| format!("Invalid ticket pattern '{}'", ticket_pattern))?,
+            max_length,
+            user: user.to_string(),
+        })
+    }
+
+    ///
+    /// The template configured with `ubr.branchTemplate`, `ubr.ticketPattern` and
+    /// `ubr.branchMaxLength`. `{user}` is `$USER`, or else `user.name`.
+    ///
+    pub fn from_config(config: &Config) -> anyhow::Result<Self> {
+        let template = config
+            .get_string("ubr.branchTemplate")
+            .unwrap_or_else(|_| DEFAULT_TEMPLATE.to_string());
+        let ticket_pattern = config
+            .get_string("ubr.ticketPattern")
+            .unwrap_or_else(|_| DEFAULT_TICKET_PATTERN.to_string());
+        let max_length = config
+            .get_i64("ubr.branchMaxLength")
+            .ok()
+            .and_then(|length| usize::try_from(length).ok())
+            .unwrap_or(DEFAULT_MAX_LENGTH);
+        let user = std::env::var("USER")
+            .ok()
+            .or_else(|| {
+                config
+                    .get_string("user.name")
+                    .ok()
+                    .map(|name| slugify(&name))
+            })
+            .unwrap_or_else(|| "user".to_string());
+        BranchNameTemplate::new(&template, &ticket_pattern, max_length, &user)
+    }
+
+    ///
+    /// The branch name for a commit titled `title`. `fallback`, e.g. the short commit id, is
+    /// the slug if the title has nothing to make one from.
+    ///
+    pub fn render(&self, title: &str, fallback: &str) -> String {
+        self.render_with_suffix(title, fallback, "")
+    }
+
+    ///
+    /// Like [`BranchNameTemplate::render`], with `suffix` appended. The slug is shortened to
+    /// keep room for the suffix within the maximum length, `fallback` is used if nothing of the
+    /// template is left.
+    ///
+    pub fn render_with_suffix(&self, title: &str, fallback: &str, suffix: &str) -> String {
+        let ticket = if self.template.contains("{ticket}") {
+            self.ticket_pattern.find(title)
+        } else {
+            None
+        };
+        // The ticket has a place of its own, so leave it out of the slug
+        let title = match ticket {
+            Some(ticket) => format!("{} {}", &title[..ticket.start()], &title[ticket.end()..]),
+            None => title.to_string(),
+        };
+        let ticket = ticket.map(|ticket| ticket.as_str()).unwrap_or_default();
+        let render = |slug: &str| {
+            let name = normalize(
+                &self
+                    .template
+                    .replace("{user}", &self.user)
+                    .replace("{ticket}", ticket)
+                    .replace("{slug}", slug),
+            );
+            format!("{}{}", name, suffix)
+        };
+
+        let slug = match slugify(&title) {
+            slug if slug.is_empty() => slugify(fallback),
+            slug => slug,
+        };
+        let name = render(&slug);
+        let name = if name.len() <= self.max_length {
+            name
+        } else {
+            render(shorten(
+                &slug,
+                slug.len().saturating_sub(name.len() - self.max_length),
+            ))
+        };
+        // The suffix alone, or fixed parts of the template that are too long to fit
+        if name.len() > self.max_length || name.starts_with('-') || name == suffix {
+            return format!("{}{}", slugify(fallback), suffix);
+        }
+        name
+    }
+}
+
+///
+/// `value` in lower case with every run of other characters than ASCII letters, digits and `_`
+/// replaced by a single `-`. Letters with diacritics are dropped, e.g. "Ümlaut" becomes "mlaut".
+///
+pub fn slugify(value: &str) -> String {
+    value
+        .to_lowercase()
+        .split(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+///
+/// The longest prefix of `slug` of at most `length` bytes, cut between two words if possible.
+///
+fn shorten(slug: &str, length: usize) -> &str {
+    if slug.len() <= length {
+        return slug;
+    }
+    let prefix = &slug[..length];
+    match prefix.rfind('-') {
+        Some(end) if slug.as_bytes()[length] != b'-' => &prefix[..end],
+        _ => prefix.trim_end_matches('-'),
+    }
+}
+
+///
+/// `name` turned into a valid branch name: characters git doesn't allow are replaced by `-`,
+/// runs of `-` are collapsed and empty path components are removed.
+///
+fn normalize(name: &str) -> String {
+    name.split('/')
+        .map(|component| {
+            let component: String = component
+                .chars()
+                .map(|c| {
+                    if c.is_ascii_control() || " ~^:?*[\\@{}".contains(c) {
+                        '-'
+                    } else {
+                        c
+                    }
+                })
+                .collect();
+            let component = component
+                .replace("..", "-")
+                .split('-')
+                .filter(|part| !part.is_empty())
+                .collect::<Vec<_>>()
+                .join("-");
+            let component = component.trim_matches('.');
+            component
+                .strip_suffix(".lock")
+                .unwrap_or(component)
+                .to_string()
+        })
+        .filter(|component| !component.is_empty())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+
+    use super::{BranchNameTemplate, DEFAULT_MAX_LENGTH, DEFAULT_TEMPLATE, DEFAULT_TICKET_PATTERN};
+
+    fn new_template(template: &str, max_length: usize) -> BranchNameTemplate {
+        BranchNameTemplate::new(template, DEFAULT_TICKET_PATTERN, max_length, "jane").unwrap()
+    }
+
+    #[test]
+    fn test_default_template() {
+        let template = new_template(DEFAULT_TEMPLATE, DEFAULT_MAX_LENGTH);
+        assert_eq!(template.render("feature 1", "1234567"), "feature-1");
+        assert_eq!(
+            template.render("Fix: the  parser -- (again)!", "1234567"),
+            "fix-the-parser-again"
+        );
+    }
+
+    #[test]
+    fn test_user_and_ticket() {
+        let template = new_template("{user}/{ticket}-{slug}", DEFAULT_MAX_LENGTH);
+        assert_eq!(
+            template.render("PROJ-123: Add the parser", "1234567"),
+            "jane/PROJ-123-add-the-parser"
+        );
+        assert_eq!(
+            template.render("Add the parser", "1234567"),
+            "jane/add-the-parser"
+        );
+    }
+
+    #[test]
+    fn test_ticket_is_kept_in_slug_without_placeholder() {
+        let template = new_template("{user}/{slug}", DEFAULT_MAX_LENGTH);
+        assert_eq!(
+            template.render("PROJ-123: Add the parser", "1234567"),
+            "jane/proj-123-add-the-parser"
+        );
+    }
+
+    #[test]
+    fn test_length_limit() {
+        let template = new_template("{user}/{slug}", 20);
+        assert_eq!(
+            template.render("Add a parser for the configuration", "1234567"),
+            "jane/add-a-parser"
+        );
+        let template = new_template("{slug}", 10);
+        assert_eq!(
+            template.render("Internationalization", "1234567"),
+            "internatio"
+        );
+    }
+
+    #[test]
+    fn test_suffix_fits_length_limit() {
+        let template = new_template("{user}/{slug}", 20);
+        assert_eq!(
+            template.render_with_suffix("Add a parser for the configuration", "1234567", "-2"),
+            "jane/add-a-parser-2"
+        );
+        let template = new_template("{slug}", 10);
+        assert_eq!(
+            template.render_with_suffix("Internationalization", "1234567", "-12"),
+            "interna-12"
+        );
+    }
+
+    #[test]
+    fn test_short_id_when_nothing_of_the_template_fits() {
+        let template = new_template("{slug}", 2);
+        assert_eq!(
+            template.render_with_suffix("Internationalization", "1234567", "-2"),
+            "1234567-2"
+        );
+        let template = new_template("{user}/{ticket}-{slug}", 10);
+        assert_eq!(
+            template.render("PROJ-12345: Add the parser", "1234567"),
+            "1234567"
+        );
+        assert_eq!(
+            template.render_with_suffix("PROJ-12345: Add the parser", "1234567", "-2"),
+            "1234567-2"
+        );
+    }
+
+    #[test]
+    fn test_invalid_characters() {
+        let template = new_template("feature/{user}/{slug}", DEFAULT_MAX_LENGTH);
+        assert_eq!(template.render("...", "1234567"), "feature/jane/1234567");
+        let template = BranchNameTemplate::new("{user}/{slug}", "x", 64, "j.doe.lock").unwrap();
+        assert_eq!(template.render("Ümlaut", "1234567"), "j.doe/mlaut");
+    }
+}
@@ -0,0 +1,180 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use git2::Repository;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use super::Oid;
+
+// Both in the git directory, so that they never show up in the work tree
+const JOURNAL_FILE: &str = "UBR_SYNC_JOURNAL";
+const LOCK_FILE: &str = "UBR_SYNC_LOCK";
+
+///
+/// Held by the process that runs a sync for as long as it runs. The lock is released by the
+/// OS when that process exits, so a journal nobody holds the lock for was left behind by a
+/// sync that was interrupted.
+///
+pub(crate) struct SyncLock {
+    _file: std::fs::File,
+}
+
+impl SyncLock {
+    ///
+    /// Take the lock of `repo`, or `None` if another process has it.
+    ///
+    pub(crate) fn try_acquire(repo: &Repository) -> anyhow::Result<Option<Self>> {
+        let path = repo.path().join(LOCK_FILE);
+        // The file is never removed, as another process may be waiting on it
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&path)
+            .context("Opening sync lock")?;
+        match file.try_lock() {
+            Ok(()) => Ok(Some(SyncLock { _file: file })),
+            Err(std::fs::TryLockError::WouldBlock) => Ok(None),
+            Err(std::fs::TryLockError::Error(e)) => Err(e).context("Locking sync journal"),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Eq, PartialEq, Debug, Clone)]
+pub enum JournalEntry {
+    Note {
+        commit: Oid,
+        old: Option<String>,
+        new: Option<String>,
+    },
+    Push {
+        branch: String,
+        old: Option<Oid>,
+        new: Option<Oid>,
+    },
+    MainBranch {
+        old: Oid,
+        new: Oid,
+    },
+}
+
+///
+/// Every ref and note touched by an ongoing sync. It is written to disk after each change
+/// so that an interrupted sync can always be rolled back to where it started.
+///
+#[derive(Serialize, Deserialize, Eq, PartialEq, Debug, Clone)]
+pub struct Journal {
+    pub main_branch_name: String,
+    pub main_branch_head: Oid,
+    pub notes_ref: String,
+    pub notes_head: Option<Oid>,
+    pub entries: Vec<JournalEntry>,
+}
+
+impl Journal {
+    ///
+    /// Where the journal of the repository with the git directory `git_dir` is kept.
+    ///
+    pub fn path<P>(git_dir: P) -> PathBuf
+    where
+        P: AsRef<Path>,
+    {
+        git_dir.as_ref().join(JOURNAL_FILE)
+    }
+
+    pub(crate) fn begin(
+        repo: &Repository,
+        main_branch_name: &str,
+        notes_ref: &str,
+    ) -> anyhow::Result<Self> {
+        let main_branch_head = repo
+            .refname_to_id(&format!("refs/heads/{}", main_branch_name))
+            .context("Find head of main branch")?;
+        let notes_head = repo.refname_to_id(notes_ref).ok().map(Oid::from);
+        Ok(Journal {
+            main_branch_name: main_branch_name.to_string(),
+            main_branch_head: main_branch_head.into(),
+            notes_ref: notes_ref.to_string(),
+            notes_head,
+            entries: Vec::new(),
+        })
+    }
+
+    pub fn load<P>(git_dir: P) -> anyhow::Result<Option<Self>>
+    where
+        P: AsRef<Path>,
+    {
+        match std::fs::File::open(Journal::path(git_dir)) {
+            Ok(file) => Ok(Some(
+                serde_json::from_reader(file).context("Reading sync journal")?,
+            )),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e).context("Opening sync journal"),
+        }
+    }
+
+    pub(crate) fn save<P>(&self, git_dir: P) -> anyhow::Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        let path = Journal::path(git_dir);
+        let tmp_path = path.with_extension("tmp");
+        serde_json::to_writer(std::fs::File::create(&tmp_path)?, self)?;
+        std::fs::rename(&tmp_path, &path).context("Writing sync journal")
+    }
+
+    pub(crate) fn remove<P>(git_dir: P) -> anyhow::Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        std::fs::remove_file(Journal::path(git_dir)).context("Removing sync journal")
+    }
+
+    ///
+    /// Restore the notes and the main branch to where they were when the sync started.
+    /// Remote branches that were already pushed can not be restored and are only reported.
+    ///
+    pub(crate) fn rollback(&self, repo: &Repository) -> anyhow::Result<()> {
+        match self.notes_head {
+            Some(notes_head) => {
+                repo.reference(
+                    &self.notes_ref,
+                    notes_head.into(),
+                    true,
+                    "ubr: roll back sync",
+                )
+                .context("Restoring notes")?;
+            }
+            None => {
+                if let Ok(mut reference) = repo.find_reference(&self.notes_ref) {
+                    reference.delete().context("Restoring notes")?;
+                }
+            }
+        }
+
+        let main_branch_ref = format!("refs/heads/{}", self.main_branch_name);
+        let current_head = repo.refname_to_id(&main_branch_ref)?;
+        if current_head != self.main_branch_head.into() || repo.head_detached()? {
+            let old_head = repo.find_commit(self.main_branch_head.into())?;
+            repo.checkout_tree(old_head.as_object(), None)
+                .context("Checking out the main branch")?;
+            repo.set_head_detached(old_head.id())
+                .context("Detach HEAD before moving the main branch")?;
+            repo.branch(&self.main_branch_name, &old_head, true)
+                .context("Moving the main branch pointer")?;
+            repo.set_head(&main_branch_ref)
+                .context("Moving HEAD back to main branch")?;
+        }
+
+        for entry in &self.entries {
+            if let JournalEntry::Push { branch, .. } = entry {
+                warn!(
+                    "Branch {} was already pushed and is left as is on the remote",
+                    branch
+                );
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,378 @@
+use std::{borrow::Cow, error::Error, fmt::Display, str::FromStr};
+
+use git2::Oid;
+
+///
+/// The version of the note format written by this version of ubr.
+///
+/// Version 1 notes have no `ubr-version` line and are read as is; they are written back in the
+/// current format the next time the note is saved. Notes from newer versions keep their version
+/// when written back.
+///
+pub const META_DATA_VERSION: u32 = 2;
+
+const VERSION_KEY: &str = "ubr-version";
+const REMOTE_BRANCH_KEY: &str = "remote-branch";
+const REMOTE_COMMIT_KEY: &str = "remote-commit";
+const PARENT_BRANCH_KEY: &str = "parent-branch";
+const GROUP_BRANCH_KEY: &str = "group-branch";
+const PULL_REQUEST_KEY: &str = "pull-request";
+
+///
+/// The metadata of a virtual branch, stored in a note on its last commit as `key: value` lines
+/// that start with a `ubr-version` line.
+///
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct CommitMetadata<'a> {
+    /// The `ubr-version` the note was read with, [`META_DATA_VERSION`] for a new note.
+    pub version: u32,
+    pub remote_branch_name: Cow<'a, str>,
+    pub remote_commit: Oid,
+    /// The remote branch of the tracked commit this branch is stacked on, if any.
+    pub parent_branch: Option<Cow<'a, str>>,
+    /// The number of the pull request opened for this branch on the forge, if any.
+    pub pull_request: Option<u64>,
+    /// Keys this version of ubr doesn't know about, written back as they were read.
+    pub extra: Vec<(String, String)>,
+}
+
+impl CommitMetadata<'_> {
+    pub fn update_commit(mut self, oid: Oid) -> Self {
+        self.remote_commit = oid;
+        self
+    }
+}
+
+impl Display for CommitMetadata<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_fmt(format_args!(
+            "{}: {}\n",
+            VERSION_KEY,
+            self.version.max(META_DATA_VERSION)
+        ))?;
+        f.write_fmt(format_args!(
+            "{}: {}\n",
+            REMOTE_BRANCH_KEY, self.remote_branch_name
+        ))?;
+        f.write_fmt(format_args!(
+            "{}: {}\n",
+            REMOTE_COMMIT_KEY, self.remote_commit
+        ))?;
+        if let Some(parent_branch) = &self.parent_branch {
+            f.write_fmt(format_args!("{}: {}\n", PARENT_BRANCH_KEY, parent_branch))?;
+        }
+        if let Some(pull_request) = self.pull_request {
+            f.write_fmt(format_args!("{}: {}\n", PULL_REQUEST_KEY, pull_request))?;
+        }
+        for (key, value) in &self.extra {
+            f.write_fmt(format_args!("{}: {}\n", key, value))?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum MetaDataError {
+    /// The note wasn't written by ubr.
+    NotMetaData,
+    /// A line isn't a `key: value` pair.
+    MalformedLine(String),
+    /// The `ubr-version` isn't a number.
+    InvalidVersion(String),
+    /// A key that every version of the format has is missing.
+    MissingKey(&'static str),
+    /// The `remote-commit` isn't an object id.
+    InvalidRemoteCommit(String),
+    /// The `pull-request` isn't a number.
+    InvalidPullRequest(String),
+}
+
+impl Display for MetaDataError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MetaDataError::NotMetaData => f.write_str("Not ubr metadata"),
+            MetaDataError::MalformedLine(line) => write!(f, "Malformed line '{}'", line),
+            MetaDataError::InvalidVersion(version) => {
+                write!(f, "Invalid {} '{}'", VERSION_KEY, version)
+            }
+            MetaDataError::MissingKey(key) => write!(f, "Missing {}", key),
+            MetaDataError::InvalidRemoteCommit(commit) => {
+                write!(f, "Invalid {} '{}'", REMOTE_COMMIT_KEY, commit)
+            }
+            MetaDataError::InvalidPullRequest(number) => {
+                write!(f, "Invalid {} '{}'", PULL_REQUEST_KEY, number)
+            }
+        }
+    }
+}
+
+impl Error for MetaDataError {}
+
+impl FromStr for CommitMetadata<'static> {
+    type Err = MetaDataError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let meta_data = CommitMetadata::try_from(value)?;
+        Ok(CommitMetadata {
+            version: meta_data.version,
+            remote_branch_name: Cow::Owned(meta_data.remote_branch_name.into_owned()),
+            remote_commit: meta_data.remote_commit,
+            parent_branch: meta_data.parent_branch.map(|p| Cow::Owned(p.into_owned())),
+            pull_request: meta_data.pull_request,
+            extra: meta_data.extra,
+        })
+    }
+}
+
+impl<'a> TryFrom<&'a str> for CommitMetadata<'a> {
+    type Error = MetaDataError;
+
+    fn try_from(value: &'a str) -> Result<Self, Self::Error> {
+        let is_meta_data = value
+            .lines()
+            .filter_map(|line| line.split_once(':'))
+            .any(|(key, _)| {
+                [VERSION_KEY, REMOTE_BRANCH_KEY, REMOTE_COMMIT_KEY].contains(&key.trim())
+            });
+        if !is_meta_data || parse_group_member_note(value).is_some() {
+            return Err(MetaDataError::NotMetaData);
+        }
+        let entries = parse_entries(value)?;
+        let mut version = 1;
+        let mut remote_branch_name = None;
+        let mut remote_commit = None;
+        let mut parent_branch = None;
+        let mut pull_request = None;
+        let mut extra = Vec::new();
+        for (key, value) in entries {
+            match key {
+                VERSION_KEY => {
+                    // Newer versions only add keys, which are kept in `extra`
+                    version = value
+                        .parse::<u32>()
+                        .map_err(|_| MetaDataError::InvalidVersion(value.to_string()))?;
+                }
+                REMOTE_BRANCH_KEY => remote_branch_name = Some(value),
+                REMOTE_COMMIT_KEY => {
+                    remote_commit = Some(
+                        value
+                            .parse::<Oid>()
+                            .map_err(|_| MetaDataError::InvalidRemoteCommit(value.to_string()))?,
+                    )
+                }
+                PARENT_BRANCH_KEY => parent_branch = Some(value),
+                PULL_REQUEST_KEY => {
+                    pull_request = Some(
+                        value
+                            .parse::<u64>()
+                            .map_err(|_| MetaDataError::InvalidPullRequest(value.to_string()))?,
+                    )
+                }
+                _ => extra.push((key.to_string(), value.to_string())),
+            }
+        }
+        Ok(CommitMetadata {
+            version,
+            remote_branch_name: Cow::Borrowed(
+                remote_branch_name.ok_or(MetaDataError::MissingKey(REMOTE_BRANCH_KEY))?,
+            ),
+            remote_commit: remote_commit.ok_or(MetaDataError::MissingKey(REMOTE_COMMIT_KEY))?,
+            parent_branch: parent_branch.map(Cow::Borrowed),
+            pull_request,
+            extra,
+        })
+    }
+}
+
+///
+/// The `key: value` pairs of a note, skipping blank lines.
+///
+fn parse_entries(note: &str) -> Result<Vec<(&str, &str)>, MetaDataError> {
+    note.lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            line.split_once(':')
+                .map(|(key, value)| (key.trim(), value.trim()))
+                .filter(|(key, _)| !key.is_empty())
+                .ok_or_else(|| MetaDataError::MalformedLine(line.to_string()))
+        })
+        .collect()
+}
+
+///
+/// Note on every commit but the last of a virtual branch that spans a range of commits. The last
+/// commit holds the `CommitMetadata` of the branch.
+///
+pub(crate) fn group_member_note(remote_branch_name: &str) -> String {
+    format!(
+        "{}: {}\n{}: {}\n",
+        VERSION_KEY, META_DATA_VERSION, GROUP_BRANCH_KEY, remote_branch_name
+    )
+}
+
+pub(crate) fn parse_group_member_note(note: &str) -> Option<&str> {
+    note.lines()
+        .find_map(|line| line.strip_prefix("group-branch:"))
+        .map(str::trim)
+}
+
+#[cfg(test)]
+mod test {
+    use std::borrow::Cow;
+
+    use indoc::indoc;
+
+    use super::{group_member_note, CommitMetadata, MetaDataError};
+
+    #[test]
+    fn test_parse() {
+        let commit_msg = indoc! {"
+            remote-branch: branch_name
+        "};
+
+        let meta_data = TryInto::<CommitMetadata>::try_into(commit_msg);
+        assert_eq!(meta_data, Err(MetaDataError::MissingKey("remote-commit")))
+    }
+
+    #[test]
+    fn test_parse_where_there_is_no_meta() {
+        let commit_msg = indoc! {"
+            other text
+        "};
+
+        let meta_data = TryInto::<CommitMetadata>::try_into(commit_msg);
+        assert_eq!(meta_data, Err(MetaDataError::NotMetaData))
+    }
+
+    #[test]
+    fn test_parse_with_remote_commit() {
+        let msg = indoc! {"
+            remote-branch: branch_name
+            remote-commit: 6ec67b364e67bbd74c66fc8f0cbb95e6ac155d84
+        "};
+        let meta_data = TryInto::<CommitMetadata>::try_into(msg).unwrap();
+        assert_eq!(
+            meta_data,
+            CommitMetadata {
+                version: 1,
+                remote_branch_name: Cow::Borrowed("branch_name"),
+                remote_commit: "6ec67b364e67bbd74c66fc8f0cbb95e6ac155d84".parse().unwrap(),
+                parent_branch: None,
+                pull_request: None,
+                extra: Vec::new(),
+            }
+        )
+    }
+
+    #[test]
+    fn test_parse_with_parent_branch() {
+        let msg = indoc! {"
+            ubr-version: 2
+            remote-branch: branch_name
+            remote-commit: 6ec67b364e67bbd74c66fc8f0cbb95e6ac155d84
+            parent-branch: parent_name
+        "};
+        let meta_data = msg.parse::<CommitMetadata>().unwrap();
+        assert_eq!(meta_data.parent_branch, Some(Cow::Borrowed("parent_name")));
+        assert_eq!(format!("{}", meta_data), msg);
+    }
+
+    #[test]
+    fn test_parse_with_pull_request() {
+        let msg = indoc! {"
+            ubr-version: 2
+            remote-branch: branch_name
+            remote-commit: 6ec67b364e67bbd74c66fc8f0cbb95e6ac155d84
+            pull-request: 42
+        "};
+        let meta_data = msg.parse::<CommitMetadata>().unwrap();
+        assert_eq!(meta_data.pull_request, Some(42));
+        assert_eq!(format!("{}", meta_data), msg);
+
+        let meta_data = msg.replace("42", "#42").parse::<CommitMetadata>();
+        assert_eq!(
+            meta_data,
+            Err(MetaDataError::InvalidPullRequest("#42".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_parse_with_invalid_remote_commit() {
+        let msg = indoc! {"
+            remote-branch: branch_name
+            remote-commit: Invrlid
+        "};
+        let meta_data = TryInto::<CommitMetadata>::try_into(msg);
+        assert_eq!(
+            meta_data,
+            Err(MetaDataError::InvalidRemoteCommit("Invrlid".to_string()))
+        )
+    }
+
+    #[test]
+    fn test_migrate_version_1() {
+        let msg = indoc! {"
+            remote-branch: branch_name
+            remote-commit: 6ec67b364e67bbd74c66fc8f0cbb95e6ac155d84
+        "};
+        let meta_data = msg.parse::<CommitMetadata>().unwrap();
+        assert_eq!(
+            format!("{}", meta_data),
+            indoc! {"
+                ubr-version: 2
+                remote-branch: branch_name
+                remote-commit: 6ec67b364e67bbd74c66fc8f0cbb95e6ac155d84
+            "}
+        );
+    }
+
+    #[test]
+    fn test_unknown_keys_are_preserved() {
+        let msg = indoc! {"
+            ubr-version: 3
+            remote-branch: branch_name
+            pr-url: https://example.com/pr/1
+            remote-commit: 6ec67b364e67bbd74c66fc8f0cbb95e6ac155d84
+            created: 2024-01-01T00:00:00Z
+        "};
+        let meta_data = msg.parse::<CommitMetadata>().unwrap();
+        assert_eq!(
+            meta_data.extra,
+            vec![
+                ("pr-url".to_string(), "https://example.com/pr/1".to_string()),
+                ("created".to_string(), "2024-01-01T00:00:00Z".to_string()),
+            ]
+        );
+        assert_eq!(
+            format!("{}", meta_data),
+            indoc! {"
+                ubr-version: 3
+                remote-branch: branch_name
+                remote-commit: 6ec67b364e67bbd74c66fc8f0cbb95e6ac155d84
+                pr-url: https://example.com/pr/1
+                created: 2024-01-01T00:00:00Z
+            "}
+        );
+    }
+
+    #[test]
+    fn test_parse_malformed_line() {
+        let msg = indoc! {"
+            ubr-version: 2
+            remote-branch: branch_name
+            garbage
+        "};
+        let meta_data = TryInto::<CommitMetadata>::try_into(msg);
+        assert_eq!(
+            meta_data,
+            Err(MetaDataError::MalformedLine("garbage".to_string()))
+        )
+    }
+
+    #[test]
+    fn test_group_member_note_is_not_meta_data() {
+        let note = group_member_note("branch_name");
+        let meta_data = CommitMetadata::try_from(note.as_str());
+        assert_eq!(meta_data, Err(MetaDataError::NotMetaData));
+    }
+}
@@ -1,7 +1,4 @@
-use std::{borrow::Cow, error::Error, fmt::Display, str::FromStr};
-
-use git2::{Commit, Oid, Repository};
-use itertools::Itertools;
+use git2::{Commit, Index, MergeOptions, Oid, Repository};
 
 use super::GitRepo;
 
@@ -9,9 +6,12 @@ use super::GitRepo;
 mod tests;
 
 mod tracked_commit;
-pub use tracked_commit::TrackedCommit;
+pub use tracked_commit::{RemoteState, TrackedCommit};
 mod untracked_commit;
 pub use untracked_commit::UnTrackedCommit;
+mod meta_data;
+pub(crate) use meta_data::{group_member_note, parse_group_member_note};
+pub use meta_data::{CommitMetadata, MetaDataError, META_DATA_VERSION};
 
 #[derive(Debug)]
 pub enum MainCommit<'repo> {
@@ -24,8 +24,8 @@ impl<'repo> MainCommit<'repo> {
         git_repo: &'repo GitRepo,
         repo: &'repo Repository,
         commit: Commit<'repo>,
-    ) -> Result<MainCommit<'repo>, git2::Error> {
-        let res = repo.find_note(None, commit.id());
+    ) -> crate::Result<MainCommit<'repo>> {
+        let res = repo.find_note(Some(git_repo.notes_ref()), commit.id());
         if let Err(error) = res {
             match error.code() {
                 git2::ErrorCode::NotFound => {
@@ -33,21 +33,30 @@ impl<'repo> MainCommit<'repo> {
                         repo, git_repo, commit,
                     )))
                 }
-                _ => return Err(error),
+                _ => return Err(error.into()),
             }
         }
         let note = res.expect("Already checked for error above");
-        if let Some(meta_data) = note
+        match note
             .message()
-            .and_then(|m| m.parse::<CommitMetadata>().ok())
+            .ok_or(MetaDataError::NotMetaData)
+            .and_then(|m| m.parse::<CommitMetadata>())
         {
-            Ok(MainCommit::Tracked(TrackedCommit::new(
+            Ok(meta_data) => Ok(MainCommit::Tracked(TrackedCommit::new(
                 repo, git_repo, commit, meta_data,
-            )))
-        } else {
-            Ok(MainCommit::UnTracked(UnTrackedCommit::new(
-                repo, git_repo, commit,
-            )))
+            ))),
+            Err(error) => {
+                if error != MetaDataError::NotMetaData {
+                    tracing::warn!(
+                        "Ignoring the corrupt ubr note of commit {}: {}",
+                        commit.id(),
+                        error
+                    );
+                }
+                Ok(MainCommit::UnTracked(UnTrackedCommit::new(
+                    repo, git_repo, commit,
+                )))
+            }
         }
     }
 
@@ -58,6 +67,16 @@ impl<'repo> MainCommit<'repo> {
         }
     }
 
+    ///
+    /// Cherry-pick this commit onto `parent_commit`, keeping its note.
+    ///
+    pub(crate) fn rebase(self, parent_commit: &Commit<'_>) -> anyhow::Result<Commit<'repo>> {
+        match self {
+            MainCommit::UnTracked(c) => Ok(c.rebase(parent_commit)?.commit()),
+            MainCommit::Tracked(c) => c.rebase(parent_commit),
+        }
+    }
+
     pub fn message(&self) -> Option<&str> {
         match self {
             MainCommit::UnTracked(c) => c.as_commit().message(),
@@ -66,144 +85,45 @@ impl<'repo> MainCommit<'repo> {
     }
 }
 
-#[derive(Debug, Eq, PartialEq, Clone)]
-pub struct CommitMetadata<'a> {
-    pub remote_branch_name: Cow<'a, str>,
-    pub remote_commit: Oid,
-}
-
-impl CommitMetadata<'_> {
-    pub fn update_commit(mut self, oid: Oid) -> Self {
-        self.remote_commit = oid;
-        self
+///
+/// The commit just before the first commit of the virtual branch that has `head` as its last
+/// commit. For a virtual branch of a single commit this is the parent of `head`.
+///
+pub(crate) fn group_base<'repo>(
+    git_repo: &'repo GitRepo,
+    head: &Commit<'repo>,
+    remote_branch_name: &str,
+) -> anyhow::Result<Commit<'repo>> {
+    let mut base = head.parent(0)?;
+    while git_repo.group_branch(base.id()).as_deref() == Some(remote_branch_name) {
+        base = base.parent(0)?;
     }
+    Ok(base)
 }
 
-impl Display for CommitMetadata<'_> {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.write_fmt(format_args!("remote-branch: {}\n", self.remote_branch_name))?;
-        f.write_fmt(format_args!("remote-commit: {}\n", self.remote_commit))?;
-        Ok(())
-    }
+///
+/// Cherry-pick the changes from `base` to `head` onto `onto`.
+///
+pub(crate) fn cherry_pick_range(
+    repo: &Repository,
+    base: &Commit,
+    head: &Commit,
+    onto: &Commit,
+    opts: Option<&MergeOptions>,
+) -> Result<Index, git2::Error> {
+    repo.merge_trees(&base.tree()?, &onto.tree()?, &head.tree()?, opts)
 }
 
-#[derive(Debug)]
-pub struct MetaDataError;
-
-impl Display for MetaDataError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.write_str("")
-    }
-}
-
-impl Error for MetaDataError {}
-
-impl FromStr for CommitMetadata<'static> {
-    type Err = MetaDataError;
-
-    fn from_str(value: &str) -> Result<Self, Self::Err> {
-        let mut remote_branch_name = None;
-        let mut remote_commit_id = None;
-        for line in value.lines() {
-            if let Some((key, value)) = line.splitn(2, ':').collect_tuple() {
-                if key == "remote-branch" {
-                    remote_branch_name = Some(value.trim());
-                } else if key == "remote-commit" {
-                    remote_commit_id = value.trim().parse::<Oid>().ok();
-                }
-            }
-        }
-        if let (Some(branch), Some(commit)) = (remote_branch_name, remote_commit_id) {
-            Ok(CommitMetadata {
-                remote_branch_name: Cow::Owned(branch.to_string()),
-                remote_commit: commit,
-            })
-        } else {
-            Err(MetaDataError)
+///
+/// The paths of all files with conflicts in `index`.
+///
+pub(crate) fn conflicting_files(index: &Index) -> Result<Vec<String>, git2::Error> {
+    let mut files = Vec::new();
+    for conflict in index.conflicts()? {
+        let conflict = conflict?;
+        if let Some(entry) = conflict.our.or(conflict.their).or(conflict.ancestor) {
+            files.push(String::from_utf8_lossy(&entry.path).into_owned());
         }
     }
-}
-
-impl<'a> TryFrom<&'a str> for CommitMetadata<'a> {
-    type Error = MetaDataError;
-
-    //Implement this using parse..
-    fn try_from(value: &'a str) -> Result<Self, Self::Error> {
-        let mut remote_branch_name = None;
-        let mut remote_commit_id = None;
-        for line in value.lines() {
-            if let Some((key, value)) = line.splitn(2, ':').collect_tuple() {
-                if key == "remote-branch" {
-                    remote_branch_name = Some(value.trim());
-                } else if key == "remote-commit" {
-                    remote_commit_id = value.trim().parse::<Oid>().ok();
-                }
-            }
-        }
-        if let (Some(remote_branch_name), Some(remote_commit)) =
-            (remote_branch_name, remote_commit_id)
-        {
-            Ok(CommitMetadata {
-                remote_branch_name: Cow::Owned(remote_branch_name.to_string()),
-                remote_commit,
-            })
-        } else {
-            Err(MetaDataError)
-        }
-    }
-}
-
-#[cfg(test)]
-mod test {
-    use std::borrow::Cow;
-
-    use indoc::indoc;
-
-    use super::CommitMetadata;
-
-    #[test]
-    fn test_parse() {
-        let commit_msg = indoc! {"
-            remote-branch: branch_name
-        "};
-
-        let meta_data = TryInto::<CommitMetadata>::try_into(commit_msg);
-        assert!(meta_data.is_err(),)
-    }
-
-    #[test]
-    fn test_parse_where_there_is_no_meta() {
-        let commit_msg = indoc! {"
-            other text
-        "};
-
-        let meta_data = TryInto::<CommitMetadata>::try_into(commit_msg);
-        assert!(meta_data.is_err())
-    }
-
-    #[test]
-    fn test_parse_with_remote_commit() {
-        let msg = indoc! {"
-            remote-branch: branch_name
-            remote-commit: 6ec67b364e67bbd74c66fc8f0cbb95e6ac155d84
-        "};
-        let meta_data = TryInto::<CommitMetadata>::try_into(msg).unwrap();
-        assert_eq!(
-            meta_data,
-            CommitMetadata {
-                remote_branch_name: Cow::Borrowed("branch_name"),
-                remote_commit: "6ec67b364e67bbd74c66fc8f0cbb95e6ac155d84".parse().unwrap(),
-            }
-        )
-    }
-
-    #[test]
-    fn test_parse_with_invalid_remote_commit() {
-        let msg = indoc! {"
-            remote-branch: branch_name
-            remote-commit: Invrlid
-        "};
-        let meta_data = TryInto::<CommitMetadata>::try_into(msg);
-        assert!(meta_data.is_err())
-    }
+    Ok(files)
 }
@@ -16,7 +16,7 @@ fn tracked(commit: MainCommit) -> TrackedCommit {
     }
 }
 
-fn setup_repo(remote: &RemoteRepo) -> TestRepoWithRemote {
+fn setup_repo(remote: &RemoteRepo) -> TestRepoWithRemote<'_> {
     let local = remote.clone_repo();
 
     let local = local
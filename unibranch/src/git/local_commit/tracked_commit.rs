@@ -1,18 +1,23 @@
-use std::{ffi::CString, fmt::Debug};
+use std::fmt::{Debug, Display};
 
 use anyhow::Context;
-use anyhow::Ok;
 use git2::ApplyOptions;
 use git2::Diff;
 use git2::DiffDelta;
 use git2::Index;
 use git2::MergeOptions;
-use git2::{Branch, Commit, Oid, Repository};
-use indoc::formatdoc;
+use git2::{Branch, Commit, Oid, Repository, Tree};
 use tracing::info;
 
-use crate::git::SyncState;
+use crate::{
+    error::PausedSync,
+    git::{SyncPhase, SyncState},
+    Error,
+};
 
+use super::cherry_pick_range;
+use super::conflicting_files;
+use super::group_base;
 use super::CommitMetadata;
 use super::GitRepo;
 use super::UnTrackedCommit;
@@ -23,6 +28,7 @@ pub struct TrackedCommit<'repo> {
     git_repo: &'repo GitRepo,
     commit: Commit<'repo>,
     meta_data: CommitMetadata<'repo>,
+    base: Option<Oid>,
 }
 
 impl<'repo> TrackedCommit<'repo> {
@@ -37,26 +43,66 @@ impl<'repo> TrackedCommit<'repo> {
             git_repo,
             commit,
             meta_data,
+            base: None,
         }
     }
 
-    pub fn remote_branch(&self) -> anyhow::Result<Branch> {
+    ///
+    /// Use `base` as the head of the parent branch this commit is stacked on, instead of
+    /// looking it up among the ancestors of this commit.
+    ///
+    pub fn with_base(mut self, base: Oid) -> Self {
+        self.base = Some(base);
+        self
+    }
+
+    ///
+    /// The commit the remote branch is based on. That is the head of the parent branch for
+    /// a stacked commit, or the base branch otherwise.
+    ///
+    pub fn base_commit(&self) -> crate::Result<Commit<'repo>> {
+        let Some(parent_branch) = &self.meta_data.parent_branch else {
+            return self.git_repo.base_commit();
+        };
+        if let Some(base) = self.base {
+            return Ok(self.repo.find_commit(base)?);
+        }
+        match self
+            .git_repo
+            .find_tracked_branch(&self.commit.parent(0)?, parent_branch)?
+        {
+            Some(parent) => Ok(self.repo.find_commit(parent.meta_data().remote_commit)?),
+            None => {
+                info!(
+                    "Parent branch {} is no longer tracked, using the base branch",
+                    parent_branch
+                );
+                self.git_repo.base_commit()
+            }
+        }
+    }
+
+    pub fn remote_branch(&self) -> crate::Result<Branch<'_>> {
         let remote_branch = self
             .repo
             .find_branch(
-                &format!("origin/{}", self.meta_data.remote_branch_name),
+                &format!(
+                    "{}/{}",
+                    self.git_repo.remote_name(),
+                    self.meta_data.remote_branch_name
+                ),
                 git2::BranchType::Remote,
             )
             .context("Find the remote branch")?;
         Ok(remote_branch)
     }
 
-    pub fn local_branch_head(&self) -> anyhow::Result<Commit> {
+    pub fn local_branch_head(&self) -> crate::Result<Commit<'_>> {
         let commit_meta_data = &self.meta_data;
         Ok(self.repo.find_commit(commit_meta_data.remote_commit)?)
     }
 
-    pub fn as_commit(&self) -> &Commit {
+    pub fn as_commit(&self) -> &Commit<'_> {
         &self.commit
     }
 
@@ -64,10 +110,152 @@ impl<'repo> TrackedCommit<'repo> {
         self.commit
     }
 
-    pub fn meta_data(&self) -> &CommitMetadata {
+    pub fn meta_data(&self) -> &CommitMetadata<'_> {
         &self.meta_data
     }
 
+    ///
+    /// The commit before the first commit of this virtual branch. That is the parent of this
+    /// commit unless the virtual branch spans a range of commits.
+    ///
+    pub fn group_base(&self) -> crate::Result<Commit<'repo>> {
+        Ok(group_base(
+            self.git_repo,
+            &self.commit,
+            &self.meta_data.remote_branch_name,
+        )?)
+    }
+
+    ///
+    /// All commits of this virtual branch, oldest first.
+    ///
+    pub fn commits(&self) -> crate::Result<Vec<Oid>> {
+        let group_base = self.group_base()?;
+        let mut commits = vec![self.commit.id()];
+        let mut commit = self.commit.parent(0)?;
+        while commit.id() != group_base.id() {
+            commits.push(commit.id());
+            commit = commit.parent(0)?;
+        }
+        commits.reverse();
+        Ok(commits)
+    }
+
+    ///
+    /// Whether all changes of this virtual branch are already on the base branch, e.g. because
+    /// it has been (squash) merged.
+    ///
+    pub fn is_merged(&self) -> crate::Result<bool> {
+        let base_commit = self.git_repo.base_commit()?;
+        let mut index = self.cherry_pick_onto(&base_commit, None)?;
+        if index.has_conflicts() {
+            return Ok(false);
+        }
+        Ok(index.write_tree_to(self.repo)? == base_commit.tree_id())
+    }
+
+    ///
+    /// Cherry-pick this commit onto `parent_commit`, keeping its note.
+    ///
+    pub(crate) fn rebase(self, parent_commit: &Commit<'_>) -> anyhow::Result<Commit<'repo>> {
+        Ok(UnTrackedCommit::new(self.repo, self.git_repo, self.commit)
+            .rebase(parent_commit)?
+            .commit())
+    }
+
+    ///
+    /// Cherry-pick the changes of all commits of this virtual branch onto `onto`.
+    ///
+    fn cherry_pick_onto(
+        &self,
+        onto: &Commit,
+        opts: Option<&MergeOptions>,
+    ) -> anyhow::Result<Index> {
+        Ok(cherry_pick_range(
+            self.repo,
+            &self.group_base()?,
+            &self.commit,
+            onto,
+            opts,
+        )?)
+    }
+
+    ///
+    /// The tree of the base commit with the changes of all commits of this virtual branch,
+    /// except this last one, applied.
+    ///
+    fn base_tree_without_self(&self) -> anyhow::Result<Tree<'repo>> {
+        let base_commit = self.base_commit()?;
+        let group_base = self.group_base()?;
+        let parent = self.commit.parent(0)?;
+        if group_base.id() == parent.id() {
+            return Ok(base_commit.tree()?);
+        }
+        let mut index = cherry_pick_range(self.repo, &group_base, &parent, &base_commit, None)?;
+        if index.has_conflicts() {
+            return Err(Error::MergeConflict {
+                files: conflicting_files(&index)?,
+                paused: None,
+            }
+            .into());
+        }
+        let tree_id = index.write_tree_to(self.repo)?;
+        Ok(self.repo.find_tree(tree_id)?)
+    }
+
+    ///
+    /// Compare the last pushed commit (`meta_data.remote_commit`) with the current head
+    /// of the remote branch.
+    ///
+    pub fn remote_state(&self) -> crate::Result<RemoteState> {
+        let Some(remote_head) = self
+            .git_repo
+            .find_head_of_remote_branch(&self.meta_data.remote_branch_name)
+        else {
+            return Ok(RemoteState::Missing);
+        };
+        let local_branch_head = self.meta_data.remote_commit;
+        if remote_head.id() == local_branch_head {
+            return Ok(RemoteState::UpToDate);
+        }
+        let (ahead, behind) = self
+            .repo
+            .graph_ahead_behind(local_branch_head, remote_head.id())
+            .context("Compare local branch head with remote branch head")?;
+        Ok(match (ahead, behind) {
+            (_, 0) => RemoteState::Ahead(ahead),
+            (0, _) => RemoteState::Behind(behind),
+            _ => RemoteState::Diverged { ahead, behind },
+        })
+    }
+
+    ///
+    /// Whether the changes in this commit differ from what was last pushed to the remote
+    /// branch, i.e. whether a `sync` would produce a new commit on the branch.
+    ///
+    pub fn has_local_changes(&self) -> crate::Result<bool> {
+        let local_diff = self.repo.diff_tree_to_tree(
+            Some(&self.group_base()?.tree()?),
+            Some(&self.commit.tree()?),
+            None,
+        )?;
+
+        // Compare with the changes of the remote branch since it left its base, so that
+        // a base that moved on since the last push doesn't count as a change
+        let remote_commit = self.repo.find_commit(self.meta_data.remote_commit)?;
+        let merge_base = self
+            .repo
+            .merge_base(remote_commit.id(), self.base_commit()?.id())
+            .context("Find the merge base of the remote branch")?;
+        let remote_diff = self.repo.diff_tree_to_tree(
+            Some(&self.repo.find_commit(merge_base)?.tree()?),
+            Some(&remote_commit.tree()?),
+            None,
+        )?;
+
+        Ok(local_diff.patchid(None)? != remote_diff.patchid(None)?)
+    }
+
     //
     // Apply the diff between this commit and the self.meta_data.remote_commit
     // and return the new TrackedCommit
@@ -83,31 +271,47 @@ impl<'repo> TrackedCommit<'repo> {
     //              |  /
     //              | /
     //  (origin)    *
-    pub fn update_local_branch_head(self) -> anyhow::Result<Self> {
+    pub fn update_local_branch_head(self) -> crate::Result<Self> {
         let remote_commit = self.repo.find_commit(self.meta_data().remote_commit)?;
 
         info!("Sync with branch head: {}", remote_commit.id());
 
-        let origin_main_commit = self.git_repo.base_commit()?;
-        let complete_index = self
-            .repo
-            .cherrypick_commit(
-                self.as_commit(),
+        let origin_main_commit = self.base_commit()?;
+        let mut complete_index = self
+            .cherry_pick_onto(
                 &origin_main_commit,
-                0,
                 Some(MergeOptions::default().file_favor(git2::FileFavor::Theirs)),
             )
             .context("Cherry picking directly on master")?;
 
         if complete_index.has_conflicts() {
-            anyhow::bail!("There are conflicts");
+            let error = self.git_repo.pause_sync(
+                &self.commit,
+                &mut complete_index,
+                &origin_main_commit,
+                remote_commit.id(),
+                SyncPhase::CherryPick,
+            )?;
+            return Err(error);
         }
 
-        let patch = self.repo.diff_tree_to_index(
-            Some(&remote_commit.tree()?),
-            Some(&complete_index),
-            None,
-        )?;
+        let complete_tree = self
+            .repo
+            .find_tree(complete_index.write_tree_to(self.repo)?)?;
+        Ok(self.update_local_branch_head_to(&complete_tree)?)
+    }
+
+    ///
+    /// Add commits to what was last pushed so that it ends up with the changes of
+    /// `complete_tree`, the tree of this commit on top of its base.
+    ///
+    fn update_local_branch_head_to(self, complete_tree: &Tree) -> anyhow::Result<Self> {
+        let remote_commit = self.repo.find_commit(self.meta_data().remote_commit)?;
+        let origin_main_commit = self.base_commit()?;
+
+        let patch =
+            self.repo
+                .diff_tree_to_tree(Some(&remote_commit.tree()?), Some(complete_tree), None)?;
         // Split the patch
         let main_sync_patch = self.repo.diff_tree_to_tree(
             Some(&remote_commit.tree()?),
@@ -128,39 +332,73 @@ impl<'repo> TrackedCommit<'repo> {
 
         println!("Main patch files: {:?}", files_in_main_patch);
 
-        let new_commit = self.split_and_apply_patch(remote_commit, &patch, |delta| {
-            if let Some(delta) = delta {
-                files_in_main_patch.contains(&(delta.old_file().id(), delta.new_file().id()))
-            } else {
-                panic!("delta callback without any DiffDelta");
+        // A stacked branch merges in the new head of its parent branch, so that the parent
+        // branch stays an ancestor of it.
+        let merge_parent = if self.meta_data.parent_branch.is_some()
+            && remote_commit.id() != origin_main_commit.id()
+            && !self
+                .repo
+                .graph_descendant_of(remote_commit.id(), origin_main_commit.id())?
+        {
+            Some(&origin_main_commit)
+        } else {
+            None
+        };
+
+        let new_commit =
+            self.split_and_apply_patch(remote_commit, merge_parent, &patch, |delta| {
+                delta.is_some_and(|delta| {
+                    files_in_main_patch.contains(&(delta.old_file().id(), delta.new_file().id()))
+                })
+            })?;
+
+        match new_commit {
+            Some(new_commit) => {
+                let new_commit_id = new_commit.id();
+                drop(new_commit);
+                self.with_remote_commit(new_commit_id)
             }
-        })?;
+            None => Ok(self),
+        }
+    }
 
-        if new_commit.is_none() {
-            drop(new_commit);
-            return std::result::Result::Ok(self);
+    ///
+    /// Continue a sync of this commit that was paused in `phase` on conflicts, now that they
+    /// have been resolved in `resolved_tree`.
+    ///
+    pub(crate) fn cont_update_local_branch_head(
+        self,
+        phase: SyncPhase,
+        resolved_tree: &Tree,
+    ) -> anyhow::Result<Self> {
+        match phase {
+            SyncPhase::MergeRemoteHead => {
+                anyhow::bail!("A merge with the remote branch is not part of updating it")
+            }
+            SyncPhase::CherryPick => self.update_local_branch_head_to(resolved_tree),
         }
+    }
 
-        let new_commit = new_commit.unwrap();
-        let new_commit_id = new_commit.id();
-        drop(new_commit);
-        info!("New patch commit {}", new_commit_id);
-        let new_meta = self.meta_data.update_commit(new_commit_id);
+    fn with_remote_commit(self, remote_commit: Oid) -> anyhow::Result<Self> {
+        info!("New patch commit {}", remote_commit);
+        let new_meta = self.meta_data.update_commit(remote_commit);
         self.git_repo.save_meta_data(&self.commit, &new_meta)?;
-        std::result::Result::Ok(TrackedCommit {
+        Ok(TrackedCommit {
             repo: self.repo,
             git_repo: self.git_repo,
             commit: self.commit,
             meta_data: new_meta,
+            base: self.base,
         })
     }
 
     fn split_and_apply_patch<F>(
         &self,
-        parent: Commit,
+        parent: Commit<'repo>,
+        merge_parent: Option<&Commit>,
         patch: &Diff,
         mut delta_cb: F,
-    ) -> anyhow::Result<Option<Commit<'_>>>
+    ) -> anyhow::Result<Option<Commit<'repo>>>
     where
         F: FnMut(Option<DiffDelta<'_>>) -> bool,
     {
@@ -173,9 +411,11 @@ impl<'repo> TrackedCommit<'repo> {
             )
             .context("Apply commit patch to old branch")?;
 
-        let main_sync_commit = self
-            .commit_index(&mut new_index, &parent, "Sync with main!")?
-            .unwrap_or(parent);
+        let main_sync_commit =
+            self.commit_index(&mut new_index, &parent, merge_parent, "Sync with main!")?;
+        // Merging in the parent branch must be pushed even without any other changes
+        let merged_parent_branch = merge_parent.is_some() && main_sync_commit.is_some();
+        let main_sync_commit = main_sync_commit.unwrap_or(parent);
 
         let mut index2 = self
             .repo
@@ -186,33 +426,29 @@ impl<'repo> TrackedCommit<'repo> {
             )
             .context("Apply commit patch to old branch")?;
 
-        self.commit_index(&mut index2, &main_sync_commit, "Fixup!")
+        let fixup_commit = self.commit_index(&mut index2, &main_sync_commit, None, "Fixup!")?;
+        if fixup_commit.is_none() && merged_parent_branch {
+            return Ok(Some(main_sync_commit));
+        }
+        Ok(fixup_commit)
     }
 
+    ///
+    /// Commit `index` on top of `parent`, and `merge_parent` if any, unless it has no changes.
+    ///
     fn commit_index(
         &self,
         index: &mut Index,
         parent: &Commit,
+        merge_parent: Option<&Commit>,
         msg: &str,
-    ) -> anyhow::Result<Option<Commit<'_>>> {
+    ) -> anyhow::Result<Option<Commit<'repo>>> {
         if index.has_conflicts() {
-            for c in index.conflicts()? {
-                let c = c?;
-                println!(
-                    "{} {} {}",
-                    c.our
-                        .as_ref()
-                        .map(|our| String::from_utf8(our.path.clone()).unwrap())
-                        .unwrap_or("NONE".to_string()),
-                    c.their
-                        .map(|our| String::from_utf8(our.path).unwrap())
-                        .unwrap_or("NONE".to_string()),
-                    c.ancestor
-                        .map(|our| String::from_utf8(our.path).unwrap())
-                        .unwrap_or("NONE".to_string())
-                );
+            return Err(Error::MergeConflict {
+                files: conflicting_files(index)?,
+                paused: None,
             }
-            panic!("Conflicts while cherry-picking");
+            .into());
         }
         if index.is_empty() {
             return std::result::Result::Ok(None);
@@ -224,8 +460,9 @@ impl<'repo> TrackedCommit<'repo> {
         let tree = self.repo.find_tree(tree_id)?;
         let new_commit = {
             let signature = self.as_commit().author();
+            let parents: Vec<&Commit> = std::iter::once(parent).chain(merge_parent).collect();
             self.repo
-                .commit(None, &signature, &signature, msg, &tree, &[parent])?
+                .commit(None, &signature, &signature, msg, &tree, &parents)?
         };
 
         std::result::Result::Ok(Some(self.repo.find_commit(new_commit)?))
@@ -248,7 +485,7 @@ impl<'repo> TrackedCommit<'repo> {
     ///                 | /
     ///     (origin)    *
     /// ```
-    pub fn merge_remote_head(self, new_parent: Option<&Commit>) -> anyhow::Result<Self> {
+    pub fn merge_remote_head(self, new_parent: Option<&Commit>) -> crate::Result<Self> {
         // TODO: This should not take in a parent. The rebase should happen after
         let remote_branch_commit = self.remote_branch()?.get().peel_to_commit()?;
         let remote_branch_head = remote_branch_commit.id();
@@ -270,7 +507,7 @@ impl<'repo> TrackedCommit<'repo> {
 
         let new_remote_tree = new_remote_commit.tree()?;
         let diff = self.repo.diff_tree_to_tree(
-            Some(&self.git_repo.base_commit()?.tree()?),
+            Some(&self.base_tree_without_self()?),
             Some(&new_remote_tree),
             None,
         )?;
@@ -292,7 +529,9 @@ impl<'repo> TrackedCommit<'repo> {
                 None,
                 &signature,
                 &signature,
-                self.commit.message().expect("Not valid UTF-8"),
+                self.commit
+                    .message()
+                    .context("Commit message is not valid UTF-8")?,
                 &tree,
                 &[&parent_commit],
             )?
@@ -325,18 +564,18 @@ impl<'repo> TrackedCommit<'repo> {
     //                 |       /
     //                 *------/
     //
-    pub fn sync_with_main(mut self) -> anyhow::Result<Self> {
+    pub fn sync_with_main(mut self) -> crate::Result<Self> {
         let local_branch_head = self.meta_data().remote_commit;
         let merge_base = self
             .repo
             .merge_base(local_branch_head, self.as_commit().id())
             .context("Find merge base of remote and main")?;
-        if merge_base == self.git_repo.base_commit()?.id() || merge_base == self.commit.id() {
+        if merge_base == self.base_commit()?.id() || merge_base == self.commit.id() {
             Ok(self)
         } else {
             let local_branch_commit = self.repo.find_commit(local_branch_head)?;
             let merge_oid = self
-                .merge(&self.git_repo.base_commit()?, &local_branch_commit)
+                .merge(&self.base_commit()?, &local_branch_commit)
                 .context("Merge origin/main with local_branch_head")?;
 
             let _ = std::mem::replace(&mut self.meta_data.remote_commit, merge_oid);
@@ -350,10 +589,10 @@ impl<'repo> TrackedCommit<'repo> {
         self,
         new_remote_commit: &Commit<'repo>,
         new_parent: Option<&Commit<'repo>>,
-    ) -> anyhow::Result<Self> {
+    ) -> crate::Result<Self> {
         let new_remote_tree = new_remote_commit.tree()?;
         let diff = self.repo.diff_tree_to_tree(
-            Some(&self.git_repo.base_commit()?.tree()?),
+            Some(&self.base_tree_without_self()?),
             Some(&new_remote_tree),
             None,
         )?;
@@ -375,7 +614,9 @@ impl<'repo> TrackedCommit<'repo> {
                 None,
                 &signature,
                 &signature,
-                self.commit.message().expect("Not valid UTF-8"),
+                self.commit
+                    .message()
+                    .context("Commit message is not valid UTF-8")?,
                 &tree,
                 &[&parent_commit],
             )?
@@ -399,6 +640,7 @@ impl<'repo> TrackedCommit<'repo> {
             git_repo: self.git_repo,
             commit: self.commit,
             meta_data: self.meta_data.update_commit(new_remote_head),
+            base: self.base,
         }
     }
 
@@ -407,10 +649,7 @@ impl<'repo> TrackedCommit<'repo> {
 
         //self.repo.merge_analysis_for_ref
         if merge_index.has_conflicts() {
-            for c in merge_index.conflicts()? {
-                let c = c?;
-                println!("Conclict {:?}", CString::new(c.our.unwrap().path).unwrap())
-            }
+            let files = conflicting_files(&merge_index)?;
 
             self.repo.checkout_tree(commit1.tree()?.as_object(), None)?;
             self.repo
@@ -426,15 +665,16 @@ impl<'repo> TrackedCommit<'repo> {
                 remote_commit_id: commit2.id().into(),
                 main_commit_parent_id: self.commit.parent(0)?.id().into(),
                 main_branch_name: self.git_repo.current_branch_name.clone(),
+                phase: SyncPhase::MergeRemoteHead,
             })?;
-            let message = formatdoc! {"
-                    Unable to merge local commit ({local}) with commit from remote ({remote})
-                    Once all the conflicts has been resolved, run 'ubr sync --continue'
-                    ",
-                local = commit1.id(),
-                remote = commit2.id(),
-            };
-            anyhow::bail!(message);
+            return Err(Error::MergeConflict {
+                files,
+                paused: Some(PausedSync::Merge {
+                    local: commit1.id(),
+                    remote: commit2.id(),
+                }),
+            }
+            .into());
         }
         if merge_index.is_empty() {
             anyhow::bail!("Index is empty");
@@ -454,14 +694,42 @@ impl<'repo> TrackedCommit<'repo> {
         Ok(oid)
     }
 
+    ///
+    /// Remove the notes of all commits of this virtual branch. The remote branch is left as is.
+    ///
     pub(crate) fn untrack(self) -> anyhow::Result<UnTrackedCommit<'repo>> {
-        self.git_repo.remove_meta_data(&self.commit)?;
-        //self.git_repo.remove_remote_branch(&self.meta_data.remote_branch_name)?;
+        for commit_id in self.commits()? {
+            self.git_repo
+                .remove_meta_data(&self.repo.find_commit(commit_id)?)?;
+        }
 
         Ok(UnTrackedCommit::new(self.repo, self.git_repo, self.commit))
     }
 }
 
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum RemoteState {
+    UpToDate,
+    Ahead(usize),
+    Behind(usize),
+    Diverged { ahead: usize, behind: usize },
+    Missing,
+}
+
+impl Display for RemoteState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RemoteState::UpToDate => write!(f, "up to date"),
+            RemoteState::Ahead(n) => write!(f, "{} commit(s) not pushed", n),
+            RemoteState::Behind(n) => write!(f, "{} new commit(s) on remote", n),
+            RemoteState::Diverged { ahead, behind } => {
+                write!(f, "diverged ({} local, {} remote)", ahead, behind)
+            }
+            RemoteState::Missing => write!(f, "remote branch missing"),
+        }
+    }
+}
+
 impl Debug for TrackedCommit<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let commit = &self.commit;
@@ -2,10 +2,17 @@ use std::fmt::Debug;
 
 use anyhow::Context;
 use git2::{Commit, Repository};
+use tracing::info;
 
-use crate::git::{local_commit::CommitMetadata, GitRepo};
+use crate::{
+    git::{
+        local_commit::{CommitMetadata, META_DATA_VERSION},
+        GitRepo,
+    },
+    Error,
+};
 
-use super::TrackedCommit;
+use super::{cherry_pick_range, conflicting_files, MainCommit, TrackedCommit};
 
 pub struct UnTrackedCommit<'repo> {
     repo: &'repo Repository,
@@ -21,7 +28,7 @@ impl<'repo> UnTrackedCommit<'repo> {
             commit,
         }
     }
-    pub fn as_commit(&self) -> &Commit {
+    pub fn as_commit(&self) -> &Commit<'_> {
         &self.commit
     }
 
@@ -41,12 +48,17 @@ impl<'repo> UnTrackedCommit<'repo> {
                 None,
                 &signature,
                 &signature,
-                self.commit.message().expect("Not valid UTF-8 message"),
+                self.commit
+                    .message()
+                    .context("Commit message is not valid UTF-8")?,
                 &tree,
                 &[parent_commit],
             )?;
             self.repo.find_commit(new_commit_id)?
         };
+        if let Some(note) = self.git_repo.find_note(self.commit.id()) {
+            self.git_repo.write_note(new_commit.id(), Some(&note))?;
+        }
         Ok(UnTrackedCommit {
             repo: self.repo,
             git_repo: self.git_repo,
@@ -54,22 +66,86 @@ impl<'repo> UnTrackedCommit<'repo> {
         })
     }
 
-    pub(crate) fn track(self, branch_name: Option<String>) -> anyhow::Result<TrackedCommit<'repo>> {
+    ///
+    /// Create a remote branch for this commit, based on the base branch. With `stack`, or if
+    /// the commit doesn't apply cleanly on the base branch, the remote branch is instead based
+    /// on the remote branch of the nearest tracked ancestor.
+    ///
+    pub(crate) fn track(
+        self,
+        branch_name: Option<String>,
+        stack: bool,
+    ) -> anyhow::Result<TrackedCommit<'repo>> {
+        let group_base = self.commit.parent(0)?;
+        self.track_range(group_base, branch_name, stack)
+    }
+
+    ///
+    /// Like [`UnTrackedCommit::track`], but create one remote branch for all commits after
+    /// `group_base` up to and including this commit.
+    ///
+    pub(crate) fn track_range(
+        self,
+        group_base: Commit<'repo>,
+        branch_name: Option<String>,
+        stack: bool,
+    ) -> anyhow::Result<TrackedCommit<'repo>> {
         let commit_msg = self
             .as_commit()
             .message()
             .context("Commit message is not valid UTF-8")?;
 
-        let branch_name =
-            branch_name.unwrap_or_else(|| self.generate_remote_branch_name(commit_msg));
-        let origin_main_commit = self.git_repo.base_commit()?;
-        let mut complete_index = self
-            .repo
-            .cherrypick_commit(self.as_commit(), &origin_main_commit, 0, None)
-            .context("Cherry picking directly on master")?;
+        let branch_name = match branch_name {
+            Some(branch_name) => branch_name,
+            None => self.git_repo.generate_remote_branch_name(
+                commit_msg.lines().next().unwrap_or_default(),
+                self.as_commit(),
+            )?,
+        };
+        let mut parent = if stack {
+            Some(
+                self.find_tracked_ancestor(&group_base)?
+                    .context("There is no tracked commit to stack this commit on")?,
+            )
+        } else {
+            None
+        };
+        let mut origin_main_commit = self.stack_base(parent.as_ref())?;
+        let mut complete_index = cherry_pick_range(
+            self.repo,
+            &group_base,
+            self.as_commit(),
+            &origin_main_commit,
+            None,
+        )
+        .context("Cherry picking directly on master")?;
+
+        if complete_index.has_conflicts() && parent.is_none() {
+            if let Some(ancestor) = self.find_tracked_ancestor(&group_base)? {
+                info!(
+                    "Commit does not apply on {}, stacking it on {}",
+                    self.git_repo.base_ref(),
+                    ancestor.meta_data().remote_branch_name
+                );
+                parent = Some(ancestor);
+                origin_main_commit = self.stack_base(parent.as_ref())?;
+                complete_index = cherry_pick_range(
+                    self.repo,
+                    &group_base,
+                    self.as_commit(),
+                    &origin_main_commit,
+                    None,
+                )
+                .context("Cherry picking on parent branch")?;
+            }
+        }
 
         if complete_index.has_conflicts() {
-            anyhow::bail!("There are conflicts");
+            return Err(Error::MergeConflict {
+                files: conflicting_files(&complete_index)?,
+                paused: None,
+            }
+            .into());
         }
 
         let tree_id = complete_index.write_tree_to(self.repo)?;
@@ -89,10 +165,21 @@ impl<'repo> UnTrackedCommit<'repo> {
 
         //Create meta_data
         let meta_data = CommitMetadata {
+            version: META_DATA_VERSION,
             remote_branch_name: std::borrow::Cow::Owned(branch_name),
             remote_commit,
+            parent_branch: parent
+                .map(|p| std::borrow::Cow::Owned(p.meta_data().remote_branch_name.to_string())),
+            pull_request: None,
+            extra: Vec::new(),
         };
         self.git_repo.save_meta_data(self.as_commit(), &meta_data)?;
+        let mut member = self.commit.parent(0)?;
+        while member.id() != group_base.id() {
+            self.git_repo
+                .save_group_member(&member, &meta_data.remote_branch_name)?;
+            member = member.parent(0)?;
+        }
         Ok(TrackedCommit::new(
             self.repo,
             self.git_repo,
@@ -101,20 +188,31 @@ impl<'repo> UnTrackedCommit<'repo> {
         ))
     }
 
-    fn generate_remote_branch_name(&self, commit_msg: &str) -> String {
-        let branch_name = {
-            let title = commit_msg
-                .lines()
-                .next()
-                .expect("Must have at least one line");
-            title
-                .replace(
-                    |c: char| !(c.is_ascii_alphanumeric() || c == '-' || c == '_'),
-                    "-",
-                )
-                .to_ascii_lowercase()
-        };
-        branch_name
+    fn stack_base(&self, parent: Option<&TrackedCommit<'repo>>) -> anyhow::Result<Commit<'repo>> {
+        match parent {
+            Some(parent) => Ok(self.repo.find_commit(parent.meta_data().remote_commit)?),
+            None => Ok(self.git_repo.base_commit()?),
+        }
+    }
+
+    ///
+    /// The closest tracked commit, starting from `from`, that is not yet on the base branch.
+    ///
+    fn find_tracked_ancestor(
+        &self,
+        from: &Commit<'repo>,
+    ) -> anyhow::Result<Option<TrackedCommit<'repo>>> {
+        let base_id = self.git_repo.base_commit()?.id();
+        let mut commit = from.clone();
+        while commit.id() != base_id && self.repo.graph_descendant_of(commit.id(), base_id)? {
+            let parent = commit.parent(0)?;
+            if let MainCommit::Tracked(tracked) = MainCommit::new(self.git_repo, self.repo, commit)?
+            {
+                return Ok(Some(tracked));
+            }
+            commit = parent;
+        }
+        Ok(None)
     }
 }
 
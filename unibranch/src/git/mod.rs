@@ -1,17 +1,32 @@
-use std::path::{Path, PathBuf};
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+};
 
 use anyhow::Context;
 use clap::builder::OsStr;
-use git2::{Commit, Repository, RepositoryOpenFlags};
+use git2::{build::CheckoutBuilder, Commit, Repository, RepositoryOpenFlags};
 use serde::{Deserialize, Serialize};
 
+use crate::{error::PausedSync, Error};
+
 use self::{
+    branch_name::BranchNameTemplate,
     local_commit::{CommitMetadata, MainCommit, TrackedCommit},
     remote_command::RemoteGitCommand,
 };
 
+pub mod branch_name;
+mod journal;
+use journal::SyncLock;
+pub use journal::{Journal, JournalEntry};
 pub mod local_commit;
+mod notes;
 mod oid;
+mod op_log;
+use op_log::PendingOperation;
+pub use op_log::{NoteSnapshot, OpLog, Operation, RemoteHead, Snapshot};
 pub mod remote_command;
 pub use oid::Oid;
 
@@ -21,31 +36,55 @@ pub enum CommandOption {
     DryRun,
 }
 
+pub const DEFAULT_NOTES_REF: &str = "refs/notes/ubr";
+
 #[derive(Serialize, Deserialize, Eq, PartialEq, Debug)]
 pub struct SyncState {
     pub main_commit_id: Oid,
     pub remote_commit_id: Oid,
     pub main_commit_parent_id: Oid,
     pub main_branch_name: String,
+    #[serde(default)]
+    pub phase: SyncPhase,
+}
+
+///
+/// The step of syncing a tracked commit that ran into conflicts.
+///
+#[derive(Serialize, Deserialize, Eq, PartialEq, Debug, Default, Clone, Copy)]
+pub enum SyncPhase {
+    /// Merging the remote branch head `remote_commit_id` into what was last pushed.
+    #[default]
+    MergeRemoteHead,
+    /// Cherry-picking the local commit onto its base, to update `remote_commit_id` with.
+    CherryPick,
 }
 
 pub struct GitRepo {
     repo: git2::Repository,
     pub current_branch_name: String,
+    remote_name: String,
+    /// `None` if not configured, the base branch is then taken from the push remote
+    base_remote_name: Option<String>,
+    base_branch_name: String,
+    notes_ref: String,
     path: PathBuf,
     git_command_option: CommandOption,
     sync_state: Option<SyncState>,
+    journal: RefCell<Option<Journal>>,
+    sync_lock: RefCell<Option<SyncLock>>,
+    operation: RefCell<Option<PendingOperation>>,
 }
 
 impl GitRepo {
-    pub fn open<P>(path: P) -> anyhow::Result<Self>
+    pub fn open<P>(path: P) -> crate::Result<Self>
     where
         P: AsRef<Path>,
     {
         GitRepo::open_with_remote(path, CommandOption::Silent)
     }
 
-    pub fn open_with_remote<P>(path: P, remote: CommandOption) -> anyhow::Result<Self>
+    pub fn open_with_remote<P>(path: P, remote: CommandOption) -> crate::Result<Self>
     where
         P: AsRef<Path>,
     {
@@ -55,31 +94,68 @@ impl GitRepo {
             &[] as &[&OsStr],
         )
         .context("Opening git repository")?;
+        let journal = Journal::load(repo.path())?;
         if let Some(state) = GitRepo::try_load_sync_state(path.as_ref()) {
+            let (remote_name, base_remote_name, base_branch_name) =
+                GitRepo::read_remote_config(&repo, &state.main_branch_name)?;
+            let notes_ref = GitRepo::read_notes_ref(&repo)?;
             return Ok(GitRepo {
                 repo,
                 current_branch_name: state.main_branch_name.clone(),
+                remote_name,
+                base_remote_name,
+                base_branch_name,
+                notes_ref,
                 path: path.as_ref().into(),
                 git_command_option: remote,
                 sync_state: Some(state),
+                journal: RefCell::new(journal),
+                sync_lock: RefCell::new(None),
+                operation: RefCell::new(None),
             });
             //anyhow::bail!("{:?}", state);
         }
+        if let Some(journal) = journal {
+            // Only a sync whose process is gone is rolled back, not one running right now
+            if let Some(_lock) = SyncLock::try_acquire(&repo)? {
+                tracing::warn!(
+                    "Found an unfinished sync of {}, rolling it back",
+                    journal.main_branch_name
+                );
+                journal.rollback(&repo)?;
+                Journal::remove(repo.path())?;
+            } else {
+                tracing::debug!(
+                    "A sync of {} is running in another process",
+                    journal.main_branch_name
+                );
+            }
+        }
         let head = repo.head().context("No head")?;
         if !head.is_branch() {
-            anyhow::bail!("Detached HEAD");
+            return Err(Error::DetachedHead);
         }
 
-        let current_branch_name = head.name().expect("Branch must have a name");
-        let current_branch_name = current_branch_name
-            .strip_prefix("refs/heads/")
-            .expect("Unknown branch format");
-        let current_branch_name = current_branch_name.into();
+        let current_branch_name = head
+            .name()
+            .and_then(|name| name.strip_prefix("refs/heads/"))
+            .context("Branch name is not valid UTF-8")?;
+        let current_branch_name: String = current_branch_name.into();
 
         drop(head);
+        let (remote_name, base_remote_name, base_branch_name) =
+            GitRepo::read_remote_config(&repo, &current_branch_name)?;
 
+        let notes_ref = GitRepo::read_notes_ref(&repo)?;
+        GitRepo::migrate_notes(&repo, &notes_ref)?;
         let mut config = repo.config()?;
-        config.set_str("notes.rewriteRef", "refs/notes/*")?;
+        // Replaces the `refs/notes/*` of older versions, so that rewriting commits only
+        // carries the metadata over and leaves the user's own notes alone
+        config.set_multivar(
+            "notes.rewriteRef",
+            &format!("^(refs/notes/\\*|{})$", escape_regex(&notes_ref)),
+            &notes_ref,
+        )?;
 
         {
             std::fs::write(repo.path().join("info/exclude"), ".ubr")?;
@@ -88,87 +164,763 @@ impl GitRepo {
             repo,
             path: path.as_ref().into(),
             current_branch_name,
+            remote_name,
+            base_remote_name,
+            base_branch_name,
+            notes_ref,
             git_command_option: remote,
             sync_state: None,
+            journal: RefCell::new(None),
+            sync_lock: RefCell::new(None),
+            operation: RefCell::new(None),
         })
     }
 
-    pub fn remote(&self) -> RemoteGitCommand {
+    ///
+    /// The notes ref the metadata of virtual branches is stored in, read from `ubr.notesRef`.
+    /// Defaults to `refs/notes/ubr`, so that it never mixes with the user's own notes.
+    ///
+    fn read_notes_ref(repo: &Repository) -> anyhow::Result<String> {
+        let notes_ref = repo
+            .config()?
+            .get_string("ubr.notesRef")
+            .unwrap_or_else(|_| DEFAULT_NOTES_REF.to_string());
+        if notes_ref.starts_with("refs/notes/") {
+            Ok(notes_ref)
+        } else {
+            Ok(format!("refs/notes/{}", notes_ref))
+        }
+    }
+
+    ///
+    /// Move the metadata that older versions stored in the default notes ref to `notes_ref`.
+    /// Only done while `notes_ref` doesn't exist yet, other notes are left where they are.
+    ///
+    fn migrate_notes(repo: &Repository, notes_ref: &str) -> anyhow::Result<()> {
+        let default_ref = repo.note_default_ref()?;
+        if default_ref == notes_ref || repo.find_reference(notes_ref).is_ok() {
+            return Ok(());
+        }
+        let notes = match repo.notes(Some(&default_ref)) {
+            Ok(notes) => notes,
+            Err(e) if e.code() == git2::ErrorCode::NotFound => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut meta_data_notes = Vec::new();
+        for note in notes {
+            let (_, commit_id) = note?;
+            let note = repo.find_note(Some(&default_ref), commit_id)?;
+            if let Some(message) = note.message() {
+                if is_meta_data_note(message) {
+                    meta_data_notes.push((commit_id, message.to_string()));
+                }
+            }
+        }
+        for (commit_id, message) in meta_data_notes {
+            let signature = note_signature(repo, commit_id)?;
+            repo.note(
+                &signature,
+                &signature,
+                Some(notes_ref),
+                commit_id,
+                &message,
+                true,
+            )
+            .context("Migrating notes")?;
+            repo.note_delete(commit_id, Some(&default_ref), &signature, &signature)
+                .context("Migrating notes")?;
+        }
+        Ok(())
+    }
+
+    pub fn notes_ref(&self) -> &str {
+        &self.notes_ref
+    }
+
+    ///
+    /// Returns the remote to push virtual branches to, and the remote and branch that
+    /// the virtual branches are based on.
+    ///
+    /// The push remote is read from `ubr.remote` and defaults to `origin`.
+    /// The base is read from `ubr.base` (e.g. `upstream/main`). If that is not set the
+    /// base branch is the upstream of `branch_name` (`branch.<name>.merge`), or a branch
+    /// with the same name, on `ubr.baseRemote` or `branch.<name>.remote`. The base remote is
+    /// `None` if neither is set, so that it follows the push remote.
+    ///
+    fn read_remote_config(
+        repo: &Repository,
+        branch_name: &str,
+    ) -> anyhow::Result<(String, Option<String>, String)> {
+        let config = repo.config()?;
+        let remote_name = config
+            .get_string("ubr.remote")
+            .unwrap_or_else(|_| "origin".to_string());
+
+        if let Ok(base) = config.get_string("ubr.base") {
+            let base_remote_name = repo
+                .branch_remote_name(&format!("refs/remotes/{}", base))
+                .ok()
+                .and_then(|remote| remote.as_str().map(|r| r.to_string()));
+            let (base_remote_name, base_branch_name) = match base_remote_name {
+                Some(remote) => {
+                    let branch = base
+                        .strip_prefix(&format!("{}/", remote))
+                        .unwrap_or(&base)
+                        .to_string();
+                    (remote, branch)
+                }
+                None => base
+                    .split_once('/')
+                    .map(|(remote, branch)| (remote.to_string(), branch.to_string()))
+                    .with_context(|| format!("Invalid ubr.base '{}'", base))?,
+            };
+            return Ok((remote_name, Some(base_remote_name), base_branch_name));
+        }
+
+        let base_remote_name = config
+            .get_string("ubr.baseRemote")
+            .or_else(|_| config.get_string(&format!("branch.{}.remote", branch_name)))
+            .ok();
+        let base_branch_name = config
+            .get_string(&format!("branch.{}.merge", branch_name))
+            .ok()
+            .and_then(|merge| merge.strip_prefix("refs/heads/").map(|b| b.to_string()))
+            .unwrap_or_else(|| branch_name.to_string());
+        Ok((remote_name, base_remote_name, base_branch_name))
+    }
+
+    ///
+    /// Override the remote that virtual branches are pushed to. Unless a base remote is
+    /// configured, the base branch is then taken from this remote as well.
+    ///
+    pub fn with_remote_name<T: Into<String>>(mut self, name: T) -> Self {
+        self.remote_name = name.into();
+        self
+    }
+
+    ///
+    /// Override the remote that the base branch is taken from.
+    ///
+    pub fn with_base_remote_name<T: Into<String>>(mut self, name: T) -> Self {
+        self.base_remote_name = Some(name.into());
+        self
+    }
+
+    ///
+    /// Override the branch (on the base remote) that virtual branches are based on.
+    ///
+    pub fn with_base_branch_name<T: Into<String>>(mut self, name: T) -> Self {
+        self.base_branch_name = name.into();
+        self
+    }
+
+    pub fn remote_name(&self) -> &str {
+        &self.remote_name
+    }
+
+    pub fn base_remote_name(&self) -> &str {
+        self.base_remote_name
+            .as_deref()
+            .unwrap_or(&self.remote_name)
+    }
+
+    pub fn base_branch_name(&self) -> &str {
+        &self.base_branch_name
+    }
+
+    ///
+    /// The full name of the reference that all virtual branches are based on.
+    ///
+    pub fn base_ref(&self) -> String {
+        if self.base_remote_name() == "." {
+            format!("refs/heads/{}", self.base_branch_name)
+        } else {
+            format!(
+                "refs/remotes/{}/{}",
+                self.base_remote_name(),
+                self.base_branch_name
+            )
+        }
+    }
+
+    pub fn remote(&self) -> RemoteGitCommand<'_> {
+        match self.git_command_option {
+            CommandOption::Default => RemoteGitCommand::Default(&self.path, &self.remote_name),
+            CommandOption::Silent => RemoteGitCommand::Silent(&self.path, &self.remote_name),
+            CommandOption::DryRun => RemoteGitCommand::DryRun(&self.path, &self.remote_name),
+        }
+    }
+
+    ///
+    /// The remote the base branch is taken from.
+    ///
+    pub fn base_remote(&self) -> RemoteGitCommand<'_> {
         match self.git_command_option {
-            CommandOption::Default => RemoteGitCommand::Default(&self.path),
-            CommandOption::Silent => RemoteGitCommand::Silent(&self.path),
-            CommandOption::DryRun => RemoteGitCommand::DryRun(&self.path),
+            CommandOption::Default => {
+                RemoteGitCommand::Default(&self.path, self.base_remote_name())
+            }
+            CommandOption::Silent => RemoteGitCommand::Silent(&self.path, self.base_remote_name()),
+            CommandOption::DryRun => RemoteGitCommand::DryRun(&self.path, self.base_remote_name()),
         }
     }
 
-    pub fn base_commit(&self) -> anyhow::Result<Commit> {
-        let remote_ref = format!("refs/remotes/origin/{}", self.current_branch_name);
-        let base_commit_id = self.repo.refname_to_id(&remote_ref)?;
+    ///
+    /// The URL of the remote that virtual branches are pushed to.
+    ///
+    pub fn remote_url(&self) -> crate::Result<String> {
+        let remote = self
+            .repo
+            .find_remote(&self.remote_name)
+            .with_context(|| format!("Remote {} not found", self.remote_name))?;
+        Ok(remote
+            .url()
+            .with_context(|| format!("The URL of remote {} is not valid UTF-8", self.remote_name))?
+            .to_string())
+    }
+
+    pub fn config(&self) -> crate::Result<git2::Config> {
+        Ok(self.repo.config()?)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn is_dry_run(&self) -> bool {
+        matches!(self.git_command_option, CommandOption::DryRun)
+    }
+
+    pub fn base_commit(&self) -> crate::Result<Commit<'_>> {
+        let base_ref = self.base_ref();
+        let base_commit_id = self
+            .repo
+            .refname_to_id(&base_ref)
+            .with_context(|| format!("Base branch {} not found", base_ref))?;
         Ok(self.repo.find_commit(base_commit_id)?)
     }
 
-    pub fn head(&self) -> anyhow::Result<Commit> {
+    pub fn head(&self) -> crate::Result<Commit<'_>> {
         Ok(self.repo.head()?.peel_to_commit()?)
     }
 
-    pub fn find_head_of_remote_branch(&self, branch_name: &str) -> Option<Commit> {
+    pub fn find_head_of_remote_branch(&self, branch_name: &str) -> Option<Commit<'_>> {
         self.repo
-            .find_branch(&format!("origin/{}", branch_name), git2::BranchType::Remote)
+            .find_branch(
+                &format!("{}/{}", self.remote_name, branch_name),
+                git2::BranchType::Remote,
+            )
             .ok()
             .and_then(|b| b.get().peel_to_commit().ok())
     }
 
-    pub fn find_unpushed_commit(&self, commit_ref: &str) -> anyhow::Result<MainCommit> {
+    ///
+    /// The number of commits reachable from `new` that are not reachable from `old`.
+    ///
+    pub fn count_new_commits(&self, old: git2::Oid, new: git2::Oid) -> crate::Result<usize> {
+        Ok(self.repo.graph_ahead_behind(new, old)?.0)
+    }
+
+    pub fn find_commit(&self, commit_ref: &str) -> crate::Result<Commit<'_>> {
         let (obj, _) = self
             .repo
             .revparse_ext(commit_ref)
             .with_context(|| format!("Bad revision '{}'", commit_ref))?;
-        let commit = obj.peel_to_commit()?;
+        Ok(obj.peel_to_commit()?)
+    }
+
+    pub fn find_unpushed_commit(&self, commit_ref: &str) -> crate::Result<MainCommit<'_>> {
+        let commit = self.find_commit(commit_ref)?;
         if !self
             .repo
             .graph_descendant_of(commit.id(), self.base_commit()?.id())?
         {
-            anyhow::bail!(format!(
-                "Commit {} is already pushed to the remote",
-                commit.id()
-            ));
+            return Err(Error::AlreadyPushed {
+                commit: commit.id(),
+            });
         }
 
-        Ok(MainCommit::new(self, &self.repo, commit)?)
+        MainCommit::new(self, &self.repo, commit)
     }
 
-    pub fn save_meta_data(
+    ///
+    /// Find the tracked commit pushed to `branch_name` among `head` and those of its ancestors
+    /// that are not yet on the base branch.
+    ///
+    pub fn find_tracked_branch(
         &self,
-        commit: &Commit,
-        meta_data: &CommitMetadata,
-    ) -> Result<(), git2::Error> {
-        let committer = self.repo.signature().or_else(|_| {
-            git2::Signature::now(
-                String::from_utf8_lossy(commit.committer().name_bytes()).as_ref(),
-                String::from_utf8_lossy(commit.committer().email_bytes()).as_ref(),
-            )
+        head: &Commit,
+        branch_name: &str,
+    ) -> crate::Result<Option<TrackedCommit<'_>>> {
+        Ok(self
+            .tracked_commits(head)?
+            .into_iter()
+            .rev()
+            .find(|tracked| tracked.meta_data().remote_branch_name == branch_name))
+    }
+
+    ///
+    /// All tracked commits from the base commit up to and including `head`, oldest first.
+    ///
+    pub fn tracked_commits(&self, head: &Commit) -> crate::Result<Vec<TrackedCommit<'_>>> {
+        let mut walk = self.repo.revwalk()?;
+        walk.set_sorting(git2::Sort::TOPOLOGICAL | git2::Sort::REVERSE)?;
+        walk.push(head.id())?;
+        walk.hide(self.base_commit()?.id())?;
+        let mut tracked_commits = Vec::new();
+        for commit_id in walk {
+            let commit = self.repo.find_commit(commit_id?)?;
+            if let MainCommit::Tracked(tracked) = MainCommit::new(self, &self.repo, commit)? {
+                tracked_commits.push(tracked);
+            }
+        }
+        Ok(tracked_commits)
+    }
+
+    pub fn save_meta_data(&self, commit: &Commit, meta_data: &CommitMetadata) -> crate::Result<()> {
+        Ok(self.write_note(commit.id(), Some(&format!("{}", meta_data)))?)
+    }
+
+    pub fn remove_meta_data(&self, commit: &Commit) -> crate::Result<()> {
+        Ok(self.write_note(commit.id(), None)?)
+    }
+
+    fn write_note(&self, commit_id: git2::Oid, note: Option<&str>) -> anyhow::Result<()> {
+        let committer = note_signature(&self.repo, commit_id)?;
+        self.record(|| JournalEntry::Note {
+            commit: commit_id.into(),
+            old: self.find_note(commit_id),
+            new: note.map(|n| n.to_string()),
         })?;
-        self.repo.note(
-            &committer,
-            &committer,
-            None,
+        match note {
+            Some(note) => {
+                self.repo.note(
+                    &committer,
+                    &committer,
+                    Some(&self.notes_ref),
+                    commit_id,
+                    note,
+                    true,
+                )?;
+            }
+            None => {
+                self.repo
+                    .note_delete(commit_id, Some(&self.notes_ref), &committer, &committer)?
+            }
+        }
+        Ok(())
+    }
+
+    fn find_note(&self, commit_id: git2::Oid) -> Option<String> {
+        self.repo
+            .find_note(Some(&self.notes_ref), commit_id)
+            .ok()
+            .and_then(|note| note.message().map(|m| m.to_string()))
+    }
+
+    ///
+    /// Mark `commit` as one of the commits, but not the last, of a virtual branch spanning
+    /// several commits.
+    ///
+    pub fn save_group_member(
+        &self,
+        commit: &Commit,
+        remote_branch_name: &str,
+    ) -> crate::Result<()> {
+        Ok(self.write_note(
             commit.id(),
-            &format!("{}", meta_data),
-            true,
-        )?;
-        std::result::Result::Ok(())
+            Some(&local_commit::group_member_note(remote_branch_name)),
+        )?)
     }
 
-    pub fn remove_meta_data(&self, commit: &Commit) -> Result<(), git2::Error> {
-        let committer = self.repo.signature().or_else(|_| {
-            git2::Signature::now(
-                String::from_utf8_lossy(commit.committer().name_bytes()).as_ref(),
-                String::from_utf8_lossy(commit.committer().email_bytes()).as_ref(),
-            )
-        })?;
+    ///
+    /// The virtual branch that `commit_id` is part of, if it is one of the commits, but not the
+    /// last, of a virtual branch spanning several commits.
+    ///
+    pub fn group_branch(&self, commit_id: git2::Oid) -> Option<String> {
+        self.find_note(commit_id)
+            .and_then(|note| local_commit::parse_group_member_note(&note).map(|b| b.to_string()))
+    }
+
+    ///
+    /// All notes holding `CommitMetadata` or group membership, i.e. every virtual branch.
+    ///
+    fn meta_data_notes(&self) -> anyhow::Result<Vec<NoteSnapshot>> {
+        let notes = match self.repo.notes(Some(&self.notes_ref)) {
+            Ok(notes) => notes,
+            Err(e) if e.code() == git2::ErrorCode::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut result = Vec::new();
+        for note in notes {
+            let (_, commit_id) = note?;
+            if let Some(note) = self.find_note(commit_id) {
+                if is_meta_data_note(&note) {
+                    result.push(NoteSnapshot {
+                        commit: commit_id.into(),
+                        note,
+                    });
+                }
+            }
+        }
+        Ok(result)
+    }
+
+    ///
+    /// The remote branch names of all virtual branches, with a commit that is tracked by each.
+    /// Only unpushed commits count, notes left on commits that were amended or rebased away
+    /// don't track anything anymore.
+    ///
+    pub(crate) fn virtual_branches(&self) -> anyhow::Result<HashMap<String, git2::Oid>> {
+        let unpushed_commits: HashSet<_> = self
+            .unpushed_commits()?
+            .iter()
+            .map(|commit| commit.id())
+            .collect();
+        Ok(self
+            .meta_data_notes()?
+            .into_iter()
+            .filter(|note| unpushed_commits.contains(&note.commit.into()))
+            .filter_map(|note| {
+                let branch = match note.note.parse::<CommitMetadata>() {
+                    Ok(meta_data) => meta_data.remote_branch_name.into_owned(),
+                    Err(_) => local_commit::parse_group_member_note(&note.note)?.to_string(),
+                };
+                Some((branch, note.commit.into()))
+            })
+            .collect())
+    }
+
+    ///
+    /// A name for the remote branch of a new virtual branch for `commit` titled `title`, made
+    /// from the configured template. A number is appended if a remote branch or another virtual
+    /// branch already has that name.
+    ///
+    pub fn generate_remote_branch_name(
+        &self,
+        title: &str,
+        commit: &Commit,
+    ) -> crate::Result<String> {
+        let template = BranchNameTemplate::from_config(&self.repo.config()?)?;
+        let short_id = commit.as_object().short_id()?;
+        let short_id = short_id.as_str().unwrap_or_default();
+        let taken = self.virtual_branches()?;
+        let is_taken = |name: &str| {
+            taken.contains_key(name) || self.find_head_of_remote_branch(name).is_some()
+        };
+        let name = template.render(title, short_id);
+        if !is_taken(&name) {
+            return Ok(name);
+        }
+        Ok((2..)
+            .map(|suffix| template.render_with_suffix(title, short_id, &format!("-{}", suffix)))
+            .find(|name| !is_taken(name))
+            .expect("Some suffix is free"))
+    }
+
+    ///
+    /// The ref the metadata notes of the push remote are fetched into.
+    ///
+    fn remote_notes_ref(&self) -> String {
+        format!(
+            "refs/ubr/remotes/{}/{}",
+            self.remote_name,
+            self.notes_ref.trim_start_matches("refs/")
+        )
+    }
+
+    ///
+    /// Fetch the metadata notes of the push remote and merge them into the local notes, so
+    /// that virtual branches created or synced on another machine are tracked here as well.
+    ///
+    pub fn fetch_meta_data(&self) -> crate::Result<()> {
+        let tracking_ref = self.remote_notes_ref();
+        self.remote()
+            .fetch_notes(&self.notes_ref, &tracking_ref)
+            .context("Fetching metadata")?;
+        let Ok(theirs) = self.repo.refname_to_id(&tracking_ref) else {
+            return Ok(());
+        };
+        let Ok(ours) = self.repo.refname_to_id(&self.notes_ref) else {
+            self.repo
+                .reference(&self.notes_ref, theirs, false, "ubr: fetch metadata")?;
+            return Ok(());
+        };
+        if ours == theirs || self.repo.graph_descendant_of(ours, theirs)? {
+            return Ok(());
+        }
+        if self.repo.graph_descendant_of(theirs, ours)? {
+            self.repo
+                .reference(&self.notes_ref, theirs, true, "ubr: fetch metadata")?;
+            return Ok(());
+        }
+
+        let base = self.repo.merge_base(ours, theirs).ok();
+        let changes = notes::merge_notes(
+            &self.repo,
+            &notes::read_notes(&self.repo, base)?,
+            &notes::read_notes(&self.repo, Some(ours))?,
+            &notes::read_notes(&self.repo, Some(theirs))?,
+        );
+        for (commit_id, note) in changes {
+            self.write_note(commit_id, note.as_deref())?;
+        }
+        // Record that the remote notes are merged, so that they can be pushed on top of them
+        let merged = self
+            .repo
+            .find_commit(self.repo.refname_to_id(&self.notes_ref)?)?;
+        let signature = note_signature(&self.repo, merged.id())?;
         self.repo
-            .note_delete(commit.id(), None, &committer, &committer)?;
-        std::result::Result::Ok(())
+            .commit(
+                Some(&self.notes_ref),
+                &signature,
+                &signature,
+                &format!("Merge metadata from {}", self.remote_name),
+                &merged.tree()?,
+                &[&merged, &self.repo.find_commit(theirs)?],
+            )
+            .context("Merging metadata")?;
+        Ok(())
+    }
+
+    ///
+    /// Merge the metadata notes of the push remote and push the result back to it.
+    ///
+    pub fn push_meta_data(&self) -> crate::Result<()> {
+        self.fetch_meta_data()?;
+        let tracking_ref = self.remote_notes_ref();
+        let Ok(head) = self.repo.refname_to_id(&self.notes_ref) else {
+            return Ok(());
+        };
+        let expected = self.repo.refname_to_id(&tracking_ref).ok();
+        if expected == Some(head) {
+            return Ok(());
+        }
+        self.remote().push_notes(&self.notes_ref, head, expected)?;
+        if !matches!(self.git_command_option, CommandOption::DryRun) {
+            self.repo
+                .reference(&tracking_ref, head, true, "ubr: push metadata")?;
+        }
+        Ok(())
+    }
+
+    ///
+    /// Track every untracked local commit that has the same changes as the remote branch of a
+    /// virtual branch in the metadata notes, but of another commit. This is the case in a new
+    /// clone, where the metadata was fetched but the commits were recreated, e.g. by
+    /// cherry-picking them. Returns the commits that are tracked now.
+    ///
+    pub fn rebuild_tracking(&self) -> crate::Result<Vec<TrackedCommit<'_>>> {
+        let unpushed_commits = self.unpushed_commits()?;
+        let mut candidates: Vec<CommitMetadata> = Vec::new();
+        for note in self.meta_data_notes()? {
+            let Ok(meta_data) = note.note.parse::<CommitMetadata>() else {
+                continue;
+            };
+            let is_known = |name: &str| {
+                candidates.iter().any(|c| c.remote_branch_name == name)
+                    || unpushed_commits.iter().any(|commit| match commit {
+                        MainCommit::Tracked(tracked) => {
+                            tracked.meta_data().remote_branch_name == name
+                        }
+                        MainCommit::UnTracked(_) => false,
+                    })
+            };
+            if !is_known(&meta_data.remote_branch_name) {
+                candidates.push(meta_data);
+            }
+        }
+        if candidates.is_empty() {
+            return Ok(Vec::new());
+        }
+        let branches: Vec<&str> = candidates
+            .iter()
+            .map(|meta_data| &*meta_data.remote_branch_name)
+            .collect();
+        self.remote()
+            .fetch(&branches)
+            .context("Fetching the remote branches")?;
+
+        let mut tracked_commits = Vec::new();
+        for commit in unpushed_commits {
+            let MainCommit::UnTracked(commit) = commit else {
+                continue;
+            };
+            let commit = commit.commit();
+            if commit.parent_count() != 1 {
+                continue;
+            }
+            let patch_id = self.commit_diff(&commit)?.patchid(None)?;
+            let Some(position) = candidates.iter().position(|meta_data| {
+                self.remote_branch_diff(
+                    &meta_data.remote_branch_name,
+                    meta_data.parent_branch.as_deref(),
+                )
+                .and_then(|diff| Ok(diff.patchid(None)?))
+                .is_ok_and(|remote_patch_id| remote_patch_id == patch_id)
+            }) else {
+                continue;
+            };
+            let meta_data = candidates.remove(position);
+            let remote_head = self
+                .find_head_of_remote_branch(&meta_data.remote_branch_name)
+                .context("Remote branch disappeared")?;
+            let meta_data = meta_data.update_commit(remote_head.id());
+            self.save_meta_data(&commit, &meta_data)?;
+            tracked_commits.push(TrackedCommit::new(&self.repo, self, commit, meta_data));
+        }
+        Ok(tracked_commits)
+    }
+
+    ///
+    /// The changes on the remote branch `branch` since it was last synced with its base, i.e.
+    /// `parent_branch` for a stacked branch or else the base branch.
+    ///
+    pub fn remote_branch_diff(
+        &self,
+        branch: &str,
+        parent_branch: Option<&str>,
+    ) -> crate::Result<git2::Diff<'_>> {
+        let head = self
+            .find_head_of_remote_branch(branch)
+            .with_context(|| format!("Remote branch {}/{} not found", self.remote_name, branch))?;
+        let base = match parent_branch {
+            Some(parent_branch) => self
+                .find_head_of_remote_branch(parent_branch)
+                .with_context(|| {
+                    format!(
+                        "Remote branch {}/{} not found",
+                        self.remote_name, parent_branch
+                    )
+                })?
+                .id(),
+            None => self.base_commit()?.id(),
+        };
+        let base = self
+            .repo
+            .find_commit(self.repo.merge_base(head.id(), base)?)?;
+        Ok(self
+            .repo
+            .diff_tree_to_tree(Some(&base.tree()?), Some(&head.tree()?), None)?)
+    }
+
+    ///
+    /// The changes of `commit` on top of its parent.
+    ///
+    pub fn commit_diff(&self, commit: &Commit) -> crate::Result<git2::Diff<'_>> {
+        Ok(self.repo.diff_tree_to_tree(
+            Some(&commit.parent(0)?.tree()?),
+            Some(&commit.tree()?),
+            None,
+        )?)
+    }
+
+    pub fn op_log(&self) -> crate::Result<OpLog> {
+        Ok(OpLog::load(self.repo.path())?)
+    }
+
+    ///
+    /// Start a new operation by taking a snapshot of the current state, so that the operation
+    /// can be undone later. The operation is only logged by `finish_operation`.
+    ///
+    pub fn begin_operation(&self, command: &str) -> crate::Result<()> {
+        self.operation.replace(Some(PendingOperation {
+            id: None,
+            command: command.to_string(),
+            snapshot: self.snapshot()?,
+        }));
+        Ok(())
+    }
+
+    ///
+    /// Continue recording into the last operation, e.g. for a sync that was paused.
+    ///
+    pub fn resume_operation(&self) -> crate::Result<()> {
+        self.operation
+            .replace(self.op_log()?.last().map(|op| PendingOperation {
+                id: Some(op.id),
+                command: op.command.clone(),
+                snapshot: op.snapshot.clone(),
+            }));
+        Ok(())
+    }
+
+    ///
+    /// Add the operation to the operation log, unless it didn't change anything.
+    ///
+    pub fn finish_operation(&self) -> crate::Result<()> {
+        let Some(operation) = self.operation.take() else {
+            return Ok(());
+        };
+        let mut op_log = self.op_log()?;
+        match operation.id {
+            Some(id) => op_log.update(id, operation.snapshot),
+            // A paused sync is always logged, as '--continue' records into it
+            None if operation.snapshot.remote_heads.is_empty()
+                && !self.sync_paused()
+                && operation.snapshot == self.snapshot()? =>
+            {
+                return Ok(())
+            }
+            None => {
+                op_log.push(&operation.command, operation.snapshot);
+            }
+        }
+        Ok(op_log.save(self.repo.path())?)
+    }
+
+    ///
+    /// Drop the operation after it failed or was rolled back, also from the operation log if it
+    /// was a continued one.
+    ///
+    pub fn discard_operation(&self) -> crate::Result<()> {
+        if let Some(id) = self.operation.take().and_then(|operation| operation.id) {
+            let mut op_log = self.op_log()?;
+            op_log.remove(id);
+            op_log.save(self.repo.path())?;
+        }
+        Ok(())
+    }
+
+    fn snapshot(&self) -> crate::Result<Snapshot> {
+        Ok(Snapshot {
+            main_branch_name: self.current_branch_name.clone(),
+            main_branch_head: self
+                .repo
+                .refname_to_id(&format!("refs/heads/{}", self.current_branch_name))?
+                .into(),
+            notes: self.meta_data_notes()?,
+            remote_heads: Vec::new(),
+        })
+    }
+
+    ///
+    /// Restore the main branch and all virtual branch notes from a snapshot.
+    ///
+    pub fn restore_snapshot(&self, snapshot: &Snapshot) -> crate::Result<()> {
+        let main_branch_head = self
+            .repo
+            .find_commit(snapshot.main_branch_head.into())
+            .context("Find previous head of main branch")?;
+        if snapshot.main_branch_name == self.current_branch_name {
+            self.update_current_branch(&main_branch_head)?;
+        } else {
+            self.repo
+                .branch(&snapshot.main_branch_name, &main_branch_head, true)
+                .context("Moving the main branch pointer")?;
+        }
+
+        let current_notes = self.meta_data_notes()?;
+        for note in &current_notes {
+            if !snapshot.notes.iter().any(|n| n.commit == note.commit) {
+                self.write_note(note.commit.into(), None)?;
+            }
+        }
+        for note in &snapshot.notes {
+            if !current_notes.contains(note) {
+                self.write_note(note.commit.into(), Some(&note.note))?;
+            }
+        }
+        Ok(())
     }
 
     fn try_load_sync_state<P>(path: P) -> Option<SyncState>
@@ -182,18 +934,162 @@ impl GitRepo {
     }
 
     fn cleanup_state(&self) -> anyhow::Result<()> {
-        std::fs::remove_file(self.path.join(".ubr/SYNC_MERGE_HEAD")).context("Cleanup sync state")
+        std::fs::remove_file(self.path.join(".ubr/SYNC_MERGE_HEAD"))
+            .context("Cleanup sync state")?;
+        self.remove_state_dir();
+        Ok(())
+    }
+
+    fn remove_state_dir(&self) {
+        // Only succeeds if there is nothing else left in there
+        let _ = std::fs::remove_dir(self.path.join(".ubr"));
+    }
+
+    ///
+    /// Whether a sync has been paused because of a merge conflict
+    ///
+    pub fn sync_paused(&self) -> bool {
+        self.path.join(".ubr/SYNC_MERGE_HEAD").exists()
+    }
+
+    ///
+    /// Start recording every ref and note that is changed from here on in a journal, so
+    /// that the changes can be rolled back if the sync does not finish.
+    ///
+    pub fn begin_sync(&self) -> crate::Result<()> {
+        if self.sync_state.is_some() {
+            return Err(Error::SyncInProgress);
+        }
+        self.lock_sync()?;
+        let journal = Journal::begin(&self.repo, &self.current_branch_name, &self.notes_ref)?;
+        journal.save(self.repo.path())?;
+        self.journal.replace(Some(journal));
+        Ok(())
+    }
+
+    ///
+    /// Take over the journal of a sync that was paused by conflicts, so that no other process
+    /// mistakes it for an interrupted sync while it is being continued.
+    ///
+    pub fn resume_sync(&self) -> crate::Result<()> {
+        Ok(self.lock_sync()?)
+    }
+
+    fn lock_sync(&self) -> anyhow::Result<()> {
+        if self.sync_lock.borrow().is_none() {
+            let lock = SyncLock::try_acquire(&self.repo)?.ok_or(Error::SyncInProgress)?;
+            self.sync_lock.replace(Some(lock));
+        }
+        Ok(())
+    }
+
+    pub fn finish_sync(&self) -> crate::Result<()> {
+        if self.journal.take().is_some() {
+            Journal::remove(self.repo.path())?;
+            self.remove_state_dir();
+        }
+        self.sync_lock.take();
+        Ok(())
+    }
+
+    pub fn rollback_sync(&self) -> crate::Result<()> {
+        if let Some(journal) = self.journal.take() {
+            tracing::info!("Rolling back sync");
+            journal.rollback(&self.repo)?;
+            Journal::remove(self.repo.path())?;
+            self.remove_state_dir();
+        }
+        self.sync_lock.take();
+        Ok(())
     }
 
-    pub fn save_sync_state(&self, state: &SyncState) -> anyhow::Result<()> {
+    ///
+    /// Remember the current head of a remote branch that is about to be pushed to.
+    ///
+    pub fn record_push(&self, branch: &str, new_head: Option<git2::Oid>) -> crate::Result<()> {
+        let old_head = self
+            .find_head_of_remote_branch(branch)
+            .map(|c| Oid::from(c.id()));
+        self.record(|| JournalEntry::Push {
+            branch: branch.to_string(),
+            old: old_head,
+            new: new_head.map(Oid::from),
+        })?;
+        if let Some(operation) = self.operation.borrow_mut().as_mut() {
+            operation.snapshot.record_remote_head(branch, old_head);
+        }
+        Ok(())
+    }
+
+    fn record<F>(&self, entry: F) -> anyhow::Result<()>
+    where
+        F: FnOnce() -> JournalEntry,
+    {
+        if let Some(journal) = self.journal.borrow_mut().as_mut() {
+            journal.entries.push(entry());
+            journal.save(self.repo.path())?;
+        }
+        Ok(())
+    }
+
+    pub fn save_sync_state(&self, state: &SyncState) -> crate::Result<()> {
         std::fs::create_dir_all(format!("{}/.ubr", self.path.display()))?;
         let file =
             std::fs::File::create_new(format!("{}/.ubr/SYNC_MERGE_HEAD", self.path.display()))?;
-        serde_json::to_writer(file, state)?;
+        serde_json::to_writer(file, state).context("Writing the sync state")?;
         Ok(())
     }
 
-    pub fn unpushed_commits(&self) -> anyhow::Result<Vec<MainCommit>> {
+    ///
+    /// Pause the sync of `main_commit` in `phase` on conflicts in `index`: check out `onto`
+    /// with the conflicted `index` so that the user can resolve them and run
+    /// `ubr sync --continue`.
+    ///
+    pub(crate) fn pause_sync(
+        &self,
+        main_commit: &Commit,
+        index: &mut git2::Index,
+        onto: &Commit,
+        remote_commit_id: git2::Oid,
+        phase: SyncPhase,
+    ) -> anyhow::Result<Error> {
+        let files = local_commit::conflicting_files(index)?;
+        self.repo
+            .checkout_tree(onto.as_object(), Some(CheckoutBuilder::new().force()))?;
+        self.repo
+            .set_head_detached(onto.id())
+            .context("Detach HEAD")?;
+        let mut repo_index = self.repo.index()?;
+        repo_index.clear()?;
+        for entry in index.iter() {
+            repo_index.add(&entry)?;
+        }
+        repo_index.write()?;
+        self.repo.checkout_index(
+            Some(&mut repo_index),
+            Some(
+                CheckoutBuilder::new()
+                    .force()
+                    .allow_conflicts(true)
+                    .conflict_style_merge(true),
+            ),
+        )?;
+        self.save_sync_state(&SyncState {
+            main_commit_id: main_commit.id().into(),
+            remote_commit_id: remote_commit_id.into(),
+            main_commit_parent_id: main_commit.parent(0)?.id().into(),
+            main_branch_name: self.current_branch_name.clone(),
+            phase,
+        })?;
+        Ok(Error::MergeConflict {
+            files,
+            paused: Some(PausedSync::Update {
+                commit: main_commit.id(),
+            }),
+        })
+    }
+
+    pub fn unpushed_commits(&self) -> crate::Result<Vec<MainCommit<'_>>> {
         let mut walk = self.repo.revwalk()?;
         walk.set_sorting(git2::Sort::TOPOLOGICAL.union(git2::Sort::REVERSE))?;
 
@@ -212,14 +1108,14 @@ impl GitRepo {
 
         walk.hide(base_commit_id)?;
 
-        let result: Result<Vec<_>, _> = walk
-            .map(|r| self.repo.find_commit(r.expect("whhat")).unwrap())
-            .map(|c| MainCommit::new(self, &self.repo, c))
-            .collect();
-        Ok(result?)
+        walk.map(|commit_id| {
+            let commit = self.repo.find_commit(commit_id?)?;
+            MainCommit::new(self, &self.repo, commit)
+        })
+        .collect()
     }
 
-    pub fn update_current_branch(&self, new_head: &Commit) -> anyhow::Result<()> {
+    pub fn update_current_branch(&self, new_head: &Commit) -> crate::Result<()> {
         if matches!(self.git_command_option, CommandOption::DryRun) {
             println!(
                 "Setting {} to point to {}",
@@ -228,6 +1124,13 @@ impl GitRepo {
             );
             return Ok(());
         }
+        let old_head = self
+            .repo
+            .refname_to_id(&format!("refs/heads/{}", self.current_branch_name))?;
+        self.record(|| JournalEntry::MainBranch {
+            old: old_head.into(),
+            new: new_head.id().into(),
+        })?;
         self.repo
             .checkout_tree(new_head.tree()?.as_object(), None)?;
         self.repo
@@ -243,40 +1146,156 @@ impl GitRepo {
         Ok(())
     }
 
-    pub(crate) fn finish_merge(&self) -> anyhow::Result<TrackedCommit> {
-        let state = self.sync_state.as_ref().expect("Must have a sync state");
+    ///
+    /// Abort an ongoing sync that was interrupted by a merge conflict.
+    ///
+    /// Moves the main branch and HEAD back to where they were before the sync started and
+    /// rolls back the notes using the sync journal. Branches that were already
+    /// pushed to the remote are left untouched.
+    ///
+    pub fn abort_sync(&self) -> crate::Result<()> {
+        let state = self.sync_state.as_ref().context("No sync in progress")?;
+        self.lock_sync()?;
+        tracing::info!("Aborting previous sync {:?}", state);
+
+        self.repo.cleanup_state()?;
+
+        let main_branch_ref = format!("refs/heads/{}", state.main_branch_name);
+        let main_branch_head = match self.journal.borrow().as_ref() {
+            Some(journal) => journal.main_branch_head.into(),
+            None => self.repo.refname_to_id(&main_branch_ref)?,
+        };
+        let main_branch_head = self.repo.find_commit(main_branch_head)?;
+        self.repo
+            .set_head_detached(main_branch_head.id())
+            .context("Detach HEAD before moving the main branch")?;
+        self.repo
+            .branch(&state.main_branch_name, &main_branch_head, true)
+            .context("Moving the main branch pointer")?;
+        self.repo
+            .set_head(&main_branch_ref)
+            .context("Moving HEAD back to main branch")?;
+        self.repo
+            .reset(main_branch_head.as_object(), git2::ResetType::Hard, None)
+            .context("Resetting the working directory")?;
+
+        self.cleanup_state()?;
+        self.rollback_sync()
+    }
+
+    ///
+    /// Finish syncing the commit that the paused sync stopped at, using the conflict
+    /// resolution in the index.
+    ///
+    pub(crate) fn continue_sync(&self) -> anyhow::Result<TrackedCommit<'_>> {
+        let state = self
+            .sync_state
+            .as_ref()
+            .context("There is no sync to continue")?;
         let tree = self.repo.index()?.write_tree()?;
         let tree = self.repo.find_tree(tree)?;
-        let author = self.repo.signature()?;
 
         tracing::info!("Continuing previous sync {:?}", state);
 
-        let merge_commit_id = self.repo.commit(
-            None,
-            &author,
-            &author,
-            "Merge",
-            &tree,
-            &[
-                &self.repo.head()?.peel_to_commit()?,
-                &self.repo.find_commit(state.remote_commit_id.into())?,
-            ],
-        )?;
+        let merge_commit = match state.phase {
+            SyncPhase::MergeRemoteHead => {
+                let author = self.repo.signature()?;
+                let merge_commit_id = self.repo.commit(
+                    None,
+                    &author,
+                    &author,
+                    "Merge",
+                    &tree,
+                    &[
+                        &self.repo.head()?.peel_to_commit()?,
+                        &self.repo.find_commit(state.remote_commit_id.into())?,
+                    ],
+                )?;
+                Some(self.repo.find_commit(merge_commit_id)?)
+            }
+            SyncPhase::CherryPick => {
+                // The resolution is kept in `tree`, the work tree is updated when the main
+                // branch is moved
+                self.repo.reset(
+                    self.repo.head()?.peel_to_commit()?.as_object(),
+                    git2::ResetType::Hard,
+                    None,
+                )?;
+                None
+            }
+        };
         self.repo.cleanup_state()?;
         self.cleanup_state()?;
-        let tracked_commit =
-            match self.find_unpushed_commit(&format!("{}", state.main_commit_id))? {
-                MainCommit::UnTracked(_) => todo!(),
-                MainCommit::Tracked(commit) => commit,
-            };
+        // The base branch may have moved on, so the commit need not be unpushed anymore
+        let main_commit = self.repo.find_commit(state.main_commit_id.into())?;
+        let tracked_commit = match MainCommit::new(self, &self.repo, main_commit)? {
+            MainCommit::UnTracked(_) => anyhow::bail!(
+                "Commit {} is no longer tracked, run 'ubr sync --abort'",
+                state.main_commit_id
+            ),
+            MainCommit::Tracked(commit) => commit,
+        };
+        let main_commit_parent = self.repo.find_commit(state.main_commit_parent_id.into())?;
+        let parent_head = match &tracked_commit.meta_data().parent_branch {
+            Some(parent_branch) => self
+                .find_tracked_branch(&main_commit_parent, parent_branch)?
+                .map(|parent| parent.meta_data().remote_commit),
+            None => None,
+        };
+        let tracked_commit = match parent_head {
+            Some(parent_head) => tracked_commit.with_base(parent_head),
+            None => tracked_commit,
+        };
 
-        tracked_commit.cont(
-            &self.repo.find_commit(merge_commit_id)?,
-            Some(&self.repo.find_commit(state.main_commit_parent_id.into())?),
-        )
+        match merge_commit {
+            Some(merge_commit) => {
+                Ok(tracked_commit.cont(&merge_commit, Some(&main_commit_parent))?)
+            }
+            None => Ok(tracked_commit
+                .cont_update_local_branch_head(state.phase, &tree)?
+                .merge_remote_head(Some(&main_commit_parent))?),
+        }
     }
 }
 
+///
+/// Whether `note` holds `CommitMetadata` or group membership, i.e. was written by ubr.
+///
+fn is_meta_data_note(note: &str) -> bool {
+    note.parse::<CommitMetadata>().is_ok() || local_commit::parse_group_member_note(note).is_some()
+}
+
+///
+/// The signature to write notes on `commit_id` with: the configured user, or the committer of
+/// the commit if there is none.
+///
+fn note_signature(
+    repo: &Repository,
+    commit_id: git2::Oid,
+) -> anyhow::Result<git2::Signature<'static>> {
+    Ok(repo.signature().or_else(|_| {
+        let commit = repo.find_commit(commit_id)?;
+        let committer = commit.committer();
+        git2::Signature::now(
+            String::from_utf8_lossy(committer.name_bytes()).as_ref(),
+            String::from_utf8_lossy(committer.email_bytes()).as_ref(),
+        )
+    })?)
+}
+
+fn escape_regex(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || matches!(c, '/' | '_' | '-') {
+                c.to_string()
+            } else {
+                format!("\\{}", c)
+            }
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod test {
     use std::fs::File;
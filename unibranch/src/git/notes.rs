@@ -0,0 +1,145 @@
+use std::collections::{BTreeSet, HashMap};
+
+use git2::{Oid, Repository, TreeWalkMode, TreeWalkResult};
+use tracing::warn;
+
+use super::local_commit::CommitMetadata;
+
+///
+/// The notes of the notes commit `notes_commit`, by annotated commit.
+///
+pub(crate) fn read_notes(
+    repo: &Repository,
+    notes_commit: Option<Oid>,
+) -> anyhow::Result<HashMap<Oid, String>> {
+    let mut notes = HashMap::new();
+    let Some(notes_commit) = notes_commit else {
+        return Ok(notes);
+    };
+    let tree = repo.find_commit(notes_commit)?.tree()?;
+    let mut entries = Vec::new();
+    tree.walk(TreeWalkMode::PreOrder, |root, entry| {
+        if entry.kind() == Some(git2::ObjectType::Blob) {
+            // Notes are stored by object id, split into directories ("fanout") in large trees
+            let path = format!(
+                "{}{}",
+                root.replace('/', ""),
+                entry.name().unwrap_or_default()
+            );
+            if let Ok(commit_id) = Oid::from_str(&path) {
+                if path.len() == 40 {
+                    entries.push((commit_id, entry.id()));
+                }
+            }
+        }
+        TreeWalkResult::Ok
+    })?;
+    for (commit_id, blob_id) in entries {
+        let blob = repo.find_blob(blob_id)?;
+        notes.insert(
+            commit_id,
+            String::from_utf8_lossy(blob.content()).into_owned(),
+        );
+    }
+    Ok(notes)
+}
+
+///
+/// The changes to make to the notes at `ours` to merge in the notes at `theirs`: the new note
+/// of every commit whose note changes, `None` to remove it.
+///
+/// Notes only changed on one side since `base`, the notes both sides started from, take that
+/// change. Notes changed on both sides keep ours, unless both are the metadata of the same
+/// virtual branch and theirs was synced with a newer remote commit.
+///
+pub(crate) fn merge_notes(
+    repo: &Repository,
+    base: &HashMap<Oid, String>,
+    ours: &HashMap<Oid, String>,
+    theirs: &HashMap<Oid, String>,
+) -> Vec<(Oid, Option<String>)> {
+    let commits: BTreeSet<&Oid> = ours.keys().chain(theirs.keys()).collect();
+    let mut changes = Vec::new();
+    for commit in commits {
+        let (base, ours, theirs) = (base.get(commit), ours.get(commit), theirs.get(commit));
+        if ours == theirs || theirs == base {
+            continue;
+        }
+        if ours == base {
+            changes.push((*commit, theirs.cloned()));
+            continue;
+        }
+        match (ours, theirs) {
+            (None, Some(theirs)) => changes.push((*commit, Some(theirs.clone()))),
+            (Some(_), None) => {}
+            (Some(ours), Some(theirs)) => {
+                if is_newer(repo, theirs, ours) {
+                    changes.push((*commit, Some(theirs.clone())));
+                } else {
+                    warn!(
+                        "The metadata of commit {} was changed both locally and on the remote, keeping the local one",
+                        commit
+                    );
+                }
+            }
+            (None, None) => {}
+        }
+    }
+    changes
+}
+
+///
+/// Whether `theirs` is the metadata of the same virtual branch as `ours`, synced with a remote
+/// commit that comes after the one of `ours`.
+///
+fn is_newer(repo: &Repository, theirs: &str, ours: &str) -> bool {
+    let (Ok(theirs), Ok(ours)) = (
+        theirs.parse::<CommitMetadata>(),
+        ours.parse::<CommitMetadata>(),
+    ) else {
+        return false;
+    };
+    theirs.remote_branch_name == ours.remote_branch_name
+        && repo
+            .graph_descendant_of(theirs.remote_commit, ours.remote_commit)
+            .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use git2::{Oid, Repository};
+
+    use super::merge_notes;
+
+    fn notes(entries: &[(u8, &str)]) -> HashMap<Oid, String> {
+        entries
+            .iter()
+            .map(|(id, note)| (Oid::from_bytes(&[*id; 20]).unwrap(), note.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_merge_notes() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        let base = notes(&[(1, "unchanged"), (2, "deleted by them"), (3, "changed")]);
+        let ours = notes(&[(1, "unchanged"), (2, "deleted by them"), (3, "ours")]);
+        let theirs = notes(&[(1, "unchanged"), (3, "theirs"), (4, "added by them")]);
+
+        let mut changes = merge_notes(&repo, &base, &ours, &theirs);
+        changes.sort();
+
+        assert_eq!(
+            changes,
+            vec![
+                (Oid::from_bytes(&[2; 20]).unwrap(), None),
+                (
+                    Oid::from_bytes(&[4; 20]).unwrap(),
+                    Some("added by them".to_string())
+                ),
+            ]
+        );
+    }
+}
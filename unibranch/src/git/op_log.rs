@@ -0,0 +1,144 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use super::Oid;
+
+/// In the git directory, so that the log never shows up in the work tree
+const OP_LOG_FILE: &str = "UBR_OP_LOG";
+const MAX_OPERATIONS: usize = 100;
+
+#[derive(Serialize, Deserialize, Eq, PartialEq, Debug, Clone)]
+pub struct NoteSnapshot {
+    pub commit: Oid,
+    pub note: String,
+}
+
+#[derive(Serialize, Deserialize, Eq, PartialEq, Debug, Clone)]
+pub struct RemoteHead {
+    pub branch: String,
+    pub head: Option<Oid>,
+}
+
+///
+/// The state of the main branch and all virtual branches just before an operation ran.
+///
+#[derive(Serialize, Deserialize, Eq, PartialEq, Debug, Clone)]
+pub struct Snapshot {
+    pub main_branch_name: String,
+    pub main_branch_head: Oid,
+    pub notes: Vec<NoteSnapshot>,
+    pub remote_heads: Vec<RemoteHead>,
+}
+
+impl Snapshot {
+    ///
+    /// Remember the head a remote branch had before the operation pushed to it. Only the first
+    /// push of a branch within an operation is recorded.
+    ///
+    pub(crate) fn record_remote_head(&mut self, branch: &str, head: Option<Oid>) {
+        if !self.remote_heads.iter().any(|r| r.branch == branch) {
+            self.remote_heads.push(RemoteHead {
+                branch: branch.to_string(),
+                head,
+            });
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Eq, PartialEq, Debug, Clone)]
+pub struct Operation {
+    pub id: u64,
+    pub command: String,
+    pub time: i64,
+    pub snapshot: Snapshot,
+}
+
+///
+/// Log of the last operations (`create`, `sync`, `push`, ...) together with a snapshot of
+/// the state from before each of them, so that they can be undone.
+///
+#[derive(Serialize, Deserialize, Eq, PartialEq, Debug, Clone, Default)]
+pub struct OpLog {
+    pub operations: Vec<Operation>,
+}
+
+impl OpLog {
+    pub fn path<P>(git_dir: P) -> PathBuf
+    where
+        P: AsRef<Path>,
+    {
+        git_dir.as_ref().join(OP_LOG_FILE)
+    }
+
+    pub fn load<P>(git_dir: P) -> anyhow::Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        match std::fs::File::open(OpLog::path(git_dir)) {
+            Ok(file) => serde_json::from_reader(file).context("Reading operation log"),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(OpLog::default()),
+            Err(e) => Err(e).context("Opening operation log"),
+        }
+    }
+
+    pub(crate) fn save<P>(&self, git_dir: P) -> anyhow::Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        let path = OpLog::path(git_dir);
+        let tmp_path = path.with_extension("tmp");
+        serde_json::to_writer(std::fs::File::create(&tmp_path)?, self)?;
+        std::fs::rename(&tmp_path, &path).context("Writing operation log")
+    }
+
+    pub(crate) fn push(&mut self, command: &str, snapshot: Snapshot) -> u64 {
+        let id = self.operations.last().map(|op| op.id + 1).unwrap_or(1);
+        let time = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or(0);
+        self.operations.push(Operation {
+            id,
+            command: command.to_string(),
+            time,
+            snapshot,
+        });
+        if self.operations.len() > MAX_OPERATIONS {
+            self.operations.remove(0);
+        }
+        id
+    }
+
+    pub fn find(&self, id: u64) -> Option<&Operation> {
+        self.operations.iter().find(|op| op.id == id)
+    }
+
+    pub fn last(&self) -> Option<&Operation> {
+        self.operations.last()
+    }
+
+    ///
+    /// Replace the snapshot of the operation `id`, e.g. with the pushes of a continued sync.
+    ///
+    pub(crate) fn update(&mut self, id: u64, snapshot: Snapshot) {
+        if let Some(op) = self.operations.iter_mut().find(|op| op.id == id) {
+            op.snapshot = snapshot;
+        }
+    }
+
+    pub(crate) fn remove(&mut self, id: u64) {
+        self.operations.retain(|op| op.id != id);
+    }
+}
+
+///
+/// An operation that is still running. It is only added to the log once it succeeded.
+///
+pub(crate) struct PendingOperation {
+    /// The id of the logged operation this continues, e.g. a sync that was paused
+    pub(crate) id: Option<u64>,
+    pub(crate) command: String,
+    pub(crate) snapshot: Snapshot,
+}
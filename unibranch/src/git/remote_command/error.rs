@@ -0,0 +1,72 @@
+use std::{error::Error, fmt::Display};
+
+use git2::Oid;
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum RefPushError {
+    /// The remote refused to update the reference.
+    Rejected { reference: String, message: String },
+    /// The remote reference no longer points to the remote commit we last synced with.
+    Stale {
+        reference: String,
+        expected: Option<Oid>,
+        actual: Option<Oid>,
+    },
+}
+
+impl RefPushError {
+    pub fn reference(&self) -> &str {
+        match self {
+            RefPushError::Rejected { reference, .. } => reference,
+            RefPushError::Stale { reference, .. } => reference,
+        }
+    }
+}
+
+impl Display for RefPushError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RefPushError::Rejected { reference, message } => {
+                write!(f, "{} rejected: {}", reference, message)
+            }
+            RefPushError::Stale {
+                reference,
+                expected,
+                actual,
+            } => {
+                let describe = |oid: &Option<Oid>| match oid {
+                    Some(oid) => format!("{}", oid),
+                    None => "nothing".to_string(),
+                };
+                write!(
+                    f,
+                    "{}: remote changed since last sync, run ubr sync (expected {} but found {})",
+                    reference,
+                    describe(expected),
+                    describe(actual)
+                )
+            }
+        }
+    }
+}
+
+///
+/// A push where the remote did not accept all references.
+///
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct PushError {
+    pub remote: String,
+    pub refs: Vec<RefPushError>,
+}
+
+impl Display for PushError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Push to {} failed", self.remote)?;
+        for error in &self.refs {
+            write!(f, "\n  {}", error)?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for PushError {}
@@ -1,53 +1,372 @@
-use std::{
-    path::Path,
-    process::{Command, Stdio},
+use std::{cell::RefCell, collections::HashMap, path::Path};
+
+use anyhow::Context;
+use git2::{
+    Config, Cred, CredentialType, FetchOptions, FetchPrune, Oid, PushOptions, RemoteCallbacks,
+    Repository,
 };
 
 use super::local_commit::CommitMetadata;
 
+mod error;
+pub use error::{PushError, RefPushError};
+
 pub enum RemoteGitCommand<'a> {
-    Default(&'a Path),
-    Silent(&'a Path),
-    DryRun(&'a Path),
+    Default(&'a Path, &'a str),
+    Silent(&'a Path, &'a str),
+    DryRun(&'a Path, &'a str),
+}
+
+struct RefUpdate<'a> {
+    /// A branch name, or the full name of any other reference
+    branch: &'a str,
+    new_head: Option<Oid>,
+    lease: Lease,
+}
+
+impl RefUpdate<'_> {
+    fn reference(&self) -> String {
+        if self.branch.starts_with("refs/") {
+            self.branch.to_string()
+        } else {
+            format!("refs/heads/{}", self.branch)
+        }
+    }
+}
+
+enum Lease {
+    /// Update the branch whatever it points to
+    None,
+    /// Only update the branch if it still points to the remote commit we last synced with
+    Synced,
+    /// Only update the branch if it points to this commit, or doesn't exist if `None`
+    Expect(Option<Oid>),
 }
 
 impl RemoteGitCommand<'_> {
     pub fn push(&self, meta_data: &CommitMetadata) -> anyhow::Result<()> {
+        self.push_branch(&meta_data.remote_branch_name, meta_data.remote_commit)
+    }
+
+    pub fn push_branch(&self, branch: &str, commit: Oid) -> anyhow::Result<()> {
+        self.push_update(RefUpdate {
+            branch,
+            new_head: Some(commit),
+            lease: Lease::Synced,
+        })
+    }
+
+    ///
+    /// Push the branch of `meta_data` over `previous`, the commit we pushed to it before, even
+    /// if the new commit doesn't build on it.
+    ///
+    pub fn push_replacing(&self, meta_data: &CommitMetadata, previous: Oid) -> anyhow::Result<()> {
+        self.push_update(RefUpdate {
+            branch: &meta_data.remote_branch_name,
+            new_head: Some(meta_data.remote_commit),
+            lease: Lease::Expect(Some(previous)),
+        })
+    }
+
+    ///
+    /// Push the branches of all `meta_data` in a single push. If any of the remote branches has
+    /// changed since it was last synced, none of them are updated.
+    ///
+    pub fn push_all(&self, meta_data: &[CommitMetadata]) -> anyhow::Result<()> {
+        if meta_data.is_empty() {
+            return Ok(());
+        }
+        let updates: Vec<RefUpdate> = meta_data
+            .iter()
+            .map(|meta_data| RefUpdate {
+                branch: &meta_data.remote_branch_name,
+                new_head: Some(meta_data.remote_commit),
+                lease: Lease::Synced,
+            })
+            .collect();
+        match self {
+            RemoteGitCommand::Default(path, remote) => {
+                RemoteGitCommand::push_native(path, remote, &updates, false)
+            }
+            RemoteGitCommand::Silent(path, remote) => {
+                RemoteGitCommand::push_native(path, remote, &updates, true)
+            }
+            RemoteGitCommand::DryRun(_, remote) => {
+                for meta_data in meta_data {
+                    println!(
+                        "Pushing commit {} to {}/{}",
+                        meta_data.remote_commit, remote, meta_data.remote_branch_name
+                    );
+                }
+                Ok(())
+            }
+        }
+    }
+
+    ///
+    /// Force the remote branch to point to `head`, or delete it if `head` is `None`.
+    ///
+    pub fn force_push(&self, branch: &str, head: Option<Oid>) -> anyhow::Result<()> {
+        self.push_update(RefUpdate {
+            branch,
+            new_head: head,
+            lease: Lease::None,
+        })
+    }
+
+    pub fn delete_branch(&self, branch: &str) -> anyhow::Result<()> {
+        self.force_push(branch, None)
+    }
+
+    ///
+    /// Update the remote-tracking branches of `branches` from the remote. Remote-tracking
+    /// branches whose branch was deleted on the remote are removed.
+    ///
+    pub fn fetch(&self, branches: &[&str]) -> anyhow::Result<()> {
+        if branches.is_empty() {
+            return Ok(());
+        }
+        match self {
+            RemoteGitCommand::Default(path, remote) | RemoteGitCommand::Silent(path, remote) => {
+                let refspecs: Vec<String> = branches
+                    .iter()
+                    .map(|branch| format!("+refs/heads/{0}:refs/remotes/{1}/{0}", branch, remote))
+                    .collect();
+                RemoteGitCommand::fetch_native(path, remote, &refspecs)
+            }
+            RemoteGitCommand::DryRun(_, remote) => {
+                println!("Fetching {} from {}", branches.join(", "), remote);
+                Ok(())
+            }
+        }
+    }
+
+    ///
+    /// Fetch the notes ref `notes_ref` of the remote into `tracking_ref`, or remove
+    /// `tracking_ref` if the remote has no such notes.
+    ///
+    pub fn fetch_notes(&self, notes_ref: &str, tracking_ref: &str) -> anyhow::Result<()> {
         match self {
-            RemoteGitCommand::Default(path) => {
-                RemoteGitCommand::push_real(path, meta_data, Stdio::inherit)
+            RemoteGitCommand::Default(path, remote) | RemoteGitCommand::Silent(path, remote) => {
+                RemoteGitCommand::fetch_native(
+                    path,
+                    remote,
+                    &[format!("+{}:{}", notes_ref, tracking_ref)],
+                )
             }
-            RemoteGitCommand::Silent(path) => {
-                RemoteGitCommand::push_real(path, meta_data, Stdio::null)
+            RemoteGitCommand::DryRun(_, remote) => {
+                println!("Fetching {} from {}", notes_ref, remote);
+                Ok(())
             }
-            RemoteGitCommand::DryRun(_) => {
-                println!(
-                    "Pushing commit {} to origin/{}",
-                    meta_data.remote_commit, meta_data.remote_branch_name
-                );
+        }
+    }
+
+    ///
+    /// Push the notes ref `notes_ref` if the remote one still points to `expected`, the notes
+    /// last fetched from it, or doesn't exist if `None`.
+    ///
+    pub fn push_notes(
+        &self,
+        notes_ref: &str,
+        head: Oid,
+        expected: Option<Oid>,
+    ) -> anyhow::Result<()> {
+        self.push_update(RefUpdate {
+            branch: notes_ref,
+            new_head: Some(head),
+            lease: Lease::Expect(expected),
+        })
+    }
+
+    fn fetch_native(path: &Path, remote_name: &str, refspecs: &[String]) -> anyhow::Result<()> {
+        let repo = Repository::open(path).context("Opening repository to fetch into")?;
+        let config = repo.config()?;
+        let mut remote = repo
+            .find_remote(remote_name)
+            .with_context(|| format!("Finding remote {}", remote_name))?;
+        let mut options = FetchOptions::new();
+        options
+            .remote_callbacks(remote_callbacks(&config))
+            .prune(FetchPrune::On);
+        remote
+            .fetch(refspecs, Some(&mut options), None)
+            .with_context(|| format!("Fetching from {}", remote_name))
+    }
+
+    fn push_update(&self, update: RefUpdate) -> anyhow::Result<()> {
+        match self {
+            RemoteGitCommand::Default(path, remote) => {
+                RemoteGitCommand::push_native(path, remote, &[update], false)
+            }
+            RemoteGitCommand::Silent(path, remote) => {
+                RemoteGitCommand::push_native(path, remote, &[update], true)
+            }
+            RemoteGitCommand::DryRun(_, remote) => {
+                match (update.new_head, update.lease) {
+                    (Some(head), Lease::None) => println!(
+                        "Force pushing commit {} to {}/{}",
+                        head, remote, update.branch
+                    ),
+                    (Some(head), _) => {
+                        println!("Pushing commit {} to {}/{}", head, remote, update.branch)
+                    }
+                    (None, _) => println!("Deleting {}/{}", remote, update.branch),
+                }
                 Ok(())
             }
         }
     }
 
-    fn push_real<F>(path: &Path, meta_data: &CommitMetadata, stdio: F) -> anyhow::Result<()>
-    where
-        F: Fn() -> Stdio,
-    {
-        Command::new("git")
-            .current_dir(path)
-            .arg("push")
-            .arg("--no-verify")
-            .arg("--force-with-lease")
-            .arg("--")
-            .arg("origin")
-            .arg(format!(
-                "{}:refs/heads/{}",
-                meta_data.remote_commit, &meta_data.remote_branch_name
-            ))
-            .stderr(stdio())
-            .stdout(stdio())
-            .status()?;
+    ///
+    /// Push all `updates` with libgit2. Fails with a [`PushError`] listing every reference that
+    /// was stale or that the remote rejected.
+    ///
+    fn push_native(
+        path: &Path,
+        remote_name: &str,
+        updates: &[RefUpdate],
+        quiet: bool,
+    ) -> anyhow::Result<()> {
+        let repo = Repository::open(path).context("Opening repository to push from")?;
+        let config = repo.config()?;
+        let mut remote = repo
+            .find_remote(remote_name)
+            .with_context(|| format!("Finding remote {}", remote_name))?;
+
+        // What the branches must still point to on the remote
+        let leases: HashMap<String, Option<Oid>> = updates
+            .iter()
+            .filter_map(|update| {
+                let expected = match update.lease {
+                    Lease::None => return None,
+                    Lease::Synced => expected_remote_head(&repo, remote_name, update),
+                    Lease::Expect(expected) => expected,
+                };
+                Some((update.reference(), expected))
+            })
+            .collect();
+        let refspecs: Vec<String> = updates
+            .iter()
+            .map(|update| match update.new_head {
+                Some(head) => format!("+{}:{}", head, update.reference()),
+                None => format!(":{}", update.reference()),
+            })
+            .collect();
+        let stale = RefCell::new(Vec::new());
+        let rejected = RefCell::new(Vec::new());
+        let result = {
+            let mut callbacks = remote_callbacks(&config);
+            callbacks.push_negotiation(|remote_updates| {
+                for update in remote_updates {
+                    let Some(reference) = update.dst_refname() else {
+                        continue;
+                    };
+                    if let Some(expected) = leases.get(reference) {
+                        let actual = Some(update.src()).filter(|oid| !oid.is_zero());
+                        if *expected != actual {
+                            stale.borrow_mut().push(RefPushError::Stale {
+                                reference: reference.to_string(),
+                                expected: *expected,
+                                actual,
+                            });
+                        }
+                    }
+                }
+                if stale.borrow().is_empty() {
+                    Ok(())
+                } else {
+                    Err(git2::Error::from_str("Remote branches have changed"))
+                }
+            });
+            callbacks.push_update_reference(|reference, status| {
+                if let Some(message) = status {
+                    rejected.borrow_mut().push(RefPushError::Rejected {
+                        reference: reference.to_string(),
+                        message: message.to_string(),
+                    });
+                }
+                Ok(())
+            });
+            let mut options = PushOptions::new();
+            options.remote_callbacks(callbacks);
+            remote.push(&refspecs, Some(&mut options))
+        };
+        let stale = stale.into_inner();
+        if !stale.is_empty() {
+            return Err(PushError {
+                remote: remote_name.to_string(),
+                refs: stale,
+            }
+            .into());
+        }
+        result.with_context(|| format!("Pushing to {}", remote_name))?;
+        let rejected = rejected.into_inner();
+        if !rejected.is_empty() {
+            return Err(PushError {
+                remote: remote_name.to_string(),
+                refs: rejected,
+            }
+            .into());
+        }
+
+        if !quiet {
+            for update in updates {
+                match update.new_head {
+                    Some(head) => println!("Pushed {} to {}/{}", head, remote_name, update.branch),
+                    None => println!("Deleted {}/{}", remote_name, update.branch),
+                }
+            }
+        }
         Ok(())
     }
 }
+
+///
+/// The commit the remote branch of `update` must point to for the push to go ahead.
+///
+/// This is the newest commit of the remote-tracking branch that is part of the pushed history,
+/// i.e. the remote commit we last synced with. Remote commits that were fetched, for instance
+/// by a background `git fetch`, but never merged therefore make the push fail instead of
+/// being overwritten.
+///
+fn expected_remote_head(repo: &Repository, remote_name: &str, update: &RefUpdate) -> Option<Oid> {
+    let remote_head = repo
+        .refname_to_id(&format!("refs/remotes/{}/{}", remote_name, update.branch))
+        .ok()?;
+    match update.new_head {
+        Some(new_head) => repo.merge_base(new_head, remote_head).ok(),
+        None => Some(remote_head),
+    }
+}
+
+///
+/// Callbacks that authenticate with the ssh-agent or the configured credential helper.
+///
+fn remote_callbacks(config: &Config) -> RemoteCallbacks<'_> {
+    let mut callbacks = RemoteCallbacks::new();
+    let mut tried = CredentialType::empty();
+    callbacks.credentials(move |url, username, allowed| {
+        if allowed.contains(CredentialType::USERNAME) {
+            return Cred::username(username.unwrap_or("git"));
+        }
+        if allowed.contains(CredentialType::SSH_KEY) && !tried.contains(CredentialType::SSH_KEY) {
+            tried |= CredentialType::SSH_KEY;
+            return Cred::ssh_key_from_agent(username.unwrap_or("git"));
+        }
+        if allowed.contains(CredentialType::USER_PASS_PLAINTEXT)
+            && !tried.contains(CredentialType::USER_PASS_PLAINTEXT)
+        {
+            tried |= CredentialType::USER_PASS_PLAINTEXT;
+            return Cred::credential_helper(config, url, username);
+        }
+        if allowed.contains(CredentialType::DEFAULT) && !tried.contains(CredentialType::DEFAULT) {
+            tried |= CredentialType::DEFAULT;
+            return Cred::default();
+        }
+        Err(git2::Error::from_str(&format!(
+            "No usable credentials for {}",
+            url
+        )))
+    });
+    callbacks
+}
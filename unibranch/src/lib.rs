@@ -1,2 +1,6 @@
 pub mod commands;
+pub mod error;
+pub mod forge;
 pub mod git;
+
+pub use error::{Error, Result};
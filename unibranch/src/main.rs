@@ -1,10 +1,11 @@
 use anyhow::Context;
-use clap::{command, Parser, Subcommand};
+use clap::{Parser, Subcommand};
 use tracing::level_filters::LevelFilter;
 use tracing_subscriber::EnvFilter;
 use ubr::{
-    commands::{create, push, sync},
+    commands::{adopt, cleanup, create, fetch, op, push, status, sync, untrack},
     git::{CommandOption, GitRepo},
+    Error,
 };
 
 #[derive(Parser)]
@@ -21,13 +22,38 @@ struct Cli {
 
     #[arg(short, long)]
     verbose: bool,
+
+    /// The remote to push virtual branches to [default: ubr.remote or origin]
+    #[arg(long, global = true)]
+    remote: Option<String>,
+
+    /// The remote to take the base branch from [default: ubr.baseRemote, the upstream remote or
+    /// the push remote]
+    #[arg(long, global = true)]
+    base_remote: Option<String>,
 }
 
 #[derive(Subcommand)]
 enum Commands {
     Create(create::Options),
     Sync(sync::Options),
-    Push,
+    /// Fetch the base branch and the remote branches of all virtual branches
+    Fetch(fetch::Options),
+    Push(push::Options),
+    /// Show every unpushed commit, its virtual branch and the status of its pull request
+    #[command(alias = "log")]
+    Status(status::Options),
+    /// Untrack virtual branches that have been merged or whose remote branch was deleted
+    #[command(alias = "land")]
+    Cleanup(cleanup::Options),
+    /// Stop tracking a commit, or range of commits, as a virtual branch
+    #[command(alias = "delete")]
+    Untrack(untrack::Options),
+    /// Track a commit with an existing remote branch, without pushing anything
+    Adopt(adopt::Options),
+    Undo(op::UndoOptions),
+    #[command(subcommand)]
+    Op(op::Command),
 }
 
 fn main() -> anyhow::Result<()> {
@@ -59,12 +85,64 @@ fn main() -> anyhow::Result<()> {
     } else {
         CommandOption::Default
     };
-    let git_repo = GitRepo::open_with_remote(".", remote_option).context("Opening GIT repo")?;
+    let mut git_repo = GitRepo::open_with_remote(".", remote_option).context("Opening GIT repo")?;
+    if let Some(remote) = cli.remote {
+        git_repo = git_repo.with_remote_name(remote);
+    }
+    if let Some(base_remote) = cli.base_remote {
+        git_repo = git_repo.with_base_remote_name(base_remote);
+    }
 
-    match cli.command {
-        Commands::Create(config) => create::execute(config, git_repo)?,
-        Commands::Sync(config) => sync::execute(config, git_repo)?,
-        Commands::Push => push::execute(".")?,
+    let result = match cli.command {
+        Commands::Create(config) => create::execute(config, git_repo),
+        Commands::Sync(config) => sync::execute(config, git_repo),
+        Commands::Fetch(options) => fetch::execute(options, git_repo),
+        Commands::Push(options) => push::execute(options, git_repo),
+        Commands::Status(options) => status::execute(options, git_repo),
+        Commands::Cleanup(options) => cleanup::execute(options, git_repo),
+        Commands::Untrack(options) => untrack::execute(options, git_repo),
+        Commands::Adopt(options) => adopt::execute(options, git_repo),
+        Commands::Undo(options) => op::undo(options, git_repo),
+        Commands::Op(command) => op::execute(command, git_repo),
     };
-    Ok(())
+    result.map_err(|error| match error {
+        // Keep the context chain of the underlying error in the report
+        Error::Other(error) => error,
+        error => error.into(),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use clap::{CommandFactory, Parser};
+
+    use super::{Cli, Commands};
+
+    #[test]
+    fn verify_cli() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn parse_subcommands_with_global_remote() {
+        for args in [
+            vec!["ubr", "undo"],
+            vec!["ubr", "op", "restore", "1"],
+            vec!["ubr", "untrack"],
+            vec!["ubr", "--remote", "fork", "undo"],
+        ] {
+            assert!(Cli::try_parse_from(&args).is_ok(), "{:?}", args);
+        }
+    }
+
+    #[test]
+    fn parse_untrack_delete_remote() {
+        let cli = Cli::try_parse_from(["ubr", "untrack", "--delete-remote", "HEAD~2.."]).unwrap();
+        let Commands::Untrack(options) = cli.command else {
+            panic!("Expected untrack");
+        };
+        assert!(options.delete_remote);
+        assert_eq!(options.commit_ref.as_deref(), Some("HEAD~2.."));
+        assert!(cli.remote.is_none());
+    }
 }
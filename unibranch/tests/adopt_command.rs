@@ -0,0 +1,197 @@
+use pretty_assertions::assert_eq;
+use test_repo::{RemoteRepo, TestRepoWithRemote};
+use ubr::{
+    commands::{adopt, create, sync, untrack},
+    git::GitRepo,
+    Error,
+};
+
+fn git_repo(value: &TestRepoWithRemote) -> GitRepo {
+    GitRepo::open(value.path()).unwrap()
+}
+
+///
+/// Push a branch with the changes made by `change`, as if it was created by hand from another
+/// clone.
+///
+fn push_branch_by_hand<F>(remote_repo: &RemoteRepo, branch: &str, change: F)
+where
+    F: FnOnce(TestRepoWithRemote) -> TestRepoWithRemote,
+{
+    let other_clone = change(remote_repo.clone_repo()).commit_all("By hand");
+    assert!(other_clone
+        .run_command()
+        .args(["push", "origin", &format!("HEAD:refs/heads/{}", branch)])
+        .status()
+        .unwrap()
+        .success());
+}
+
+#[test]
+fn adopt_existing_remote_branch() {
+    let remote_repo = RemoteRepo::new();
+    let local_repo = remote_repo
+        .clone_repo()
+        .create_file("File1", "Hello, World!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Starting on a new feature")
+        .commit_all("feature 1");
+    push_branch_by_hand(&remote_repo, "my-pr", |repo| {
+        repo.append_file("File1", "Starting on a new feature")
+    });
+
+    adopt::execute(adopt::Options::new("HEAD", "my-pr"), git_repo(&local_repo)).unwrap();
+
+    let remote_head = local_repo.rev_parse("origin/my-pr");
+    local_repo.assert_note(
+        "HEAD",
+        format!(
+            "ubr-version: 2\nremote-branch: my-pr\nremote-commit: {}\n",
+            remote_head
+        ),
+    );
+    assert_eq!(
+        local_repo
+            .find_commit_by_reference("refs/remotes/origin/my-pr")
+            .summary(),
+        Some("By hand")
+    );
+
+    // The adopted branch is synced like any other virtual branch
+    let local_repo = local_repo
+        .append_file("File1", "Some local fixes")
+        .commit_all_amend();
+    sync::execute(sync::Options::default(), git_repo(&local_repo)).unwrap();
+    let new_remote_head = local_repo.find_commit_by_reference("refs/remotes/origin/my-pr");
+    assert_eq!(
+        new_remote_head.parent_id(0).unwrap().to_string(),
+        remote_head
+    );
+    local_repo.assert_diff("HEAD", "origin/my-pr", "");
+}
+
+#[test]
+fn adopt_remote_branch_with_other_changes() {
+    let remote_repo = RemoteRepo::new();
+    let local_repo = remote_repo
+        .clone_repo()
+        .create_file("File1", "Hello, World!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Starting on a new feature")
+        .commit_all("feature 1");
+    push_branch_by_hand(&remote_repo, "my-pr", |repo| {
+        repo.create_file("File2", "Something else")
+    });
+
+    adopt::execute(adopt::Options::new("HEAD", "my-pr"), git_repo(&local_repo)).unwrap();
+
+    assert!(local_repo
+        .find_note("HEAD")
+        .starts_with("ubr-version: 2\nremote-branch: my-pr\n"));
+    assert_eq!(
+        local_repo.rev_parse("origin/my-pr"),
+        remote_repo.clone_repo().rev_parse("origin/my-pr")
+    );
+}
+
+#[test]
+fn adopt_missing_remote_branch() {
+    let remote_repo = RemoteRepo::new();
+    let local_repo = remote_repo
+        .clone_repo()
+        .create_file("File1", "Hello, World!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Starting on a new feature")
+        .commit_all("feature 1");
+
+    let result = adopt::execute(adopt::Options::new("HEAD", "my-pr"), git_repo(&local_repo));
+
+    assert!(result.is_err());
+    assert_eq!(local_repo.find_note("HEAD"), "");
+}
+
+#[test]
+fn adopt_tracked_commit() {
+    let remote_repo = RemoteRepo::new();
+    let local_repo = remote_repo
+        .clone_repo()
+        .create_file("File1", "Hello, World!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Starting on a new feature")
+        .commit_all("feature 1");
+    create::execute(create::Options::default(), git_repo(&local_repo)).unwrap();
+    push_branch_by_hand(&remote_repo, "my-pr", |repo| {
+        repo.append_file("File1", "Starting on a new feature")
+    });
+
+    let result = adopt::execute(adopt::Options::new("HEAD", "my-pr"), git_repo(&local_repo));
+
+    assert!(matches!(
+        result,
+        Err(Error::CommitAlreadyTracked { branch, .. }) if branch == "feature-1"
+    ));
+}
+
+#[test]
+fn adopt_branch_of_other_virtual_branch() {
+    let remote_repo = RemoteRepo::new();
+    let local_repo = remote_repo
+        .clone_repo()
+        .create_file("File1", "Hello, World!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Starting on a new feature")
+        .commit_all("feature 1")
+        .create_file("File2", "Another feature")
+        .commit_all("feature 2");
+    create::execute(
+        create::Options::default().with_commit_ref("HEAD^"),
+        git_repo(&local_repo),
+    )
+    .unwrap();
+    let feature_1 = local_repo.rev_parse("HEAD^");
+
+    let result = adopt::execute(
+        adopt::Options::new("HEAD", "feature-1"),
+        git_repo(&local_repo),
+    );
+
+    assert!(matches!(
+        result,
+        Err(Error::BranchAlreadyTracked { branch, commit })
+            if branch == "feature-1" && commit.to_string() == feature_1
+    ));
+    assert_eq!(local_repo.find_note("HEAD"), "");
+}
+
+#[test]
+fn adopt_branch_after_untracking_an_amended_commit() {
+    let remote_repo = RemoteRepo::new();
+    let local_repo = remote_repo
+        .clone_repo()
+        .create_file("File1", "Hello, World!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Starting on a new feature")
+        .commit_all("feature 1");
+    create::execute(create::Options::default(), git_repo(&local_repo)).unwrap();
+    let local_repo = local_repo
+        .append_file("File1", "Some local fixes")
+        .commit_all_amend();
+    untrack::execute(untrack::Options::default(), git_repo(&local_repo)).unwrap();
+
+    //The note of the commit from before the amend is still there
+    adopt::execute(
+        adopt::Options::new("HEAD", "feature-1"),
+        git_repo(&local_repo),
+    )
+    .unwrap();
+
+    assert!(local_repo
+        .find_note("HEAD")
+        .contains("remote-branch: feature-1\n"));
+}
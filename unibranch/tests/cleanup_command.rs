@@ -0,0 +1,134 @@
+use pretty_assertions::assert_eq;
+use test_repo::{RemoteRepo, TestRepoWithRemote};
+use ubr::{
+    commands::{cleanup, create, sync},
+    git::GitRepo,
+};
+
+fn git_repo(value: &TestRepoWithRemote) -> GitRepo {
+    GitRepo::open(value.path()).unwrap()
+}
+
+fn squash_merge(remote_repo: &RemoteRepo, branch: &str) {
+    let other_clone = remote_repo.clone_repo();
+    assert!(other_clone
+        .run_command()
+        .args(["merge", "--squash", &format!("origin/{}", branch)])
+        .status()
+        .unwrap()
+        .success());
+    other_clone
+        .commit_all(&format!("Squashed {}", branch))
+        .push();
+}
+
+#[test]
+fn cleanup_merged_branch() {
+    let remote_repo = RemoteRepo::new();
+    let local_repo = remote_repo
+        .clone_repo()
+        .create_file("File1", "Hello, World!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Starting on a new feature")
+        .commit_all("feature 1")
+        .create_file("File2", "Another feature")
+        .commit_all("feature 2");
+
+    create::execute(
+        create::Options::default().with_commit_ref("HEAD^"),
+        git_repo(&local_repo),
+    )
+    .unwrap();
+    squash_merge(&remote_repo, "feature-1");
+    let local_repo = local_repo.fetch();
+
+    cleanup::execute(
+        cleanup::Options::default().with_delete_remote(),
+        git_repo(&local_repo),
+    )
+    .unwrap();
+
+    local_repo.assert_log(vec!["feature 2\n", "commit1\n"]);
+    assert_eq!(local_repo.find_note("HEAD"), "");
+    assert!(local_repo.ls_remote_heads("feature-1").stdout.is_empty());
+    local_repo.assert_workdir_is_clean();
+}
+
+#[test]
+fn cleanup_deleted_remote_branch() {
+    let remote_repo = RemoteRepo::new();
+    let local_repo = remote_repo
+        .clone_repo()
+        .create_file("File1", "Hello, World!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Starting on a new feature")
+        .commit_all("feature 1");
+
+    create::execute(create::Options::default(), git_repo(&local_repo)).unwrap();
+    let head = local_repo.head();
+    assert!(local_repo
+        .run_command()
+        .args(["push", "origin", "--delete", "feature-1"])
+        .status()
+        .unwrap()
+        .success());
+
+    cleanup::execute(cleanup::Options::default(), git_repo(&local_repo)).unwrap();
+
+    assert_eq!(local_repo.head(), head);
+    assert_eq!(local_repo.find_note("HEAD"), "");
+}
+
+#[test]
+fn cleanup_without_landed_branches() {
+    let remote_repo = RemoteRepo::new();
+    let local_repo = remote_repo
+        .clone_repo()
+        .create_file("File1", "Hello, World!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Starting on a new feature")
+        .commit_all("feature 1");
+
+    create::execute(create::Options::default(), git_repo(&local_repo)).unwrap();
+    let head = local_repo.head();
+    let note = local_repo.find_note("HEAD");
+
+    cleanup::execute(cleanup::Options::default(), git_repo(&local_repo)).unwrap();
+
+    assert_eq!(local_repo.head(), head);
+    local_repo.assert_note("HEAD", note);
+}
+
+#[test]
+fn sync_drops_merged_branch() {
+    let remote_repo = RemoteRepo::new();
+    let local_repo = remote_repo
+        .clone_repo()
+        .create_file("File1", "Hello, World!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Starting on a new feature")
+        .commit_all("feature 1")
+        .create_file("File2", "Another feature")
+        .commit_all("feature 2");
+
+    create::execute(
+        create::Options::default().with_commit_ref("HEAD^"),
+        git_repo(&local_repo),
+    )
+    .unwrap();
+    create::execute(create::Options::default(), git_repo(&local_repo)).unwrap();
+    squash_merge(&remote_repo, "feature-1");
+    let local_repo = local_repo.fetch();
+
+    sync::execute(sync::Options::default(), git_repo(&local_repo)).unwrap();
+
+    local_repo.assert_log(vec!["feature 2\n", "Squashed feature-1\n"]);
+    assert!(local_repo
+        .find_note("HEAD")
+        .starts_with("ubr-version: 2\nremote-branch: feature-2\n"));
+    local_repo.assert_workdir_is_clean();
+}
@@ -0,0 +1,180 @@
+use test_repo::{RemoteRepo, TestRepoWithRemote};
+use ubr::{
+    commands::{create, sync},
+    git::GitRepo,
+};
+
+fn git_repo(value: &TestRepoWithRemote) -> GitRepo {
+    GitRepo::open(value.path()).unwrap()
+}
+
+fn diff(local_repo: &TestRepoWithRemote, rev1: &str, rev2: &str) -> String {
+    String::from_utf8(local_repo.diff(rev1, rev2).stdout).unwrap()
+}
+
+fn local_repo_with_three_commits(remote_repo: &RemoteRepo) -> TestRepoWithRemote<'_> {
+    remote_repo
+        .clone_repo()
+        .create_file("File1", "Hello, World!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Starting on a new feature")
+        .commit_all("feature 1 part 1")
+        .create_file("File2", "More of the feature")
+        .commit_all("feature 1 part 2")
+        .append_file("File1", "Finishing the feature")
+        .commit_all("feature 1 part 3")
+}
+
+#[test]
+fn create_branch_from_range() {
+    let remote_repo = RemoteRepo::new();
+    let local_repo = local_repo_with_three_commits(&remote_repo);
+
+    create::execute(
+        create::Options::default()
+            .with_commit_ref("HEAD~3..HEAD")
+            .with_name("feature-1"),
+        git_repo(&local_repo),
+    )
+    .unwrap();
+
+    assert_eq!(
+        local_repo.find_note("HEAD~2"),
+        "ubr-version: 2\ngroup-branch: feature-1\n"
+    );
+    assert_eq!(
+        local_repo.find_note("HEAD~1"),
+        "ubr-version: 2\ngroup-branch: feature-1\n"
+    );
+    assert!(local_repo
+        .find_note("HEAD")
+        .starts_with("ubr-version: 2\nremote-branch: feature-1\n"));
+
+    let local_repo = local_repo.fetch();
+    local_repo.assert_diff(
+        "origin/master",
+        "origin/feature-1",
+        &diff(&local_repo, "HEAD~3", "HEAD"),
+    );
+}
+
+#[test]
+fn create_from_range_with_pushed_commits() {
+    let remote_repo = RemoteRepo::new();
+    let local_repo = local_repo_with_three_commits(&remote_repo);
+
+    assert!(create::execute(
+        create::Options::default().with_commit_ref("HEAD~4..HEAD"),
+        git_repo(&local_repo),
+    )
+    .is_err());
+}
+
+#[test]
+fn create_on_commit_in_range() {
+    let remote_repo = RemoteRepo::new();
+    let local_repo = local_repo_with_three_commits(&remote_repo);
+
+    create::execute(
+        create::Options::default().with_commit_ref("HEAD~3.."),
+        git_repo(&local_repo),
+    )
+    .unwrap();
+
+    assert!(create::execute(
+        create::Options::default().with_commit_ref("HEAD^"),
+        git_repo(&local_repo),
+    )
+    .is_err());
+}
+
+#[test]
+fn sync_range_with_local_changes() {
+    let remote_repo = RemoteRepo::new();
+    let local_repo = local_repo_with_three_commits(&remote_repo);
+
+    create::execute(
+        create::Options::default()
+            .with_commit_ref("HEAD~3..HEAD")
+            .with_name("feature-1"),
+        git_repo(&local_repo),
+    )
+    .unwrap();
+
+    let part_2 = local_repo.find_commit(1).id();
+    let local_repo = local_repo
+        .append_file("File2", "Review fixes")
+        .commit_all_fixup(part_2);
+
+    sync::execute(sync::Options::default(), git_repo(&local_repo)).unwrap();
+
+    local_repo.assert_log(vec![
+        "feature 1 part 3\n",
+        "feature 1 part 2\n",
+        "feature 1 part 1\n",
+        "commit1\n",
+    ]);
+    assert_eq!(
+        local_repo.find_note("HEAD~2"),
+        "ubr-version: 2\ngroup-branch: feature-1\n"
+    );
+    assert_eq!(
+        local_repo.find_note("HEAD~1"),
+        "ubr-version: 2\ngroup-branch: feature-1\n"
+    );
+    let local_repo = local_repo.fetch();
+    local_repo.assert_diff(
+        "origin/master",
+        "origin/feature-1",
+        &diff(&local_repo, "HEAD~3", "HEAD"),
+    );
+}
+
+#[test]
+fn sync_range_with_remote_changes() {
+    let remote_repo = RemoteRepo::new();
+    let local_repo = local_repo_with_three_commits(&remote_repo);
+
+    create::execute(
+        create::Options::default()
+            .with_commit_ref("HEAD~3..HEAD")
+            .with_name("feature-1"),
+        git_repo(&local_repo),
+    )
+    .unwrap();
+
+    {
+        remote_repo
+            .clone_repo()
+            .checkout("feature-1")
+            .create_file("File3", "Remote fixes")
+            .commit_all("Fixup")
+            .push();
+    }
+
+    let local_repo = local_repo.fetch();
+    sync::execute(sync::Options::default(), git_repo(&local_repo)).unwrap();
+
+    local_repo.assert_log(vec![
+        "feature 1 part 3\n",
+        "feature 1 part 2\n",
+        "feature 1 part 1\n",
+        "commit1\n",
+    ]);
+    assert_eq!(
+        local_repo.find_note("HEAD~2"),
+        "ubr-version: 2\ngroup-branch: feature-1\n"
+    );
+    assert_eq!(
+        local_repo.find_note("HEAD~1"),
+        "ubr-version: 2\ngroup-branch: feature-1\n"
+    );
+    let local_repo = local_repo.fetch();
+    assert!(diff(&local_repo, "HEAD^", "HEAD").contains("Remote fixes"));
+    local_repo.assert_diff(
+        "origin/master",
+        "origin/feature-1",
+        &diff(&local_repo, "HEAD~3", "HEAD"),
+    );
+}
@@ -1,7 +1,10 @@
 use git2::Oid;
 use indoc::indoc;
 use test_repo::{RemoteRepo, TestRepoWithRemote};
-use ubr::{commands::create, git::GitRepo};
+use ubr::{
+    commands::{create, untrack},
+    git::GitRepo,
+};
 
 use pretty_assertions::assert_eq;
 
@@ -55,6 +58,7 @@ fn basic_test() {
     assert_eq!(output, expected_diff);
 
     let expected_note = indoc! {"
+            ubr-version: 2
             remote-branch: commit3
             remote-commit: {}
         "};
@@ -114,6 +118,7 @@ fn can_override_the_default_branch_name() {
     assert_eq!(output, expected_diff);
 
     let expected_note = indoc! {"
+            ubr-version: 2
             remote-branch: override-branch-name
             remote-commit: {}
         "};
@@ -167,6 +172,7 @@ fn test_create_from_not_head_commit() {
     assert_eq!(actual_diff, expected_diff);
 
     let expected_note = indoc! {"
+            ubr-version: 2
             remote-branch: commit2
             remote-commit: {}
         "};
@@ -223,4 +229,93 @@ fn force_if_already_tracked() {
     let repo = repo.append_file("File1", "More lines").commit_all_amend();
 
     create::execute(create::Options::default().with_force(), git_repo(&repo)).unwrap();
+
+    assert!(repo
+        .find_note("HEAD")
+        .starts_with("ubr-version: 2\nremote-branch: commit2\n"));
+    repo.assert_diff("HEAD", "origin/commit2", "");
+}
+
+#[test]
+fn generated_name_avoids_existing_branches() {
+    let remote = RemoteRepo::new();
+    let repo = remote
+        .clone_repo()
+        .create_file("File1", "Hello world!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Another Hello, World!")
+        .commit_all("Fix the parser");
+    assert!(repo
+        .run_command()
+        .args(["push", "origin", "origin/master:refs/heads/fix-the-parser"])
+        .status()
+        .unwrap()
+        .success());
+    let repo = repo.fetch();
+
+    create::execute(create_options(None), git_repo(&repo)).unwrap();
+
+    let repo = repo
+        .create_file("File2", "Yet another Hello, World!")
+        .commit_all("Fix the parser");
+    create::execute(create_options(None), git_repo(&repo)).unwrap();
+
+    assert!(repo
+        .find_note("HEAD^")
+        .starts_with("ubr-version: 2\nremote-branch: fix-the-parser-2\n"));
+    assert!(repo
+        .find_note("HEAD")
+        .starts_with("ubr-version: 2\nremote-branch: fix-the-parser-3\n"));
+}
+
+#[test]
+fn generated_name_ignores_notes_of_amended_commits() {
+    let remote = RemoteRepo::new();
+    let repo = remote
+        .clone_repo()
+        .create_file("File1", "Hello world!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Another Hello, World!")
+        .commit_all("Fix the parser");
+    create::execute(create_options(None), git_repo(&repo)).unwrap();
+    let repo = repo
+        .append_file("File1", "Some local fixes")
+        .commit_all_amend();
+    untrack::execute(
+        untrack::Options::default().with_delete_remote(),
+        git_repo(&repo),
+    )
+    .unwrap();
+
+    create::execute(create_options(None), git_repo(&repo)).unwrap();
+
+    assert!(repo
+        .find_note("HEAD")
+        .starts_with("ubr-version: 2\nremote-branch: fix-the-parser\n"));
+}
+
+#[test]
+fn generated_name_follows_the_configured_template() {
+    let remote = RemoteRepo::new();
+    let repo = remote
+        .clone_repo()
+        .create_file("File1", "Hello world!")
+        .commit_all("commit1")
+        .push()
+        .set_config("ubr.branchTemplate", "feature/{ticket}-{slug}")
+        .set_config("ubr.branchMaxLength", "30")
+        .append_file("File1", "Another Hello, World!")
+        .commit_all("PROJ-42: Add a parser for the -- configuration");
+
+    create::execute(create_options(None), git_repo(&repo)).unwrap();
+
+    assert!(repo
+        .find_note("HEAD")
+        .starts_with("ubr-version: 2\nremote-branch: feature/PROJ-42-add-a-parser\n"));
+    assert!(!repo
+        .ls_remote_heads("feature/PROJ-42-add-a-parser")
+        .stdout
+        .is_empty());
 }
@@ -0,0 +1,100 @@
+use test_repo::{RemoteRepo, TestRepoWithRemote};
+use ubr::{
+    commands::{create, sync},
+    git::GitRepo,
+    Error,
+};
+
+fn git_repo(value: &TestRepoWithRemote) -> GitRepo {
+    GitRepo::open(value.path()).unwrap()
+}
+
+#[test]
+fn create_on_tracked_commit() {
+    let remote_repo = RemoteRepo::new();
+    let local_repo = remote_repo
+        .clone_repo()
+        .create_file("File1", "Hello, World!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Starting on a new feature")
+        .commit_all("feature 1");
+
+    create::execute(create::Options::default(), git_repo(&local_repo)).unwrap();
+    let error = create::execute(create::Options::default(), git_repo(&local_repo)).unwrap_err();
+
+    assert!(matches!(
+        error,
+        Error::CommitAlreadyTracked { commit, branch }
+            if commit == local_repo.head() && branch == "feature-1"
+    ));
+}
+
+#[test]
+fn create_on_pushed_commit() {
+    let remote_repo = RemoteRepo::new();
+    let local_repo = remote_repo
+        .clone_repo()
+        .create_file("File1", "Hello, World!")
+        .commit_all("commit1")
+        .push();
+
+    let error = create::execute(create::Options::default(), git_repo(&local_repo)).unwrap_err();
+
+    assert!(matches!(
+        error,
+        Error::AlreadyPushed { commit } if commit == local_repo.head()
+    ));
+}
+
+#[test]
+fn open_with_detached_head() {
+    let remote_repo = RemoteRepo::new();
+    let local_repo = remote_repo
+        .clone_repo()
+        .create_file("File1", "Hello, World!")
+        .commit_all("commit1")
+        .push();
+    assert!(local_repo
+        .run_command()
+        .args(["checkout", "--detach"])
+        .status()
+        .unwrap()
+        .success());
+
+    assert!(matches!(
+        GitRepo::open(local_repo.path()),
+        Err(Error::DetachedHead)
+    ));
+}
+
+#[test]
+fn sync_while_paused() {
+    let remote_repo = RemoteRepo::new();
+    let local_repo = remote_repo
+        .clone_repo()
+        .create_file("File1", "Hello, World!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Starting on a new feature")
+        .commit_all("feature 1");
+
+    create::execute(create::Options::default(), git_repo(&local_repo)).unwrap();
+    remote_repo
+        .clone_repo()
+        .checkout("feature-1")
+        .append_file("File1", "Some remote fixes")
+        .commit_all("Fixup")
+        .push();
+    let local_repo = local_repo
+        .append_file("File1", "Some local fixes")
+        .commit_all_amend();
+    assert!(matches!(
+        sync::execute(sync::Options::default(), git_repo(&local_repo)),
+        Err(Error::MergeConflict { .. })
+    ));
+
+    let error = sync::execute(sync::Options::default(), git_repo(&local_repo)).unwrap_err();
+
+    assert!(matches!(error, Error::SyncInProgress));
+}
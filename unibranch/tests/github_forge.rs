@@ -0,0 +1,646 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use pretty_assertions::assert_eq;
+use serde_json::{json, Value};
+use test_repo::{mock_server::MockServer, RemoteRepo, TestRepoWithRemote};
+use ubr::{
+    commands::{create, status, sync},
+    forge::{CheckState, PullRequestStatus, ReviewState},
+    git::GitRepo,
+};
+
+fn git_repo(value: &TestRepoWithRemote) -> GitRepo {
+    GitRepo::open(value.path()).unwrap()
+}
+
+///
+/// A mock of the pull request endpoints of the GitHub API for the repository `owner/name`.
+///
+fn fake_github(pull_requests: Arc<Mutex<Vec<Value>>>) -> MockServer {
+    MockServer::start(move |request| {
+        let mut pull_requests = pull_requests.lock().unwrap();
+        // The fake uses the branch name as the commit id of the head of the pull request
+        if let Some(sha) = request
+            .path()
+            .strip_prefix("/repos/owner/name/commits/")
+            .and_then(|path| path.strip_suffix("/status"))
+        {
+            return match pull_requests.iter().find(|pr| pr["head"]["sha"] == sha) {
+                Some(pull_request) => (200, pull_request["status"].clone()),
+                None => (404, json!({ "message": "Not Found" })),
+            };
+        }
+        if let Some(sha) = request
+            .path()
+            .strip_prefix("/repos/owner/name/commits/")
+            .and_then(|path| path.strip_suffix("/check-runs"))
+        {
+            let query = |name: &str| {
+                request
+                    .url
+                    .split(['?', '&'])
+                    .find_map(|param| param.strip_prefix(name)?.strip_prefix('='))
+                    .and_then(|value| value.parse::<usize>().ok())
+            };
+            let (page, per_page) = (query("page").unwrap_or(1), query("per_page").unwrap_or(30));
+            return match pull_requests.iter().find(|pr| pr["head"]["sha"] == sha) {
+                Some(pull_request) => {
+                    let check_runs = pull_request["check_runs"].as_array().unwrap();
+                    let page: Vec<_> = check_runs
+                        .iter()
+                        .skip((page - 1) * per_page)
+                        .take(per_page)
+                        .collect();
+                    (
+                        200,
+                        json!({ "total_count": check_runs.len(), "check_runs": page }),
+                    )
+                }
+                None => (404, json!({ "message": "Not Found" })),
+            };
+        }
+        let Some(path) = request.path().strip_prefix("/repos/owner/name/pulls") else {
+            return (404, json!({ "message": "Not Found" }));
+        };
+        if let Some(number) = path
+            .strip_prefix('/')
+            .and_then(|path| path.strip_suffix("/reviews"))
+            .and_then(|n| n.parse::<usize>().ok())
+        {
+            return match pull_requests.get(number - 1) {
+                Some(pull_request) => (200, pull_request["reviews"].clone()),
+                None => (404, json!({ "message": "Not Found" })),
+            };
+        }
+        let number = path.strip_prefix('/').and_then(|n| n.parse::<u64>().ok());
+        match (request.method.as_str(), number) {
+            ("GET", None) => {
+                let head = request
+                    .url
+                    .split(['?', '&'])
+                    .find_map(|param| param.strip_prefix("head=owner%3A"))
+                    .unwrap_or_default()
+                    .replace("%2F", "/");
+                let found = pull_requests
+                    .iter()
+                    .filter(|pr| pr["head"]["ref"] == head.as_str() && pr["state"] == "open")
+                    .cloned()
+                    .collect::<Vec<_>>();
+                (200, Value::Array(found))
+            }
+            ("POST", None) => {
+                let body = request.json();
+                let number = pull_requests.len() as u64 + 1;
+                let pull_request = json!({
+                    "number": number,
+                    "html_url": format!("https://github.com/owner/name/pull/{}", number),
+                    "state": "open",
+                    "title": body["title"],
+                    "body": body["body"],
+                    "head": { "ref": body["head"], "sha": body["head"] },
+                    "base": { "ref": body["base"] },
+                    "mergeable": null,
+                    "reviews": [],
+                    "status": { "state": "pending", "total_count": 0 },
+                    "check_runs": [],
+                });
+                pull_requests.push(pull_request.clone());
+                (201, pull_request)
+            }
+            (method, Some(number)) => {
+                let Some(pull_request) = pull_requests.get_mut(number as usize - 1) else {
+                    return (404, json!({ "message": "Not Found" }));
+                };
+                if method == "PATCH" {
+                    let body = request.json();
+                    pull_request["title"] = body["title"].clone();
+                    pull_request["body"] = body["body"].clone();
+                    pull_request["base"]["ref"] = body["base"].clone();
+                }
+                (200, pull_request.clone())
+            }
+            _ => (405, json!({ "message": "Method Not Allowed" })),
+        }
+    })
+}
+
+fn setup_forge<'a>(
+    local_repo: TestRepoWithRemote<'a>,
+    server: &MockServer,
+) -> TestRepoWithRemote<'a> {
+    local_repo
+        .set_config("ubr.forge", "github")
+        .set_config("ubr.forgeUrl", &server.url())
+        .set_config("ubr.forgeRepository", "owner/name")
+        .set_config(
+            "credential.helper",
+            "!f() { echo username=ubr; echo password=secret; }; f",
+        )
+}
+
+#[test]
+fn create_opens_pull_request() {
+    let pull_requests = Arc::new(Mutex::new(Vec::new()));
+    let server = fake_github(Arc::clone(&pull_requests));
+    let remote_repo = RemoteRepo::new();
+    let local_repo = setup_forge(remote_repo.clone_repo(), &server)
+        .create_file("File1", "Hello, World!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Starting on a new feature")
+        .commit_all("feature 1\n\nWhat the feature is about");
+
+    create::execute(create::Options::default().with_pr(), git_repo(&local_repo)).unwrap();
+
+    let pull_requests = pull_requests.lock().unwrap();
+    assert_eq!(pull_requests.len(), 1);
+    assert_eq!(pull_requests[0]["title"], "feature 1");
+    assert_eq!(pull_requests[0]["body"], "What the feature is about");
+    assert_eq!(pull_requests[0]["head"]["ref"], "feature-1");
+    assert_eq!(pull_requests[0]["base"]["ref"], "master");
+    local_repo.assert_note(
+        "HEAD",
+        format!(
+            "ubr-version: 2\nremote-branch: feature-1\nremote-commit: {}\npull-request: 1\n",
+            local_repo.rev_parse("origin/feature-1")
+        ),
+    );
+    if std::env::var("GITHUB_TOKEN").is_err() && std::env::var("GH_TOKEN").is_err() {
+        assert!(server
+            .requests()
+            .iter()
+            .all(|request| request.header("Authorization") == Some("Bearer secret")));
+    }
+}
+
+#[test]
+fn create_reuses_open_pull_request_of_branch() {
+    let pull_requests = Arc::new(Mutex::new(vec![json!({
+        "number": 1,
+        "html_url": "https://github.com/owner/name/pull/1",
+        "state": "open",
+        "title": "feature 1",
+        "body": null,
+        "head": { "ref": "feature-1" },
+        "base": { "ref": "master" },
+    })]));
+    let server = fake_github(Arc::clone(&pull_requests));
+    let remote_repo = RemoteRepo::new();
+    let local_repo = setup_forge(remote_repo.clone_repo(), &server)
+        .create_file("File1", "Hello, World!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Starting on a new feature")
+        .commit_all("feature 1");
+
+    create::execute(create::Options::default().with_pr(), git_repo(&local_repo)).unwrap();
+
+    assert_eq!(pull_requests.lock().unwrap().len(), 1);
+    assert!(local_repo.find_note("HEAD").ends_with("pull-request: 1\n"));
+    assert!(server
+        .requests()
+        .iter()
+        .all(|request| request.method == "GET"));
+}
+
+#[test]
+fn stacked_pull_request_is_based_on_parent_branch() {
+    let pull_requests = Arc::new(Mutex::new(Vec::new()));
+    let server = fake_github(Arc::clone(&pull_requests));
+    let remote_repo = RemoteRepo::new();
+    let local_repo = setup_forge(remote_repo.clone_repo(), &server)
+        .create_file("File1", "Hello, World!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Starting on a new feature")
+        .commit_all("feature 1");
+    create::execute(create::Options::default().with_pr(), git_repo(&local_repo)).unwrap();
+    let local_repo = local_repo
+        .append_file("File1", "Building on the new feature")
+        .commit_all("feature 2");
+
+    create::execute(
+        create::Options::default().with_stack().with_pr(),
+        git_repo(&local_repo),
+    )
+    .unwrap();
+
+    let pull_requests = pull_requests.lock().unwrap();
+    assert_eq!(pull_requests[1]["head"]["ref"], "feature-2");
+    assert_eq!(pull_requests[1]["base"]["ref"], "feature-1");
+}
+
+#[test]
+fn sync_updates_pull_requests() {
+    let pull_requests = Arc::new(Mutex::new(Vec::new()));
+    let server = fake_github(Arc::clone(&pull_requests));
+    let remote_repo = RemoteRepo::new();
+    let local_repo = setup_forge(remote_repo.clone_repo(), &server)
+        .create_file("File1", "Hello, World!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Starting on a new feature")
+        .commit_all("feature 1");
+    create::execute(create::Options::default().with_pr(), git_repo(&local_repo)).unwrap();
+
+    let local_repo = local_repo
+        .append_file("File1", "Some fixes")
+        .commit_all_amend_with_message("Feature 1\n\nNow with a description");
+    sync::execute(sync::Options::default(), git_repo(&local_repo)).unwrap();
+
+    let pull_requests = pull_requests.lock().unwrap();
+    assert_eq!(pull_requests.len(), 1);
+    assert_eq!(pull_requests[0]["title"], "Feature 1");
+    assert_eq!(pull_requests[0]["body"], "Now with a description");
+    assert!(local_repo.find_note("HEAD").ends_with("pull-request: 1\n"));
+}
+
+#[test]
+fn sync_opens_pull_requests_when_asked() {
+    let pull_requests = Arc::new(Mutex::new(Vec::new()));
+    let server = fake_github(Arc::clone(&pull_requests));
+    let remote_repo = RemoteRepo::new();
+    let local_repo = setup_forge(remote_repo.clone_repo(), &server)
+        .create_file("File1", "Hello, World!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Starting on a new feature")
+        .commit_all("feature 1")
+        .create_file("File2", "Another feature")
+        .commit_all("feature 2");
+    create::execute(
+        create::Options::default().with_commit_ref("HEAD^"),
+        git_repo(&local_repo),
+    )
+    .unwrap();
+    create::execute(create::Options::default(), git_repo(&local_repo)).unwrap();
+
+    // Without pull requests to update, the forge isn't contacted
+    sync::execute(sync::Options::default(), git_repo(&local_repo)).unwrap();
+    assert!(server.requests().is_empty());
+
+    sync::execute(sync::Options::default().with_pr(), git_repo(&local_repo)).unwrap();
+
+    let pull_requests = pull_requests.lock().unwrap();
+    assert_eq!(
+        pull_requests
+            .iter()
+            .map(|pr| pr["head"]["ref"].as_str().unwrap())
+            .collect::<Vec<_>>(),
+        vec!["feature-1", "feature-2"]
+    );
+    assert!(local_repo.find_note("HEAD^").ends_with("pull-request: 1\n"));
+    assert!(local_repo.find_note("HEAD").ends_with("pull-request: 2\n"));
+}
+
+///
+/// The navigation block of the pull request `current` in a stack of pull requests opened by
+/// `fake_github`, newest first.
+///
+fn stack_block(stack: &[(u64, &str)], current: u64) -> String {
+    let links: String = stack
+        .iter()
+        .map(|(number, title)| {
+            let link = format!("[{}](https://github.com/owner/name/pull/{})", title, number);
+            if *number == current {
+                format!("- **{}** \u{2190} this one\n", link)
+            } else {
+                format!("- {}\n", link)
+            }
+        })
+        .collect();
+    format!(
+        "<!-- ubr-stack -->\n---\n**Stack**\n\n{}<!-- /ubr-stack -->",
+        links
+    )
+}
+
+#[test]
+fn pull_requests_list_the_stack() {
+    let pull_requests = Arc::new(Mutex::new(Vec::new()));
+    let server = fake_github(Arc::clone(&pull_requests));
+    let remote_repo = RemoteRepo::new();
+    let local_repo = setup_forge(remote_repo.clone_repo(), &server)
+        .create_file("File1", "Hello, World!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Starting on a new feature")
+        .commit_all("feature 1\n\nAbout feature 1")
+        .create_file("File2", "Another feature")
+        .commit_all("feature 2");
+    create::execute(
+        create::Options::default()
+            .with_commit_ref("HEAD^")
+            .with_pr(),
+        git_repo(&local_repo),
+    )
+    .unwrap();
+    // A single pull request is no stack
+    assert_eq!(pull_requests.lock().unwrap()[0]["body"], "About feature 1");
+
+    create::execute(create::Options::default().with_pr(), git_repo(&local_repo)).unwrap();
+
+    let stack = [(2, "feature 2"), (1, "feature 1")];
+    let pull_requests = pull_requests.lock().unwrap();
+    assert_eq!(
+        pull_requests[0]["body"],
+        format!("About feature 1\n\n{}", stack_block(&stack, 1))
+    );
+    assert_eq!(pull_requests[1]["body"], stack_block(&stack, 2));
+}
+
+#[test]
+fn sync_updates_the_stack_and_keeps_other_text() {
+    let pull_requests = Arc::new(Mutex::new(Vec::new()));
+    let server = fake_github(Arc::clone(&pull_requests));
+    let remote_repo = RemoteRepo::new();
+    let local_repo = setup_forge(remote_repo.clone_repo(), &server)
+        .create_file("File1", "Hello, World!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Starting on a new feature")
+        .commit_all("feature 1")
+        .create_file("File2", "Another feature")
+        .commit_all("feature 2");
+    create::execute(
+        create::Options::default().with_commit_ref("HEAD^"),
+        git_repo(&local_repo),
+    )
+    .unwrap();
+    create::execute(create::Options::default(), git_repo(&local_repo)).unwrap();
+    sync::execute(sync::Options::default().with_pr(), git_repo(&local_repo)).unwrap();
+
+    // Text written on the forge, before and after the block
+    {
+        let mut pull_requests = pull_requests.lock().unwrap();
+        let body = format!(
+            "Written by the author\n\n{}\n\nWritten by a reviewer",
+            pull_requests[1]["body"].as_str().unwrap()
+        );
+        pull_requests[1]["body"] = Value::String(body);
+    }
+    let local_repo = local_repo
+        .create_file("File3", "A third feature")
+        .commit_all("feature 3");
+    create::execute(create::Options::default(), git_repo(&local_repo)).unwrap();
+    sync::execute(sync::Options::default().with_pr(), git_repo(&local_repo)).unwrap();
+
+    let stack = [(3, "feature 3"), (2, "feature 2"), (1, "feature 1")];
+    let pull_requests = pull_requests.lock().unwrap();
+    assert_eq!(pull_requests[0]["body"], stack_block(&stack, 1));
+    assert_eq!(
+        pull_requests[1]["body"],
+        format!(
+            "Written by the author\n\n{}\n\nWritten by a reviewer",
+            stack_block(&stack, 2)
+        )
+    );
+    assert_eq!(pull_requests[2]["body"], stack_block(&stack, 3));
+}
+
+fn pull_request_statuses(
+    local_repo: &TestRepoWithRemote,
+    refresh: bool,
+) -> HashMap<u64, PullRequestStatus> {
+    let git_repo = git_repo(local_repo);
+    let statuses = status::collect(&git_repo).unwrap();
+    status::collect_pull_requests(&git_repo, &statuses, refresh).unwrap()
+}
+
+#[test]
+fn status_of_pull_requests() {
+    let pull_requests = Arc::new(Mutex::new(Vec::new()));
+    let server = fake_github(Arc::clone(&pull_requests));
+    let remote_repo = RemoteRepo::new();
+    let local_repo = setup_forge(remote_repo.clone_repo(), &server)
+        .create_file("File1", "Hello, World!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Starting on a new feature")
+        .commit_all("feature 1")
+        .create_file("File2", "Another feature")
+        .commit_all("feature 2");
+    create::execute(
+        create::Options::default()
+            .with_commit_ref("HEAD^")
+            .with_pr(),
+        git_repo(&local_repo),
+    )
+    .unwrap();
+    create::execute(create::Options::default().with_pr(), git_repo(&local_repo)).unwrap();
+    {
+        let mut pull_requests = pull_requests.lock().unwrap();
+        // The latest review of every reviewer counts, comments aside
+        pull_requests[0]["reviews"] = json!([
+            { "user": { "login": "alice" }, "state": "CHANGES_REQUESTED" },
+            { "user": { "login": "alice" }, "state": "APPROVED" },
+            { "user": { "login": "bob" }, "state": "COMMENTED" },
+        ]);
+        pull_requests[0]["status"] = json!({ "state": "failure", "total_count": 2 });
+        pull_requests[0]["mergeable"] = json!(true);
+        pull_requests[1]["reviews"] = json!([
+            { "user": { "login": "alice" }, "state": "APPROVED" },
+            { "user": { "login": "bob" }, "state": "CHANGES_REQUESTED" },
+        ]);
+        pull_requests[1]["status"] = json!({ "state": "pending", "total_count": 1 });
+        pull_requests[1]["mergeable"] = json!(false);
+    }
+
+    assert_eq!(
+        pull_request_statuses(&local_repo, false),
+        HashMap::from([
+            (
+                1,
+                PullRequestStatus {
+                    review: ReviewState::Approved,
+                    checks: CheckState::Failing,
+                    mergeable: Some(true),
+                }
+            ),
+            (
+                2,
+                PullRequestStatus {
+                    review: ReviewState::ChangesRequested,
+                    checks: CheckState::Pending,
+                    mergeable: Some(false),
+                }
+            ),
+        ])
+    );
+}
+
+#[test]
+fn status_of_pull_requests_is_cached() {
+    let pull_requests = Arc::new(Mutex::new(Vec::new()));
+    let server = fake_github(Arc::clone(&pull_requests));
+    let remote_repo = RemoteRepo::new();
+    let local_repo = setup_forge(remote_repo.clone_repo(), &server)
+        .create_file("File1", "Hello, World!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Starting on a new feature")
+        .commit_all("feature 1");
+    create::execute(create::Options::default().with_pr(), git_repo(&local_repo)).unwrap();
+    let set_checks = |state: &str| {
+        pull_requests.lock().unwrap()[0]["status"] = json!({ "state": state, "total_count": 1 });
+    };
+    let checks = |refresh: bool| pull_request_statuses(&local_repo, refresh)[&1].checks;
+
+    set_checks("pending");
+    assert_eq!(checks(false), CheckState::Pending);
+    set_checks("success");
+    let requests = server.requests().len();
+    assert_eq!(checks(false), CheckState::Pending);
+    assert_eq!(server.requests().len(), requests);
+
+    assert_eq!(checks(true), CheckState::Passing);
+
+    // Pushing the branch again makes the cached status outdated
+    set_checks("failure");
+    let local_repo = local_repo
+        .append_file("File1", "Some fixes")
+        .commit_all_amend();
+    sync::execute(sync::Options::default(), git_repo(&local_repo)).unwrap();
+    assert_eq!(
+        pull_request_statuses(&local_repo, false)[&1].checks,
+        CheckState::Failing
+    );
+
+    // As does its age
+    let local_repo = local_repo.set_config("ubr.forgeCacheTtl", "0");
+    set_checks("success");
+    assert_eq!(
+        pull_request_statuses(&local_repo, false)[&1].checks,
+        CheckState::Passing
+    );
+}
+
+#[test]
+fn sync_updates_the_description_and_keeps_the_stack() {
+    let pull_requests = Arc::new(Mutex::new(Vec::new()));
+    let server = fake_github(Arc::clone(&pull_requests));
+    let remote_repo = RemoteRepo::new();
+    let local_repo = setup_forge(remote_repo.clone_repo(), &server)
+        .create_file("File1", "Hello, World!")
+        .commit_all("commit1")
+        .push()
+        .create_file("File2", "Another feature")
+        .commit_all("feature 1")
+        .append_file("File1", "Starting on a new feature")
+        .commit_all("feature 2\n\nAbout feature 2");
+    create::execute(
+        create::Options::default().with_commit_ref("HEAD^"),
+        git_repo(&local_repo),
+    )
+    .unwrap();
+    create::execute(create::Options::default(), git_repo(&local_repo)).unwrap();
+    sync::execute(sync::Options::default().with_pr(), git_repo(&local_repo)).unwrap();
+
+    let local_repo = local_repo
+        .append_file("File1", "Some fixes")
+        .commit_all_amend_with_message("feature 2\n\nMore about feature 2");
+    sync::execute(sync::Options::default(), git_repo(&local_repo)).unwrap();
+
+    let stack = [(2, "feature 2"), (1, "feature 1")];
+    let pull_requests = pull_requests.lock().unwrap();
+    assert_eq!(
+        pull_requests[1]["body"],
+        format!("More about feature 2\n\n{}", stack_block(&stack, 2))
+    );
+}
+
+#[test]
+fn status_of_pull_requests_combines_check_runs_and_statuses() {
+    let pull_requests = Arc::new(Mutex::new(Vec::new()));
+    let server = fake_github(Arc::clone(&pull_requests));
+    let remote_repo = RemoteRepo::new();
+    let local_repo = setup_forge(remote_repo.clone_repo(), &server)
+        .create_file("File1", "Hello, World!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Starting on a new feature")
+        .commit_all("feature 1");
+    create::execute(create::Options::default().with_pr(), git_repo(&local_repo)).unwrap();
+    let set_checks = |status: Value, check_runs: Value| {
+        let mut pull_requests = pull_requests.lock().unwrap();
+        pull_requests[0]["status"] = status;
+        pull_requests[0]["check_runs"] = check_runs;
+    };
+    let checks = || pull_request_statuses(&local_repo, true)[&1].checks;
+
+    set_checks(
+        json!({ "state": "pending", "total_count": 0 }),
+        json!([{ "status": "completed", "conclusion": "success" }]),
+    );
+    assert_eq!(checks(), CheckState::Passing);
+    set_checks(
+        json!({ "state": "success", "total_count": 1 }),
+        json!([{ "status": "in_progress", "conclusion": null }]),
+    );
+    assert_eq!(checks(), CheckState::Pending);
+    set_checks(
+        json!({ "state": "pending", "total_count": 1 }),
+        json!([{ "status": "completed", "conclusion": "timed_out" }]),
+    );
+    assert_eq!(checks(), CheckState::Failing);
+}
+
+#[test]
+fn status_of_pull_requests_reads_every_page_of_check_runs() {
+    let pull_requests = Arc::new(Mutex::new(Vec::new()));
+    let server = fake_github(Arc::clone(&pull_requests));
+    let remote_repo = RemoteRepo::new();
+    let local_repo = setup_forge(remote_repo.clone_repo(), &server)
+        .create_file("File1", "Hello, World!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Starting on a new feature")
+        .commit_all("feature 1");
+    create::execute(create::Options::default().with_pr(), git_repo(&local_repo)).unwrap();
+    let mut check_runs = vec![json!({ "status": "completed", "conclusion": "success" }); 250];
+    check_runs.push(json!({ "status": "completed", "conclusion": "failure" }));
+    pull_requests.lock().unwrap()[0]["check_runs"] = Value::from(check_runs);
+
+    assert_eq!(
+        pull_request_statuses(&local_repo, true)[&1].checks,
+        CheckState::Failing
+    );
+}
+
+#[test]
+fn status_of_other_pull_requests_is_read_when_one_fails() {
+    let pull_requests = Arc::new(Mutex::new(Vec::new()));
+    let server = fake_github(Arc::clone(&pull_requests));
+    let remote_repo = RemoteRepo::new();
+    let local_repo = setup_forge(remote_repo.clone_repo(), &server)
+        .create_file("File1", "Hello, World!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Starting on a new feature")
+        .commit_all("feature 1")
+        .create_file("File2", "Another feature")
+        .commit_all("feature 2");
+    create::execute(
+        create::Options::default()
+            .with_commit_ref("HEAD^")
+            .with_pr(),
+        git_repo(&local_repo),
+    )
+    .unwrap();
+    create::execute(create::Options::default().with_pr(), git_repo(&local_repo)).unwrap();
+    pull_requests.lock().unwrap()[0]["status"] = Value::Null;
+
+    let statuses = pull_request_statuses(&local_repo, false);
+    assert_eq!(statuses.len(), 1);
+    assert_eq!(statuses[&2].checks, CheckState::NoChecks);
+
+    // The status that could be read is cached
+    let requests = server.requests().len();
+    pull_request_statuses(&local_repo, false);
+    assert!(server.requests()[requests..]
+        .iter()
+        .all(|request| !request.path().contains("/pulls/2")));
+}
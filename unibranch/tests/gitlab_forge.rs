@@ -0,0 +1,249 @@
+use std::sync::{Arc, Mutex};
+
+use pretty_assertions::assert_eq;
+use serde_json::{json, Value};
+use test_repo::{mock_server::MockServer, RemoteRepo, TestRepoWithRemote};
+use ubr::{
+    commands::{create, status, sync},
+    forge::{CheckState, PullRequestStatus, ReviewState},
+    git::GitRepo,
+};
+
+fn git_repo(value: &TestRepoWithRemote) -> GitRepo {
+    GitRepo::open(value.path()).unwrap()
+}
+
+///
+/// A mock of the merge request endpoints of the GitLab v4 API for the project `group/name`.
+///
+fn fake_gitlab(merge_requests: Arc<Mutex<Vec<Value>>>) -> MockServer {
+    MockServer::start(move |request| {
+        let mut merge_requests = merge_requests.lock().unwrap();
+        let Some(path) = request
+            .path()
+            .strip_prefix("/api/v4/projects/group%2Fname/merge_requests")
+        else {
+            return (404, json!({ "message": "404 Project Not Found" }));
+        };
+        if let Some(iid) = path
+            .strip_prefix('/')
+            .and_then(|path| path.strip_suffix("/approvals"))
+            .and_then(|n| n.parse::<usize>().ok())
+        {
+            return match merge_requests.get(iid - 1) {
+                Some(merge_request) => (200, merge_request["approvals"].clone()),
+                None => (404, json!({ "message": "404 Not found" })),
+            };
+        }
+        let iid = path.strip_prefix('/').and_then(|n| n.parse::<u64>().ok());
+        match (request.method.as_str(), iid) {
+            ("GET", None) => {
+                let source_branch = request
+                    .url
+                    .split(['?', '&'])
+                    .find_map(|param| param.strip_prefix("source_branch="))
+                    .unwrap_or_default()
+                    .replace("%2F", "/");
+                let found = merge_requests
+                    .iter()
+                    .filter(|mr| {
+                        mr["source_branch"] == source_branch.as_str() && mr["state"] == "opened"
+                    })
+                    .cloned()
+                    .collect::<Vec<_>>();
+                (200, Value::Array(found))
+            }
+            ("POST", None) => {
+                let body = request.json();
+                let iid = merge_requests.len() as u64 + 1;
+                let merge_request = json!({
+                    "iid": iid,
+                    "web_url": format!("https://gitlab.example.com/group/name/-/merge_requests/{}", iid),
+                    "state": "opened",
+                    "title": body["title"],
+                    "description": body["description"],
+                    "draft": body["title"].as_str().unwrap().starts_with("Draft: "),
+                    "source_branch": body["source_branch"],
+                    "target_branch": body["target_branch"],
+                    "merge_status": "checking",
+                    "head_pipeline": null,
+                    "approvals": { "approved": false },
+                });
+                merge_requests.push(merge_request.clone());
+                (201, merge_request)
+            }
+            (method, Some(iid)) => {
+                let Some(merge_request) = merge_requests.get_mut(iid as usize - 1) else {
+                    return (404, json!({ "message": "404 Not found" }));
+                };
+                if method == "PUT" {
+                    let body = request.json();
+                    merge_request["title"] = body["title"].clone();
+                    merge_request["description"] = body["description"].clone();
+                    merge_request["target_branch"] = body["target_branch"].clone();
+                    merge_request["draft"] =
+                        Value::Bool(body["title"].as_str().unwrap().starts_with("Draft: "));
+                }
+                (200, merge_request.clone())
+            }
+            _ => (405, json!({ "message": "405 Method Not Allowed" })),
+        }
+    })
+}
+
+fn setup_forge<'a>(
+    local_repo: TestRepoWithRemote<'a>,
+    server: &MockServer,
+) -> TestRepoWithRemote<'a> {
+    local_repo
+        .set_config("ubr.forge", "gitlab")
+        .set_config("ubr.forgeUrl", &format!("{}/api/v4", server.url()))
+        .set_config("ubr.forgeRepository", "group/name")
+        .set_config(
+            "credential.helper",
+            "!f() { echo username=ubr; echo password=secret; }; f",
+        )
+}
+
+#[test]
+fn create_opens_draft_merge_request() {
+    let merge_requests = Arc::new(Mutex::new(Vec::new()));
+    let server = fake_gitlab(Arc::clone(&merge_requests));
+    let remote_repo = RemoteRepo::new();
+    let local_repo = setup_forge(remote_repo.clone_repo(), &server)
+        .create_file("File1", "Hello, World!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Starting on a new feature")
+        .commit_all("feature 1\n\nWhat the feature is about");
+
+    create::execute(
+        create::Options::default().with_pr().with_draft(),
+        git_repo(&local_repo),
+    )
+    .unwrap();
+
+    let merge_requests = merge_requests.lock().unwrap();
+    assert_eq!(merge_requests.len(), 1);
+    assert_eq!(merge_requests[0]["title"], "Draft: feature 1");
+    assert_eq!(
+        merge_requests[0]["description"],
+        "What the feature is about"
+    );
+    assert_eq!(merge_requests[0]["source_branch"], "feature-1");
+    assert_eq!(merge_requests[0]["target_branch"], "master");
+    assert!(local_repo.find_note("HEAD").ends_with("pull-request: 1\n"));
+    if std::env::var("GITLAB_TOKEN").is_err() {
+        assert!(server
+            .requests()
+            .iter()
+            .all(|request| request.header("PRIVATE-TOKEN") == Some("secret")));
+    }
+}
+
+#[test]
+fn sync_opens_stacked_merge_request() {
+    let merge_requests = Arc::new(Mutex::new(Vec::new()));
+    let server = fake_gitlab(Arc::clone(&merge_requests));
+    let remote_repo = RemoteRepo::new();
+    let local_repo = setup_forge(remote_repo.clone_repo(), &server)
+        .create_file("File1", "Hello, World!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Starting on a new feature")
+        .commit_all("feature 1");
+    create::execute(create::Options::default().with_pr(), git_repo(&local_repo)).unwrap();
+    let local_repo = local_repo
+        .append_file("File1", "Building on the new feature")
+        .commit_all("feature 2");
+    create::execute(
+        create::Options::default().with_stack(),
+        git_repo(&local_repo),
+    )
+    .unwrap();
+
+    sync::execute(sync::Options::default().with_pr(), git_repo(&local_repo)).unwrap();
+
+    let merge_requests = merge_requests.lock().unwrap();
+    assert_eq!(merge_requests.len(), 2);
+    assert_eq!(merge_requests[1]["title"], "feature 2");
+    assert_eq!(merge_requests[1]["source_branch"], "feature-2");
+    assert_eq!(merge_requests[1]["target_branch"], "feature-1");
+    assert!(local_repo.find_note("HEAD^").ends_with("pull-request: 1\n"));
+    assert!(local_repo.find_note("HEAD").ends_with("pull-request: 2\n"));
+}
+
+#[test]
+fn sync_keeps_merge_request_a_draft() {
+    let merge_requests = Arc::new(Mutex::new(Vec::new()));
+    let server = fake_gitlab(Arc::clone(&merge_requests));
+    let remote_repo = RemoteRepo::new();
+    let local_repo = setup_forge(remote_repo.clone_repo(), &server)
+        .create_file("File1", "Hello, World!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Starting on a new feature")
+        .commit_all("feature 1");
+    create::execute(
+        create::Options::default().with_pr().with_draft(),
+        git_repo(&local_repo),
+    )
+    .unwrap();
+
+    let local_repo = local_repo
+        .append_file("File1", "Some fixes")
+        .commit_all_amend_with_message("Feature 1\n\nNow with a description");
+    sync::execute(sync::Options::default(), git_repo(&local_repo)).unwrap();
+
+    let merge_requests = merge_requests.lock().unwrap();
+    assert_eq!(merge_requests.len(), 1);
+    assert_eq!(merge_requests[0]["title"], "Draft: Feature 1");
+    assert_eq!(merge_requests[0]["description"], "Now with a description");
+    assert!(server
+        .requests()
+        .iter()
+        .any(|request| request.method == "PUT"));
+}
+
+#[test]
+fn status_of_merge_requests() {
+    let merge_requests = Arc::new(Mutex::new(Vec::new()));
+    let server = fake_gitlab(Arc::clone(&merge_requests));
+    let remote_repo = RemoteRepo::new();
+    let local_repo = setup_forge(remote_repo.clone_repo(), &server)
+        .create_file("File1", "Hello, World!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Starting on a new feature")
+        .commit_all("feature 1");
+    create::execute(create::Options::default().with_pr(), git_repo(&local_repo)).unwrap();
+    let statuses = || {
+        let git_repo = git_repo(&local_repo);
+        let statuses = status::collect(&git_repo).unwrap();
+        status::collect_pull_requests(&git_repo, &statuses, true).unwrap()[&1]
+    };
+
+    assert_eq!(
+        statuses(),
+        PullRequestStatus {
+            review: ReviewState::Pending,
+            checks: CheckState::NoChecks,
+            mergeable: None,
+        }
+    );
+
+    {
+        let mut merge_requests = merge_requests.lock().unwrap();
+        merge_requests[0]["approvals"] = json!({ "approved": true });
+        merge_requests[0]["head_pipeline"] = json!({ "status": "running" });
+        merge_requests[0]["merge_status"] = json!("can_be_merged");
+    }
+    assert_eq!(
+        statuses(),
+        PullRequestStatus {
+            review: ReviewState::Approved,
+            checks: CheckState::Pending,
+            mergeable: Some(true),
+        }
+    );
+}
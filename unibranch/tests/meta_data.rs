@@ -0,0 +1,191 @@
+use pretty_assertions::assert_eq;
+use test_repo::{RemoteRepo, TestRepoWithRemote};
+use ubr::{
+    commands::{create, sync},
+    git::GitRepo,
+};
+
+fn git_repo(value: &TestRepoWithRemote) -> GitRepo {
+    GitRepo::open(value.path()).unwrap()
+}
+
+fn overwrite_note(local_repo: &TestRepoWithRemote, note: &str) {
+    assert!(local_repo
+        .run_command()
+        .args(["notes", "--ref=ubr", "add", "-f", "-m", note, "HEAD"])
+        .status()
+        .unwrap()
+        .success());
+}
+
+#[test]
+fn sync_migrates_version_1_note_and_keeps_unknown_keys() {
+    let remote_repo = RemoteRepo::new();
+    let local_repo = remote_repo
+        .clone_repo()
+        .create_file("File1", "Hello, World!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Starting on a new feature")
+        .commit_all("feature 1");
+
+    create::execute(create::Options::default(), git_repo(&local_repo)).unwrap();
+    let remote_commit = local_repo.rev_parse("origin/feature-1");
+    overwrite_note(
+        &local_repo,
+        &format!(
+            "remote-branch: feature-1\nremote-commit: {}\nreviewer: someone\n",
+            remote_commit
+        ),
+    );
+    let local_repo = local_repo
+        .append_file("File1", "More work")
+        .commit_all_amend();
+
+    sync::execute(
+        sync::Options::default().with_no_fetch(),
+        git_repo(&local_repo),
+    )
+    .unwrap();
+
+    local_repo.assert_note(
+        "HEAD",
+        format!(
+            "ubr-version: 2\nremote-branch: feature-1\nremote-commit: {}\nreviewer: someone\n",
+            local_repo.rev_parse("origin/feature-1")
+        ),
+    );
+    assert_ne!(local_repo.rev_parse("origin/feature-1"), remote_commit);
+}
+
+#[test]
+fn sync_ignores_corrupt_note() {
+    let remote_repo = RemoteRepo::new();
+    let local_repo = remote_repo
+        .clone_repo()
+        .create_file("File1", "Hello, World!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Starting on a new feature")
+        .commit_all("feature 1");
+
+    create::execute(create::Options::default(), git_repo(&local_repo)).unwrap();
+    let remote_commit = local_repo.rev_parse("origin/feature-1");
+    let corrupt_note = "ubr-version: 2\nremote-branch: feature-1\nremote-commit: garbage\n";
+    overwrite_note(&local_repo, corrupt_note);
+    let local_repo = local_repo
+        .append_file("File1", "More work")
+        .commit_all_amend();
+
+    sync::execute(
+        sync::Options::default().with_no_fetch(),
+        git_repo(&local_repo),
+    )
+    .unwrap();
+
+    local_repo.assert_note("HEAD", corrupt_note);
+    assert_eq!(local_repo.rev_parse("origin/feature-1"), remote_commit);
+}
+
+fn git_output(local_repo: &TestRepoWithRemote, args: &[&str]) -> String {
+    let output = local_repo
+        .run_command()
+        .stdout(std::process::Stdio::piped())
+        .args(args)
+        .output()
+        .unwrap();
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn notes_in_default_ref_are_migrated() {
+    let remote_repo = RemoteRepo::new();
+    let local_repo = remote_repo
+        .clone_repo()
+        .create_file("File1", "Hello, World!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Starting on a new feature")
+        .commit_all("feature 1")
+        .set_config("notes.rewriteRef", "refs/notes/*");
+
+    create::execute(create::Options::default(), git_repo(&local_repo)).unwrap();
+    // Move the note to where older versions stored it, next to a note of the user
+    let note = local_repo.find_note("HEAD");
+    git_output(&local_repo, &["notes", "add", "-m", &note, "HEAD"]);
+    git_output(&local_repo, &["notes", "add", "-m", "My own note", "HEAD^"]);
+    git_output(&local_repo, &["update-ref", "-d", "refs/notes/ubr"]);
+    assert_eq!(local_repo.find_note("HEAD"), "");
+
+    git_repo(&local_repo);
+
+    local_repo.assert_note("HEAD", note);
+    assert_eq!(git_output(&local_repo, &["notes", "show", "HEAD"]), "");
+    assert_eq!(
+        git_output(&local_repo, &["notes", "show", "HEAD^"]),
+        "My own note\n"
+    );
+    assert_eq!(
+        git_output(&local_repo, &["config", "--get-all", "notes.rewriteRef"]),
+        "refs/notes/ubr\n"
+    );
+}
+
+#[test]
+fn user_notes_are_left_alone() {
+    let remote_repo = RemoteRepo::new();
+    let local_repo = remote_repo
+        .clone_repo()
+        .create_file("File1", "Hello, World!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Starting on a new feature")
+        .commit_all("feature 1");
+
+    create::execute(create::Options::default(), git_repo(&local_repo)).unwrap();
+    git_output(&local_repo, &["notes", "add", "-m", "My own note", "HEAD"]);
+    let annotated_commit = local_repo.head().to_string();
+    let local_repo = local_repo
+        .append_file("File1", "More work")
+        .commit_all_amend();
+
+    sync::execute(
+        sync::Options::default().with_no_fetch(),
+        git_repo(&local_repo),
+    )
+    .unwrap();
+
+    assert!(local_repo
+        .find_note("HEAD")
+        .starts_with("ubr-version: 2\nremote-branch: feature-1\n"));
+    assert_eq!(git_output(&local_repo, &["notes", "show", "HEAD"]), "");
+    assert_eq!(
+        git_output(&local_repo, &["notes", "show", &annotated_commit]),
+        "My own note\n"
+    );
+}
+
+#[test]
+fn notes_ref_is_configurable() {
+    let remote_repo = RemoteRepo::new();
+    let local_repo = remote_repo
+        .clone_repo()
+        .create_file("File1", "Hello, World!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Starting on a new feature")
+        .commit_all("feature 1")
+        .set_config("ubr.notesRef", "stacks");
+
+    create::execute(create::Options::default(), git_repo(&local_repo)).unwrap();
+
+    assert_eq!(local_repo.find_note("HEAD"), "");
+    assert!(
+        git_output(&local_repo, &["notes", "--ref=stacks", "show", "HEAD"])
+            .starts_with("ubr-version: 2\nremote-branch: feature-1\n")
+    );
+    assert_eq!(
+        git_output(&local_repo, &["config", "--get-all", "notes.rewriteRef"]),
+        "refs/notes/stacks\n"
+    );
+}
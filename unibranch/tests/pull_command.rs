@@ -4,7 +4,10 @@ use test_repo::{RemoteRepo, TestRepoWithRemote};
 
 use ubr::{
     commands::{create, sync},
-    git::{local_commit::CommitMetadata, GitRepo},
+    git::{
+        local_commit::{CommitMetadata, META_DATA_VERSION},
+        GitRepo,
+    },
 };
 
 use indoc::indoc;
@@ -74,11 +77,15 @@ fn update_commit_from_remote() {
     local_repo.assert_note(
         "HEAD",
         &CommitMetadata {
+            version: META_DATA_VERSION,
             remote_branch_name: std::borrow::Cow::Owned("pr-commit".to_string()),
             remote_commit: another_local_clone
                 .rev_parse("pr-commit")
                 .parse()
                 .expect("Not a valid object id"),
+            parent_branch: None,
+            pull_request: None,
+            extra: Vec::new(),
         },
     );
 }
@@ -172,11 +179,15 @@ fn update_commit_from_remote_with_local_changes() {
     local_repo.assert_note(
         "HEAD",
         &CommitMetadata {
+            version: META_DATA_VERSION,
             remote_branch_name: std::borrow::Cow::Owned("pr-commit".to_string()),
             remote_commit: local_repo
                 .rev_parse("origin/pr-commit")
                 .parse()
                 .expect("Not a valid object id"),
+            parent_branch: None,
+            pull_request: None,
+            extra: Vec::new(),
         },
     );
 }
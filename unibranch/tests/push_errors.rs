@@ -0,0 +1,187 @@
+use pretty_assertions::assert_eq;
+use test_repo::{RemoteRepo, TestRepoWithRemote};
+use ubr::{
+    commands::{create, sync},
+    git::{
+        remote_command::{PushError, RefPushError},
+        GitRepo,
+    },
+    Error,
+};
+
+fn git_repo(value: &TestRepoWithRemote) -> GitRepo {
+    GitRepo::open(value.path()).unwrap()
+}
+
+#[test]
+fn sync_fails_when_remote_branch_changed() {
+    let remote_repo = RemoteRepo::new();
+    let local_repo = remote_repo
+        .clone_repo()
+        .create_file("File1", "Hello, World!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Starting on a new feature")
+        .commit_all("feature 1");
+
+    create::execute(create::Options::default(), git_repo(&local_repo)).unwrap();
+    let pushed_head = local_repo.rev_parse("origin/feature-1");
+
+    let remote_head = {
+        let other_clone = remote_repo
+            .clone_repo()
+            .checkout("feature-1")
+            .create_file("File2", "Some remote fixes")
+            .commit_all("Fixup")
+            .push();
+        other_clone.rev_parse("HEAD")
+    };
+
+    let local_repo = local_repo
+        .append_file("File1", "Some local fixes")
+        .commit_all_amend();
+    let note = local_repo.find_note("HEAD");
+
+    let error = sync::execute(
+        sync::Options::default().with_no_fetch(),
+        git_repo(&local_repo),
+    )
+    .unwrap_err();
+    let Error::PushRejected(push_error) = error else {
+        panic!("Expected a push error, got {}", error);
+    };
+    assert_eq!(
+        push_error.refs,
+        vec![RefPushError::Stale {
+            reference: "refs/heads/feature-1".to_string(),
+            expected: Some(pushed_head.parse().unwrap()),
+            actual: Some(remote_head.parse().unwrap()),
+        }]
+    );
+
+    local_repo.assert_note("HEAD", note);
+    let local_repo = local_repo.fetch();
+    assert_eq!(local_repo.rev_parse("origin/feature-1"), remote_head);
+}
+
+#[test]
+fn create_fails_when_push_is_rejected() {
+    let remote_repo = RemoteRepo::new();
+    let local_repo = remote_repo
+        .clone_repo()
+        .create_file("File1", "Hello, World!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Starting on a new feature")
+        .commit_all("feature 1");
+
+    //A stale lock in the remote makes it refuse to update the branch
+    std::fs::write(remote_repo.path().join("refs/heads/feature-1.lock"), "").unwrap();
+
+    let error = create::execute(create::Options::default(), git_repo(&local_repo)).unwrap_err();
+    let Error::PushRejected(push_error) = error else {
+        panic!("Expected a push error, got {}", error);
+    };
+    assert!(matches!(
+        &push_error.refs[..],
+        [RefPushError::Rejected { reference, .. }] if reference == "refs/heads/feature-1"
+    ));
+    assert_eq!(local_repo.find_note("HEAD"), "");
+}
+
+#[test]
+fn sync_pushes_no_branch_when_one_is_stale() {
+    let remote_repo = RemoteRepo::new();
+    let local_repo = remote_repo
+        .clone_repo()
+        .create_file("File1", "Hello, World!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Starting on a new feature")
+        .commit_all("feature 1")
+        .create_file("File2", "Another feature")
+        .commit_all("feature 2");
+
+    create::execute(
+        create::Options::default().with_commit_ref("HEAD^"),
+        git_repo(&local_repo),
+    )
+    .unwrap();
+    create::execute(create::Options::default(), git_repo(&local_repo)).unwrap();
+    let feature_1_head = local_repo.rev_parse("origin/feature-1");
+
+    remote_repo
+        .clone_repo()
+        .checkout("feature-2")
+        .create_file("File3", "Some remote fixes")
+        .commit_all("Fixup")
+        .push();
+
+    let feature_1 = local_repo.rev_parse("HEAD^").parse().unwrap();
+    let local_repo = local_repo
+        .append_file("File1", "Some local fixes")
+        .commit_all_fixup(feature_1)
+        .append_file("File2", "More local fixes")
+        .commit_all_amend();
+    let head = local_repo.head();
+
+    let error = sync::execute(
+        sync::Options::default().with_no_fetch(),
+        git_repo(&local_repo),
+    )
+    .unwrap_err();
+    assert!(matches!(error, Error::PushRejected(_)));
+
+    assert_eq!(local_repo.head(), head);
+    let local_repo = local_repo.fetch();
+    assert_eq!(local_repo.rev_parse("origin/feature-1"), feature_1_head);
+}
+
+#[test]
+fn push_fails_when_fetched_remote_commits_were_not_synced() {
+    let remote_repo = RemoteRepo::new();
+    let local_repo = remote_repo
+        .clone_repo()
+        .create_file("File1", "Hello, World!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Starting on a new feature")
+        .commit_all("feature 1");
+
+    create::execute(create::Options::default(), git_repo(&local_repo)).unwrap();
+    let pushed_head = local_repo.rev_parse("origin/feature-1");
+
+    let remote_head = {
+        let other_clone = remote_repo
+            .clone_repo()
+            .checkout("feature-1")
+            .create_file("File2", "Some remote fixes")
+            .commit_all("Fixup")
+            .push();
+        other_clone.rev_parse("HEAD")
+    };
+
+    //A background fetch moves origin/feature-1 without the change being synced
+    let local_repo = local_repo
+        .fetch()
+        .append_file("File1", "Some local fixes")
+        .commit_all("More fixes");
+    let new_head = local_repo.head();
+
+    let error = git_repo(&local_repo)
+        .remote()
+        .push_branch("feature-1", new_head)
+        .unwrap_err();
+    let push_error = error
+        .downcast_ref::<PushError>()
+        .expect("Expected a push error");
+    assert_eq!(
+        push_error.refs,
+        vec![RefPushError::Stale {
+            reference: "refs/heads/feature-1".to_string(),
+            expected: Some(pushed_head.parse().unwrap()),
+            actual: Some(remote_head.parse().unwrap()),
+        }]
+    );
+    assert!(format!("{}", push_error).contains("remote changed since last sync, run ubr sync"));
+}
@@ -0,0 +1,196 @@
+use std::process::Command;
+
+use test_repo::{RemoteRepo, TestRepoWithRemote};
+use ubr::{
+    commands::{create, sync},
+    git::GitRepo,
+};
+
+fn git_repo(value: &TestRepoWithRemote) -> GitRepo {
+    GitRepo::open(value.path()).unwrap()
+}
+
+#[test]
+fn create_with_remote_override() {
+    let upstream = RemoteRepo::new();
+    let fork = RemoteRepo::new();
+    let local_repo = upstream
+        .clone_repo()
+        .create_file("File1", "Hello, World!")
+        .commit_all("commit1")
+        .push()
+        .add_remote("fork", &fork)
+        .append_file("File1", "Starting on a new feature")
+        .commit_all("feature 1");
+
+    create::execute(
+        create::Options::default(),
+        git_repo(&local_repo)
+            .with_remote_name("fork")
+            .with_base_remote_name("origin"),
+    )
+    .unwrap();
+
+    assert!(!local_repo
+        .ls_remote_heads_in("fork", "feature-1")
+        .stdout
+        .is_empty());
+    assert!(local_repo.ls_remote_heads("feature-1").stdout.is_empty());
+}
+
+#[test]
+fn create_with_remote_option_keeps_the_upstream_as_base() {
+    let upstream = RemoteRepo::new();
+    let fork = RemoteRepo::new();
+    let local_repo = upstream
+        .clone_repo()
+        .create_file("File1", "Hello, World!")
+        .commit_all("commit1")
+        .push()
+        .add_remote("fork", &fork)
+        .append_file("File1", "Starting on a new feature")
+        .commit_all("feature 1");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_ubr"))
+        .current_dir(local_repo.path())
+        .args(["--remote", "fork", "create"])
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    assert!(!local_repo
+        .ls_remote_heads_in("fork", "feature-1")
+        .stdout
+        .is_empty());
+    assert!(local_repo.ls_remote_heads("feature-1").stdout.is_empty());
+}
+
+#[test]
+fn base_remote_follows_remote_override_unless_configured() {
+    let remote_repo = RemoteRepo::new();
+    let local_repo = remote_repo
+        .clone_repo()
+        .create_file("File1", "Hello, World!")
+        .commit_all("commit1")
+        .push();
+    assert!(local_repo
+        .run_command()
+        .args(["checkout", "-b", "work"])
+        .status()
+        .unwrap()
+        .success());
+
+    let repo = git_repo(&local_repo).with_remote_name("fork");
+    assert_eq!(repo.base_ref(), "refs/remotes/fork/work");
+
+    let local_repo = local_repo.set_config("ubr.baseRemote", "origin");
+    let repo = git_repo(&local_repo).with_remote_name("fork");
+    assert_eq!(repo.base_ref(), "refs/remotes/origin/work");
+}
+
+#[test]
+fn sync_fork_with_configured_remotes() {
+    let upstream = RemoteRepo::new();
+    let fork = RemoteRepo::new();
+    let local_repo = upstream
+        .clone_repo()
+        .create_file("File1", "Hello, World!")
+        .commit_all("commit1")
+        .push()
+        .add_remote("fork", &fork)
+        .set_config("ubr.remote", "fork")
+        .set_config("ubr.baseRemote", "origin")
+        .append_file("File1", "Starting on a new feature")
+        .commit_all("feature 1");
+
+    create::execute(create::Options::default(), git_repo(&local_repo)).unwrap();
+    assert!(!local_repo
+        .ls_remote_heads_in("fork", "feature-1")
+        .stdout
+        .is_empty());
+
+    let local_repo = local_repo
+        .append_file("File1", "Some local fixes")
+        .commit_all_amend()
+        .fetch_ref("fork");
+
+    sync::execute(sync::Options::default(), git_repo(&local_repo)).unwrap();
+
+    let local_repo = local_repo.fetch_ref("fork");
+    local_repo.assert_tracked_commit_in_sync(
+        local_repo.head(),
+        local_repo
+            .find_commit_by_reference("refs/remotes/fork/feature-1")
+            .id(),
+    );
+}
+
+#[test]
+fn create_on_branch_with_upstream() {
+    let remote_repo = RemoteRepo::new();
+    let local_repo = remote_repo
+        .clone_repo()
+        .create_file("File1", "Hello, World!")
+        .commit_all("commit1")
+        .push();
+    assert!(local_repo
+        .run_command()
+        .args(["checkout", "-b", "work", "--track", "origin/master"])
+        .status()
+        .unwrap()
+        .success());
+    let local_repo = local_repo
+        .append_file("File1", "Starting on a new feature")
+        .commit_all("feature 1");
+
+    let repo = git_repo(&local_repo);
+    assert_eq!(repo.base_ref(), "refs/remotes/origin/master");
+    create::execute(create::Options::default(), repo).unwrap();
+
+    let local_repo = local_repo.fetch();
+    let remote_head = local_repo.find_commit_by_reference("refs/remotes/origin/feature-1");
+    assert_eq!(
+        remote_head.parent_id(0).unwrap().to_string(),
+        local_repo.rev_parse("origin/master")
+    );
+}
+
+#[test]
+fn sync_with_configured_base() {
+    let remote_repo = RemoteRepo::new();
+    let local_repo = remote_repo
+        .clone_repo()
+        .create_file("File1", "Hello, World!")
+        .commit_all("commit1")
+        .push();
+    assert!(local_repo
+        .run_command()
+        .args(["checkout", "-b", "work"])
+        .status()
+        .unwrap()
+        .success());
+    let local_repo = local_repo
+        .set_config("ubr.base", "origin/master")
+        .append_file("File1", "Starting on a new feature")
+        .commit_all("feature 1");
+
+    create::execute(create::Options::default(), git_repo(&local_repo)).unwrap();
+
+    let local_repo = local_repo
+        .append_file("File1", "Some local fixes")
+        .commit_all_amend();
+
+    sync::execute(sync::Options::default(), git_repo(&local_repo)).unwrap();
+
+    let local_repo = local_repo.fetch();
+    local_repo.assert_tracked_commit_in_sync(
+        local_repo.head(),
+        local_repo
+            .find_commit_by_reference("refs/remotes/origin/feature-1")
+            .id(),
+    );
+}
@@ -0,0 +1,129 @@
+use pretty_assertions::assert_eq;
+use test_repo::{RemoteRepo, TestRepoWithRemote};
+use ubr::{
+    commands::{create, fetch, push},
+    git::GitRepo,
+};
+
+fn git_repo(value: &TestRepoWithRemote) -> GitRepo {
+    GitRepo::open(value.path()).unwrap()
+}
+
+fn run_git(local_repo: &TestRepoWithRemote, args: &[&str]) {
+    assert!(local_repo
+        .run_command()
+        .args(args)
+        .status()
+        .unwrap()
+        .success());
+}
+
+///
+/// Make `local_repo` point to the same local commits as `other`, as if they were pulled from
+/// another machine.
+///
+fn copy_commits(local_repo: &TestRepoWithRemote, other: &TestRepoWithRemote) {
+    run_git(
+        local_repo,
+        &["fetch", other.path().to_str().unwrap(), "master"],
+    );
+    run_git(local_repo, &["reset", "--hard", "FETCH_HEAD"]);
+}
+
+#[test]
+fn fetch_metadata_pushed_from_another_clone() {
+    let remote_repo = RemoteRepo::new();
+    let laptop = remote_repo
+        .clone_repo()
+        .create_file("File1", "Hello, World!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Starting on a new feature")
+        .commit_all("feature 1");
+    create::execute(create::Options::default(), git_repo(&laptop)).unwrap();
+    push::execute(push::Options::default().with_metadata(), git_repo(&laptop)).unwrap();
+
+    let desktop = remote_repo.clone_repo();
+    copy_commits(&desktop, &laptop);
+    assert_eq!(desktop.find_note("HEAD"), "");
+
+    fetch::execute(
+        fetch::Options::default().with_metadata(),
+        git_repo(&desktop),
+    )
+    .unwrap();
+
+    desktop.assert_note("HEAD", laptop.find_note("HEAD"));
+}
+
+#[test]
+fn push_metadata_merges_concurrent_updates() {
+    let remote_repo = RemoteRepo::new();
+    let laptop = remote_repo
+        .clone_repo()
+        .create_file("File1", "Hello, World!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Starting on a new feature")
+        .commit_all("feature 1")
+        .create_file("File2", "Another feature")
+        .commit_all("feature 2");
+    let desktop = remote_repo.clone_repo();
+    copy_commits(&desktop, &laptop);
+
+    create::execute(
+        create::Options::default().with_commit_ref("HEAD^"),
+        git_repo(&laptop),
+    )
+    .unwrap();
+    push::execute(push::Options::default().with_metadata(), git_repo(&laptop)).unwrap();
+    create::execute(create::Options::default(), git_repo(&desktop)).unwrap();
+    push::execute(push::Options::default().with_metadata(), git_repo(&desktop)).unwrap();
+    fetch::execute(fetch::Options::default().with_metadata(), git_repo(&laptop)).unwrap();
+
+    for local_repo in [&laptop, &desktop] {
+        assert!(local_repo
+            .find_note("HEAD^")
+            .starts_with("ubr-version: 2\nremote-branch: feature-1\n"));
+        assert!(local_repo
+            .find_note("HEAD")
+            .starts_with("ubr-version: 2\nremote-branch: feature-2\n"));
+    }
+}
+
+#[test]
+fn fetch_metadata_tracks_recreated_commits() {
+    let remote_repo = RemoteRepo::new();
+    let laptop = remote_repo
+        .clone_repo()
+        .create_file("File1", "Hello, World!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Starting on a new feature")
+        .commit_all("feature 1");
+    create::execute(create::Options::default(), git_repo(&laptop)).unwrap();
+    push::execute(push::Options::default().with_metadata(), git_repo(&laptop)).unwrap();
+
+    // A fresh clone where the commit is recreated from the remote branch
+    let desktop = remote_repo
+        .clone_repo()
+        .create_file("File2", "Unrelated")
+        .commit_all("unrelated");
+    run_git(&desktop, &["cherry-pick", "origin/feature-1"]);
+    assert_ne!(desktop.head(), laptop.head());
+
+    fetch::execute(
+        fetch::Options::default().with_metadata(),
+        git_repo(&desktop),
+    )
+    .unwrap();
+
+    desktop.assert_note(
+        "HEAD",
+        format!(
+            "ubr-version: 2\nremote-branch: feature-1\nremote-commit: {}\n",
+            desktop.rev_parse("origin/feature-1")
+        ),
+    );
+    assert_eq!(desktop.find_note("HEAD^"), "");
+}
@@ -0,0 +1,154 @@
+use test_repo::{RemoteRepo, TestRepoWithRemote};
+use ubr::{
+    commands::{create, sync},
+    git::GitRepo,
+};
+
+fn git_repo(value: &TestRepoWithRemote) -> GitRepo {
+    GitRepo::open(value.path()).unwrap()
+}
+
+fn is_ancestor(local_repo: &TestRepoWithRemote, ancestor: &str, descendant: &str) -> bool {
+    local_repo
+        .run_command()
+        .args(["merge-base", "--is-ancestor", ancestor, descendant])
+        .status()
+        .unwrap()
+        .success()
+}
+
+#[test]
+fn create_stacked_branch() {
+    let remote_repo = RemoteRepo::new();
+    let local_repo = remote_repo
+        .clone_repo()
+        .create_file("File1", "Hello, World!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Starting on a new feature")
+        .commit_all("feature 1")
+        .create_file("File2", "Feature 2 that builds on feature 1")
+        .commit_all("feature 2");
+
+    create::execute(
+        create::Options::default().with_commit_ref("HEAD^"),
+        git_repo(&local_repo),
+    )
+    .unwrap();
+    create::execute(
+        create::Options::default().with_stack(),
+        git_repo(&local_repo),
+    )
+    .unwrap();
+
+    assert!(local_repo
+        .find_note("HEAD")
+        .contains("parent-branch: feature-1\n"));
+    let local_repo = local_repo.fetch();
+    assert_eq!(
+        local_repo.rev_parse("origin/feature-2^"),
+        local_repo.rev_parse("origin/feature-1")
+    );
+    local_repo.assert_diff("origin/feature-1", "origin/feature-2", &{
+        String::from_utf8(local_repo.diff("HEAD^", "HEAD").stdout).unwrap()
+    });
+}
+
+#[test]
+fn create_stacks_when_commit_does_not_apply_on_base() {
+    let remote_repo = RemoteRepo::new();
+    let local_repo = remote_repo
+        .clone_repo()
+        .create_file("File1", "Hello, World!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Starting on a new feature")
+        .commit_all("feature 1")
+        .append_file("File1", "Continuing the same feature")
+        .commit_all("feature 2");
+
+    create::execute(
+        create::Options::default().with_commit_ref("HEAD^"),
+        git_repo(&local_repo),
+    )
+    .unwrap();
+    create::execute(create::Options::default(), git_repo(&local_repo)).unwrap();
+
+    assert!(local_repo
+        .find_note("HEAD")
+        .contains("parent-branch: feature-1\n"));
+    let local_repo = local_repo.fetch();
+    assert_eq!(
+        local_repo.rev_parse("origin/feature-2^"),
+        local_repo.rev_parse("origin/feature-1")
+    );
+}
+
+#[test]
+fn create_stack_without_tracked_ancestor() {
+    let remote_repo = RemoteRepo::new();
+    let local_repo = remote_repo
+        .clone_repo()
+        .create_file("File1", "Hello, World!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Starting on a new feature")
+        .commit_all("feature 1");
+
+    assert!(create::execute(
+        create::Options::default().with_stack(),
+        git_repo(&local_repo)
+    )
+    .is_err());
+}
+
+#[test]
+fn sync_updates_child_when_parent_changes() {
+    let remote_repo = RemoteRepo::new();
+    let local_repo = remote_repo
+        .clone_repo()
+        .create_file("File1", "Hello, World!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Starting on a new feature")
+        .commit_all("feature 1")
+        .create_file("File2", "Feature 2 that builds on feature 1")
+        .commit_all("feature 2");
+
+    create::execute(
+        create::Options::default().with_commit_ref("HEAD^"),
+        git_repo(&local_repo),
+    )
+    .unwrap();
+    create::execute(
+        create::Options::default().with_stack(),
+        git_repo(&local_repo),
+    )
+    .unwrap();
+
+    let feature_1 = local_repo.find_commit(1).id();
+    let local_repo = local_repo
+        .create_file("File3", "Review fixes for feature 1")
+        .commit_all_fixup(feature_1);
+
+    sync::execute(sync::Options::default(), git_repo(&local_repo)).unwrap();
+
+    let local_repo = local_repo.fetch();
+    local_repo.assert_tracked_commit_in_sync(
+        local_repo.find_commit(1).id(),
+        local_repo
+            .find_commit_by_reference("refs/remotes/origin/feature-1")
+            .id(),
+    );
+    assert!(is_ancestor(
+        &local_repo,
+        "origin/feature-1",
+        "origin/feature-2"
+    ));
+    local_repo.assert_diff("origin/feature-1", "origin/feature-2", &{
+        String::from_utf8(local_repo.diff("HEAD^", "HEAD").stdout).unwrap()
+    });
+    assert!(local_repo
+        .find_note("HEAD")
+        .contains("parent-branch: feature-1\n"));
+}
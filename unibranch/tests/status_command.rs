@@ -0,0 +1,130 @@
+use pretty_assertions::assert_eq;
+use test_repo::{RemoteRepo, TestRepoWithRemote};
+use ubr::{
+    commands::{
+        create,
+        status::{self, BranchStatus, CommitStatus},
+    },
+    git::{local_commit::RemoteState, GitRepo},
+};
+
+fn git_repo(value: &TestRepoWithRemote) -> GitRepo {
+    GitRepo::open(value.path()).unwrap()
+}
+
+#[test]
+fn status_of_tracked_and_untracked_commits() {
+    let remote_repo = RemoteRepo::new();
+    let local_repo = remote_repo
+        .clone_repo()
+        .create_file("File1", "Hello, World!")
+        .commit_all("commit1")
+        .push()
+        .create_file("File2", "Unrelated feature")
+        .commit_all("unrelated commit")
+        .append_file("File1", "Starting on a new feature")
+        .commit_all("feature 1");
+
+    create::execute(create::Options::default(), git_repo(&local_repo)).unwrap();
+
+    let statuses = status::collect(&git_repo(&local_repo)).unwrap();
+    assert_eq!(
+        statuses,
+        vec![
+            CommitStatus {
+                commit_id: local_repo.find_commit(1).id(),
+                summary: "unrelated commit".to_string(),
+                branch: None,
+            },
+            CommitStatus {
+                commit_id: local_repo.find_commit(0).id(),
+                summary: "feature 1".to_string(),
+                branch: Some(BranchStatus {
+                    remote_branch_name: "feature-1".to_string(),
+                    remote_state: RemoteState::UpToDate,
+                    local_changes: false,
+                    pull_request: None,
+                    remote_commit: local_repo.rev_parse("origin/feature-1").parse().unwrap(),
+                }),
+            },
+        ]
+    );
+}
+
+#[test]
+fn status_with_local_and_remote_changes() {
+    let remote_repo = RemoteRepo::new();
+    let local_repo = remote_repo
+        .clone_repo()
+        .create_file("File1", "Hello, World!")
+        .commit_all("commit1")
+        .push()
+        .create_file("File2", "Starting on a new feature")
+        .commit_all("feature 1");
+
+    create::execute(create::Options::default(), git_repo(&local_repo)).unwrap();
+
+    {
+        remote_repo
+            .clone_repo()
+            .checkout("feature-1")
+            .append_file("File2", "Some remote fixes")
+            .commit_all("Fixup")
+            .push();
+    }
+
+    let local_repo = local_repo
+        .create_file("File3", "Some local fixes")
+        .commit_all_amend()
+        .fetch();
+
+    let statuses = status::collect(&git_repo(&local_repo)).unwrap();
+    assert_eq!(
+        statuses,
+        vec![CommitStatus {
+            commit_id: local_repo.find_commit(0).id(),
+            summary: "feature 1".to_string(),
+            branch: Some(BranchStatus {
+                remote_branch_name: "feature-1".to_string(),
+                remote_state: RemoteState::Behind(1),
+                local_changes: true,
+                pull_request: None,
+                remote_commit: local_repo.rev_parse("origin/feature-1^").parse().unwrap(),
+            }),
+        }]
+    );
+}
+
+#[test]
+fn status_without_local_changes_after_base_moved() {
+    let remote_repo = RemoteRepo::new();
+    let local_repo = remote_repo
+        .clone_repo()
+        .create_file("File1", "Hello, World!")
+        .commit_all("commit1")
+        .push()
+        .create_file("File2", "Starting on a new feature")
+        .commit_all("feature 1");
+
+    create::execute(create::Options::default(), git_repo(&local_repo)).unwrap();
+
+    {
+        remote_repo
+            .clone_repo()
+            .create_file("File3", "Someone else's feature")
+            .commit_all("other feature")
+            .push();
+    }
+
+    let local_repo = local_repo.pull_rebase();
+
+    let statuses = status::collect(&git_repo(&local_repo)).unwrap();
+    assert_eq!(statuses.len(), 1);
+    assert_eq!(
+        statuses[0]
+            .branch
+            .as_ref()
+            .map(|branch| branch.local_changes),
+        Some(false)
+    );
+}
@@ -3,7 +3,8 @@ use pretty_assertions::assert_eq;
 use test_repo::{RemoteRepo, TestRepoWithRemote};
 use ubr::{
     commands::{create, sync},
-    git::{GitRepo, SyncState},
+    git::{GitRepo, SyncPhase, SyncState},
+    Error,
 };
 
 fn git_repo(value: &TestRepoWithRemote) -> GitRepo {
@@ -69,7 +70,8 @@ fn test_merge_conflict_from_remote() {
             remote_commit_id: remote_head.into(),
             main_commit_id: expected_main_commit_id.into(),
             main_commit_parent_id: expected_main_parent_id.into(),
-            main_branch_name: "master".to_string()
+            main_branch_name: "master".to_string(),
+            phase: SyncPhase::MergeRemoteHead,
         }
     );
 
@@ -93,7 +95,7 @@ fn test_merge_conflict_from_remote() {
     sync::execute(
         sync::Options {
             cont: true,
-            commit_ref: None,
+            ..Default::default()
         },
         git_repo(&local_repo),
     )
@@ -182,7 +184,8 @@ fn test_merge_conflict_in_the_middle_of_sync() {
             remote_commit_id: remote_head.into(),
             main_commit_id: expected_main_commit_id.into(),
             main_commit_parent_id: expected_main_parent_id.into(),
-            main_branch_name: "master".to_string()
+            main_branch_name: "master".to_string(),
+            phase: SyncPhase::MergeRemoteHead,
         }
     );
 
@@ -208,7 +211,7 @@ fn test_merge_conflict_in_the_middle_of_sync() {
     sync::execute(
         sync::Options {
             cont: true,
-            commit_ref: None,
+            ..Default::default()
         },
         git_repo(&local_repo),
     )
@@ -296,7 +299,8 @@ fn test_merge_conflict_in_the_middle_of_sync_2() {
             remote_commit_id: remote_head.into(),
             main_commit_id: expected_main_commit_id.into(),
             main_commit_parent_id: expected_main_parent_id.into(),
-            main_branch_name: "master".to_string()
+            main_branch_name: "master".to_string(),
+            phase: SyncPhase::MergeRemoteHead,
         }
     );
 
@@ -322,7 +326,7 @@ fn test_merge_conflict_in_the_middle_of_sync_2() {
     sync::execute(
         sync::Options {
             cont: true,
-            commit_ref: None,
+            ..Default::default()
         },
         git_repo(&local_repo),
     )
@@ -357,3 +361,171 @@ fn test_merge_conflict_in_the_middle_of_sync_2() {
         "},
     );
 }
+
+#[test]
+fn test_abort_merge_conflict() {
+    let remote_repo = RemoteRepo::new();
+    let local_repo = remote_repo
+        .clone_repo()
+        .create_file("File1", "Hello, World!")
+        .commit_all("commit1")
+        .push()
+        .create_file("File2", "Another feature")
+        .commit_all("feature 0")
+        .append_file("File1", "Starting on a new feature")
+        .commit_all("feature 1");
+
+    create::execute(
+        create::Options::default().with_commit_ref("HEAD^"),
+        git_repo(&local_repo),
+    )
+    .expect("Unable to create initial PR");
+    create::execute(
+        create::Options::default().with_commit_ref("HEAD"),
+        git_repo(&local_repo),
+    )
+    .expect("Unable to create initial PR");
+
+    {
+        remote_repo
+            .clone_repo()
+            .checkout("feature-0")
+            .append_file("File2", "Some remote fixes")
+            .commit_all("Fixup")
+            .push()
+            .checkout("feature-1")
+            .append_file("File1", "Some remote fixes")
+            .commit_all("Fixup")
+            .push();
+    }
+
+    let local_repo = local_repo
+        .append_file("File1", "Some local fixes")
+        .commit_all_amend()
+        .fetch();
+
+    let main_branch_head = local_repo.head();
+    let notes_head = local_repo.find_commit_by_reference("refs/notes/ubr").id();
+    let feature_0_note = local_repo.find_note("HEAD^");
+    let feature_1_note = local_repo.find_note("HEAD");
+    let operations = git_repo(&local_repo).op_log().unwrap().operations;
+
+    let result = sync::execute(sync::Options::default(), git_repo(&local_repo));
+    assert!(result.is_err());
+    assert_ne!(
+        local_repo.find_commit_by_reference("refs/notes/ubr").id(),
+        notes_head
+    );
+    assert_eq!(
+        git_repo(&local_repo).op_log().unwrap().operations.len(),
+        operations.len() + 1
+    );
+
+    sync::execute(
+        sync::Options {
+            abort: true,
+            ..Default::default()
+        },
+        git_repo(&local_repo),
+    )
+    .expect("Should be able to abort");
+
+    assert_eq!(local_repo.head_branch(), "master");
+    assert_eq!(local_repo.head(), main_branch_head);
+    assert_eq!(
+        local_repo.find_commit_by_reference("refs/notes/ubr").id(),
+        notes_head
+    );
+    local_repo.assert_note("HEAD^", feature_0_note);
+    local_repo.assert_note("HEAD", feature_1_note);
+    local_repo.assert_workdir_is_clean();
+    assert!(!local_repo.path().join(".ubr/SYNC_MERGE_HEAD").exists());
+    assert_eq!(
+        git_repo(&local_repo).op_log().unwrap().operations,
+        operations
+    );
+}
+
+#[test]
+fn test_conflict_when_updating_remote_branch() {
+    let remote_repo = RemoteRepo::new();
+    let local_repo = remote_repo
+        .clone_repo()
+        .create_file("File1", "Hello, World!")
+        .create_file("File2", "Some content")
+        .commit_all("commit1")
+        .push()
+        .append_file("File2", "Starting on a new feature")
+        .commit_all("feature 1");
+
+    create::execute(create::Options::default(), git_repo(&local_repo)).unwrap();
+    let feature_commit = local_repo.head();
+    let pushed_head = local_repo.rev_parse("origin/feature-1");
+
+    let other_clone = remote_repo.clone_repo();
+    assert!(other_clone
+        .run_command()
+        .args(["rm", "-q", "File2"])
+        .status()
+        .unwrap()
+        .success());
+    other_clone.commit_all("Remove File2").push();
+
+    let result = sync::execute(sync::Options::default(), git_repo(&local_repo));
+    assert!(matches!(
+        result.unwrap_err(),
+        Error::MergeConflict { files, .. } if files == vec!["File2".to_string()]
+    ));
+
+    let sync_state = serde_json::from_reader::<_, SyncState>(
+        std::fs::File::open(local_repo.path().join(".ubr/SYNC_MERGE_HEAD")).unwrap(),
+    )
+    .unwrap();
+    assert_eq!(
+        sync_state,
+        SyncState {
+            remote_commit_id: pushed_head.parse::<git2::Oid>().unwrap().into(),
+            main_commit_id: feature_commit.into(),
+            main_commit_parent_id: local_repo.find_commit(1).id().into(),
+            main_branch_name: "master".to_string(),
+            phase: SyncPhase::CherryPick,
+        }
+    );
+
+    //Keep the file
+    let local_repo = local_repo
+        .create_file("File2", "Some content\nResolved")
+        .add_all();
+
+    sync::execute(
+        sync::Options {
+            cont: true,
+            ..Default::default()
+        },
+        git_repo(&local_repo),
+    )
+    .expect("Should succeed");
+
+    assert!(!local_repo.path().join(".ubr/SYNC_MERGE_HEAD").exists());
+    assert_eq!(local_repo.head_branch(), "master");
+    let remote_head = local_repo.find_commit_by_reference("refs/remotes/origin/feature-1");
+    assert_eq!(remote_head.summary(), Some("Fixup!"));
+    assert_eq!(remote_head.parent_id(0).unwrap().to_string(), pushed_head);
+    local_repo.assert_note(
+        "HEAD",
+        format!(
+            "ubr-version: 2\nremote-branch: feature-1\nremote-commit: {}\n",
+            remote_head.id()
+        ),
+    );
+    let file2 = local_repo
+        .run_command()
+        .stdout(std::process::Stdio::piped())
+        .args(["show", "origin/feature-1:File2"])
+        .output()
+        .unwrap();
+    assert_eq!(
+        String::from_utf8_lossy(&file2.stdout),
+        "Some content\nResolved\n"
+    );
+}
@@ -0,0 +1,154 @@
+use pretty_assertions::assert_eq;
+use test_repo::{RemoteRepo, TestRepoWithRemote};
+use ubr::{
+    commands::{create, fetch, sync},
+    git::GitRepo,
+};
+
+fn git_repo(value: &TestRepoWithRemote) -> GitRepo {
+    GitRepo::open(value.path()).unwrap()
+}
+
+fn push_remote_fix(remote_repo: &RemoteRepo, branch: &str) -> String {
+    let other_clone = remote_repo
+        .clone_repo()
+        .checkout(branch)
+        .create_file("File2", "Some remote fixes")
+        .commit_all("Fixup")
+        .push();
+    other_clone.rev_parse("HEAD")
+}
+
+#[test]
+fn sync_fetches_remote_branches() {
+    let remote_repo = RemoteRepo::new();
+    let local_repo = remote_repo
+        .clone_repo()
+        .create_file("File1", "Hello, World!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Starting on a new feature")
+        .commit_all("feature 1");
+
+    create::execute(create::Options::default(), git_repo(&local_repo)).unwrap();
+    let remote_head = push_remote_fix(&remote_repo, "feature-1");
+
+    sync::execute(sync::Options::default(), git_repo(&local_repo)).unwrap();
+
+    assert_eq!(local_repo.rev_parse("origin/feature-1"), remote_head);
+    local_repo.assert_note(
+        "HEAD",
+        format!(
+            "ubr-version: 2\nremote-branch: feature-1\nremote-commit: {}\n",
+            remote_head
+        ),
+    );
+    assert!(local_repo.path().join("File2").exists());
+}
+
+#[test]
+fn sync_without_fetch() {
+    let remote_repo = RemoteRepo::new();
+    let local_repo = remote_repo
+        .clone_repo()
+        .create_file("File1", "Hello, World!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Starting on a new feature")
+        .commit_all("feature 1");
+
+    create::execute(create::Options::default(), git_repo(&local_repo)).unwrap();
+    let pushed_head = local_repo.rev_parse("origin/feature-1");
+    let note = local_repo.find_note("HEAD");
+    push_remote_fix(&remote_repo, "feature-1");
+
+    sync::execute(
+        sync::Options::default().with_no_fetch(),
+        git_repo(&local_repo),
+    )
+    .unwrap();
+
+    assert_eq!(local_repo.rev_parse("origin/feature-1"), pushed_head);
+    local_repo.assert_note("HEAD", note);
+    assert!(!local_repo.path().join("File2").exists());
+}
+
+#[test]
+fn sync_fetches_deleted_remote_branches() {
+    let remote_repo = RemoteRepo::new();
+    let local_repo = remote_repo
+        .clone_repo()
+        .create_file("File1", "Hello, World!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Starting on a new feature")
+        .commit_all("feature 1");
+
+    create::execute(create::Options::default(), git_repo(&local_repo)).unwrap();
+    assert!(remote_repo
+        .clone_repo()
+        .run_command()
+        .args(["push", "origin", "--delete", "feature-1"])
+        .status()
+        .unwrap()
+        .success());
+
+    sync::execute(sync::Options::default(), git_repo(&local_repo)).unwrap();
+
+    assert_eq!(local_repo.find_note("HEAD"), "");
+    local_repo.assert_log(vec!["feature 1\n", "commit1\n"]);
+}
+
+#[test]
+fn sync_without_fetch_keeps_branches_that_were_not_fetched() {
+    let remote_repo = RemoteRepo::new();
+    let local_repo = remote_repo
+        .clone_repo()
+        .create_file("File1", "Hello, World!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Starting on a new feature")
+        .commit_all("feature 1");
+
+    create::execute(create::Options::default(), git_repo(&local_repo)).unwrap();
+    //The remote branch still exists, it just isn't known locally
+    assert!(local_repo
+        .run_command()
+        .args(["update-ref", "-d", "refs/remotes/origin/feature-1"])
+        .status()
+        .unwrap()
+        .success());
+
+    let note = local_repo.find_note("HEAD");
+
+    //The branch can't be synced without its remote-tracking branch, but must stay tracked
+    let result = sync::execute(
+        sync::Options::default().with_no_fetch(),
+        git_repo(&local_repo),
+    );
+
+    assert!(result.is_err());
+    local_repo.assert_note("HEAD", note);
+}
+
+#[test]
+fn fetch_reports_only_branches_with_new_commits() {
+    let remote_repo = RemoteRepo::new();
+    let local_repo = remote_repo
+        .clone_repo()
+        .create_file("File1", "Hello, World!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Starting on a new feature")
+        .commit_all("feature 1");
+
+    create::execute(create::Options::default(), git_repo(&local_repo)).unwrap();
+    push_remote_fix(&remote_repo, "feature-1");
+
+    let updated = fetch::fetch_branches(&git_repo(&local_repo)).unwrap();
+    assert_eq!(updated, vec![("feature-1".to_string(), 1)]);
+
+    //The branch is still behind, but nothing new was fetched this time
+    let updated = fetch::fetch_branches(&git_repo(&local_repo)).unwrap();
+    assert_eq!(updated, vec![]);
+}
@@ -0,0 +1,202 @@
+use std::borrow::Cow;
+
+use pretty_assertions::assert_eq;
+use test_repo::{RemoteRepo, TestRepoWithRemote};
+use ubr::{
+    commands::{create, sync},
+    git::{
+        local_commit::{CommitMetadata, META_DATA_VERSION},
+        GitRepo, Journal,
+    },
+};
+
+fn git_repo(value: &TestRepoWithRemote) -> GitRepo {
+    GitRepo::open(value.path()).unwrap()
+}
+
+#[test]
+fn failed_sync_is_rolled_back() {
+    let remote_repo = RemoteRepo::new();
+    let local_repo = remote_repo
+        .clone_repo()
+        .create_file("File1", "Hello, World!")
+        .commit_all("commit1")
+        .push()
+        .create_file("File2", "Another feature")
+        .commit_all("feature 0")
+        .append_file("File1", "Starting on a new feature")
+        .commit_all("feature 1");
+
+    create::execute(
+        create::Options::default().with_commit_ref("HEAD^"),
+        git_repo(&local_repo),
+    )
+    .unwrap();
+    create::execute(create::Options::default(), git_repo(&local_repo)).unwrap();
+
+    let feature_0 = local_repo.find_commit(1).id();
+    let local_repo = local_repo
+        .append_file("File2", "Some local fixes")
+        .commit_all_fixup(feature_0)
+        .append_file("File1", "Some local fixes")
+        .commit_all_amend();
+    //The remote branch of the second commit has unrelated history, so the sync fails halfway through
+    let orphan = local_repo
+        .run_command()
+        .args(["commit-tree", "HEAD^{tree}", "-m", "orphan"])
+        .stdout(std::process::Stdio::piped())
+        .output()
+        .unwrap();
+    assert!(orphan.status.success());
+    assert!(local_repo
+        .run_command()
+        .args(["update-ref", "refs/remotes/origin/feature-1"])
+        .arg(String::from_utf8(orphan.stdout).unwrap().trim())
+        .status()
+        .unwrap()
+        .success());
+
+    let main_branch_head = local_repo.head();
+    let notes_head = local_repo.find_commit_by_reference("refs/notes/ubr").id();
+    let feature_0_note = local_repo.find_note("HEAD^");
+    let feature_1_note = local_repo.find_note("HEAD");
+
+    let result = sync::execute(
+        sync::Options::default().with_no_fetch(),
+        git_repo(&local_repo),
+    );
+    assert!(result.is_err());
+
+    assert_eq!(local_repo.head_branch(), "master");
+    assert_eq!(local_repo.head(), main_branch_head);
+    assert_eq!(
+        local_repo.find_commit_by_reference("refs/notes/ubr").id(),
+        notes_head
+    );
+    local_repo.assert_note("HEAD^", feature_0_note);
+    local_repo.assert_note("HEAD", feature_1_note);
+    assert!(!Journal::path(local_repo.path().join(".git")).exists());
+}
+
+#[test]
+fn interrupted_sync_is_rolled_back_when_opening_the_repo() {
+    let remote_repo = RemoteRepo::new();
+    let local_repo = remote_repo
+        .clone_repo()
+        .create_file("File1", "Hello, World!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Starting on a new feature")
+        .commit_all("feature 1");
+
+    create::execute(create::Options::default(), git_repo(&local_repo)).unwrap();
+    let original_note = local_repo.find_note("HEAD");
+
+    {
+        let repo = git_repo(&local_repo);
+        repo.begin_sync().unwrap();
+        repo.save_meta_data(
+            &repo.head().unwrap(),
+            &CommitMetadata {
+                version: META_DATA_VERSION,
+                remote_branch_name: Cow::Borrowed("feature-1"),
+                remote_commit: local_repo.find_commit(1).id(),
+                parent_branch: None,
+                pull_request: None,
+                extra: Vec::new(),
+            },
+        )
+        .unwrap();
+        //Dropped without finishing the sync, as if the process was killed
+    }
+    assert!(Journal::path(local_repo.path().join(".git")).exists());
+    assert_ne!(local_repo.find_note("HEAD"), original_note);
+
+    let _ = git_repo(&local_repo);
+
+    local_repo.assert_note("HEAD", original_note);
+    assert!(!Journal::path(local_repo.path().join(".git")).exists());
+}
+
+#[test]
+fn interrupted_sync_is_rolled_back_when_opening_the_repo_from_a_subdirectory() {
+    let remote_repo = RemoteRepo::new();
+    let local_repo = remote_repo.clone_repo();
+    std::fs::create_dir(local_repo.path().join("src")).unwrap();
+    let local_repo = local_repo
+        .create_file("src/File1", "Hello, World!")
+        .commit_all("commit1")
+        .push()
+        .append_file("src/File1", "Starting on a new feature")
+        .commit_all("feature 1");
+
+    create::execute(create::Options::default(), git_repo(&local_repo)).unwrap();
+    let original_note = local_repo.find_note("HEAD");
+
+    {
+        let repo = GitRepo::open(local_repo.path().join("src")).unwrap();
+        repo.begin_sync().unwrap();
+        repo.save_meta_data(
+            &repo.head().unwrap(),
+            &CommitMetadata {
+                version: META_DATA_VERSION,
+                remote_branch_name: Cow::Borrowed("feature-1"),
+                remote_commit: local_repo.find_commit(1).id(),
+                parent_branch: None,
+                pull_request: None,
+                extra: Vec::new(),
+            },
+        )
+        .unwrap();
+        //Dropped without finishing the sync, as if the process was killed
+    }
+    assert!(Journal::path(local_repo.path().join(".git")).exists());
+
+    let _ = git_repo(&local_repo);
+
+    local_repo.assert_note("HEAD", original_note);
+    assert!(!Journal::path(local_repo.path().join(".git")).exists());
+}
+
+#[test]
+fn running_sync_is_not_rolled_back_by_another_process() {
+    let remote_repo = RemoteRepo::new();
+    let local_repo = remote_repo
+        .clone_repo()
+        .create_file("File1", "Hello, World!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Starting on a new feature")
+        .commit_all("feature 1");
+
+    create::execute(create::Options::default(), git_repo(&local_repo)).unwrap();
+
+    let repo = git_repo(&local_repo);
+    repo.begin_sync().unwrap();
+    repo.save_meta_data(
+        &repo.head().unwrap(),
+        &CommitMetadata {
+            version: META_DATA_VERSION,
+            remote_branch_name: Cow::Borrowed("feature-1"),
+            remote_commit: local_repo.find_commit(1).id(),
+            parent_branch: None,
+            pull_request: None,
+            extra: Vec::new(),
+        },
+    )
+    .unwrap();
+    let note_during_sync = local_repo.find_note("HEAD");
+
+    //E.g. 'ubr status' in another terminal while the sync is running
+    let other_repo = git_repo(&local_repo);
+    local_repo.assert_note("HEAD", &note_during_sync);
+    assert!(Journal::path(local_repo.path().join(".git")).exists());
+    assert!(matches!(
+        other_repo.begin_sync(),
+        Err(ubr::Error::SyncInProgress)
+    ));
+
+    repo.finish_sync().unwrap();
+    assert!(!Journal::path(local_repo.path().join(".git")).exists());
+    local_repo.assert_note("HEAD", note_during_sync);
+}
@@ -0,0 +1,144 @@
+use pretty_assertions::assert_eq;
+use test_repo::{RemoteRepo, TestRepoWithRemote};
+use ubr::{
+    commands::{cleanup, create, op, sync},
+    git::GitRepo,
+};
+
+fn git_repo(value: &TestRepoWithRemote) -> GitRepo {
+    GitRepo::open(value.path()).unwrap()
+}
+
+#[test]
+fn undo_create() {
+    let remote_repo = RemoteRepo::new();
+    let local_repo = remote_repo
+        .clone_repo()
+        .create_file("File1", "Hello, World!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Starting on a new feature")
+        .commit_all("feature 1");
+
+    create::execute(create::Options::default(), git_repo(&local_repo)).unwrap();
+    assert!(!local_repo.find_note("HEAD").is_empty());
+
+    op::undo(
+        op::UndoOptions {
+            restore_remote: true,
+        },
+        git_repo(&local_repo),
+    )
+    .unwrap();
+
+    assert_eq!(local_repo.find_note("HEAD"), "");
+    assert!(local_repo.ls_remote_heads("feature-1").stdout.is_empty());
+}
+
+#[test]
+fn undo_sync() {
+    let remote_repo = RemoteRepo::new();
+    let local_repo = remote_repo
+        .clone_repo()
+        .create_file("File1", "Hello, World!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Starting on a new feature")
+        .commit_all("feature 1");
+
+    create::execute(create::Options::default(), git_repo(&local_repo)).unwrap();
+    let local_repo = local_repo
+        .append_file("File1", "Some local fixes")
+        .commit_all_amend();
+    let note_before_sync = local_repo.find_note("HEAD");
+    let remote_head_before_sync = local_repo.rev_parse("origin/feature-1");
+
+    sync::execute(sync::Options::default(), git_repo(&local_repo)).unwrap();
+    assert_ne!(local_repo.find_note("HEAD"), note_before_sync);
+    let note_after_sync = local_repo.find_note("HEAD");
+
+    op::undo(
+        op::UndoOptions {
+            restore_remote: true,
+        },
+        git_repo(&local_repo),
+    )
+    .unwrap();
+
+    local_repo.assert_note("HEAD", &note_before_sync);
+    local_repo.assert_workdir_is_clean();
+    let local_repo = local_repo.fetch();
+    assert_eq!(
+        local_repo.rev_parse("origin/feature-1"),
+        remote_head_before_sync
+    );
+
+    //Undoing the undo brings back the synced state
+    op::undo(op::UndoOptions::default(), git_repo(&local_repo)).unwrap();
+    local_repo.assert_note("HEAD", &note_after_sync);
+}
+
+#[test]
+fn restore_operation() {
+    let remote_repo = RemoteRepo::new();
+    let local_repo = remote_repo
+        .clone_repo()
+        .create_file("File1", "Hello, World!")
+        .commit_all("commit1")
+        .push()
+        .create_file("File2", "Another feature")
+        .commit_all("feature 0")
+        .append_file("File1", "Starting on a new feature")
+        .commit_all("feature 1");
+
+    create::execute(
+        create::Options::default().with_commit_ref("HEAD^"),
+        git_repo(&local_repo),
+    )
+    .unwrap();
+    create::execute(create::Options::default(), git_repo(&local_repo)).unwrap();
+
+    let op_log = git_repo(&local_repo).op_log().unwrap();
+    let first_create = op_log.operations[op_log.operations.len() - 2].id;
+
+    op::restore(
+        op::RestoreOptions {
+            id: first_create,
+            restore_remote: false,
+        },
+        git_repo(&local_repo),
+    )
+    .unwrap();
+
+    assert_eq!(local_repo.find_note("HEAD"), "");
+    assert_eq!(local_repo.find_note("HEAD^"), "");
+    assert!(!local_repo.ls_remote_heads("feature-1").stdout.is_empty());
+}
+
+#[test]
+fn failed_and_no_op_commands_are_not_logged() {
+    let remote_repo = RemoteRepo::new();
+    let local_repo = remote_repo
+        .clone_repo()
+        .create_file("File1", "Hello, World!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Starting on a new feature")
+        .commit_all("feature 1");
+
+    create::execute(create::Options::default(), git_repo(&local_repo)).unwrap();
+    let operations = git_repo(&local_repo).op_log().unwrap().operations;
+    assert_eq!(operations.len(), 1);
+
+    //Already tracked
+    assert!(create::execute(create::Options::default(), git_repo(&local_repo)).is_err());
+    //Nothing landed
+    cleanup::execute(cleanup::Options::default(), git_repo(&local_repo)).unwrap();
+    //Nothing to sync
+    sync::execute(sync::Options::default(), git_repo(&local_repo)).unwrap();
+
+    assert_eq!(
+        git_repo(&local_repo).op_log().unwrap().operations,
+        operations
+    );
+}
@@ -0,0 +1,123 @@
+use pretty_assertions::assert_eq;
+use test_repo::{RemoteRepo, TestRepoWithRemote};
+use ubr::{
+    commands::{create, untrack},
+    git::GitRepo,
+};
+
+fn git_repo(value: &TestRepoWithRemote) -> GitRepo {
+    GitRepo::open(value.path()).unwrap()
+}
+
+#[test]
+fn untrack_commit() {
+    let remote_repo = RemoteRepo::new();
+    let local_repo = remote_repo
+        .clone_repo()
+        .create_file("File1", "Hello, World!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Starting on a new feature")
+        .commit_all("feature 1");
+
+    create::execute(create::Options::default(), git_repo(&local_repo)).unwrap();
+    untrack::execute(untrack::Options::default(), git_repo(&local_repo)).unwrap();
+
+    assert_eq!(local_repo.find_note("HEAD"), "");
+    assert!(!local_repo.ls_remote_heads("feature-1").stdout.is_empty());
+}
+
+#[test]
+fn untrack_commit_and_delete_remote_branch() {
+    let remote_repo = RemoteRepo::new();
+    let local_repo = remote_repo
+        .clone_repo()
+        .create_file("File1", "Hello, World!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Starting on a new feature")
+        .commit_all("feature 1");
+
+    create::execute(create::Options::default(), git_repo(&local_repo)).unwrap();
+    untrack::execute(
+        untrack::Options::default().with_delete_remote(),
+        git_repo(&local_repo),
+    )
+    .unwrap();
+
+    assert_eq!(local_repo.find_note("HEAD"), "");
+    assert!(local_repo.ls_remote_heads("feature-1").stdout.is_empty());
+}
+
+#[test]
+fn untrack_range_of_commits() {
+    let remote_repo = RemoteRepo::new();
+    let local_repo = remote_repo
+        .clone_repo()
+        .create_file("File1", "Hello, World!")
+        .commit_all("commit1")
+        .push()
+        .create_file("File2", "Another feature")
+        .commit_all("feature 0")
+        .append_file("File1", "Starting on a new feature")
+        .commit_all("feature 1");
+
+    create::execute(
+        create::Options::default().with_commit_ref("HEAD^"),
+        git_repo(&local_repo),
+    )
+    .unwrap();
+    create::execute(create::Options::default(), git_repo(&local_repo)).unwrap();
+
+    untrack::execute(
+        untrack::Options::default().with_commit_ref("HEAD~2..HEAD"),
+        git_repo(&local_repo),
+    )
+    .unwrap();
+
+    assert_eq!(local_repo.find_note("HEAD"), "");
+    assert_eq!(local_repo.find_note("HEAD^"), "");
+}
+
+#[test]
+fn untrack_commit_in_virtual_branch_with_several_commits() {
+    let remote_repo = RemoteRepo::new();
+    let local_repo = remote_repo
+        .clone_repo()
+        .create_file("File1", "Hello, World!")
+        .commit_all("commit1")
+        .push()
+        .create_file("File2", "Another feature")
+        .commit_all("feature 1 part 1")
+        .append_file("File1", "Starting on a new feature")
+        .commit_all("feature 1 part 2");
+
+    create::execute(
+        create::Options::default().with_commit_ref("HEAD~2.."),
+        git_repo(&local_repo),
+    )
+    .unwrap();
+
+    untrack::execute(
+        untrack::Options::default().with_commit_ref("HEAD^"),
+        git_repo(&local_repo),
+    )
+    .unwrap();
+
+    assert_eq!(local_repo.find_note("HEAD"), "");
+    assert_eq!(local_repo.find_note("HEAD^"), "");
+}
+
+#[test]
+fn untrack_untracked_commit() {
+    let remote_repo = RemoteRepo::new();
+    let local_repo = remote_repo
+        .clone_repo()
+        .create_file("File1", "Hello, World!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Starting on a new feature")
+        .commit_all("feature 1");
+
+    assert!(untrack::execute(untrack::Options::default(), git_repo(&local_repo)).is_err());
+}
@@ -86,6 +86,7 @@ fn test_update_a_diff() {
     assert_eq!(
         repo.find_note("HEAD"),
         indoc! {"
+            ubr-version: 2
             remote-branch: commit2
             remote-commit: {}
         "}
@@ -206,6 +207,7 @@ fn test_update_a_commit_and_modify_the_commit_message() {
     assert_eq!(
         repo.find_note("HEAD"),
         indoc! {"
+            ubr-version: 2
             remote-branch: commit2
             remote-commit: {}
         "}
@@ -219,6 +221,7 @@ fn test_update_a_commit_and_modify_the_commit_message() {
     assert_eq!(
         repo.find_note("HEAD"),
         indoc! {"
+            ubr-version: 2
             remote-branch: commit2
             remote-commit: {}
         "}
@@ -231,6 +234,7 @@ fn test_update_a_commit_and_modify_the_commit_message() {
     assert_eq!(
         repo.find_note("HEAD"),
         indoc! {"
+            ubr-version: 2
             remote-branch: commit2
             remote-commit: {}
         "}